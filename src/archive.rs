@@ -0,0 +1,275 @@
+//! 桥数据的导出和导入
+//!
+//! 导出文件是一个json，包含桥用户(及其关联)、桥消息(及其关联的平台消息)，以及登录会话等需要迁移的文件：
+//! `session.token`、`device.json`、`telegram.session`、`tg.pack.*` 目录、`data` 下的其他json文件(死信等)和 `data/queue` 下的投递队列
+//!
+//! 导出文件带有 `version`，导入旧版本的导出文件时按 [`UPGRADES`] 依次升级
+
+use anyhow::{bail, Context};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+use crate::bridge::pojo::BridgeMessagePO;
+use crate::bridge::storage;
+use crate::bridge::user::BridgeUser;
+
+/// 当前导出文件的版本
+pub const SCHEMA_VERSION: u64 = 1;
+
+/// 导出文件的升级, 第 n 项将版本 n 升级为 n + 1; 修改导出格式时增加版本并在此追加升级
+const UPGRADES: &[fn(&mut Value) -> anyhow::Result<()>] = &[];
+
+/// 需要迁移的会话文件
+const SESSION_FILES: &[&str] = &["session.token", "device.json", "telegram.session"];
+/// 存储中的数据单独导出
+const STORAGE_FILES: &[&str] = &["bridge_user.json", "bridge_message.json"];
+const DATA_DIR: &str = "data";
+/// 投递队列, 见 [`crate::bridge::queue::MessageQueue::open`]
+const QUEUE_DIR: &str = "data/queue";
+const TG_PACK_PREFIX: &str = "tg.pack.";
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct Archive {
+    pub version: u64,
+    /// 导出时间(秒)
+    pub created_at: i64,
+    pub users: Vec<BridgeUser>,
+    pub messages: Vec<BridgeMessagePO>,
+    pub files: Vec<ArchiveFile>,
+}
+
+/// 导出的文件, 路径相对于桥的工作目录
+#[derive(Deserialize, Serialize, Debug)]
+pub struct ArchiveFile {
+    pub path: String,
+    /// base64 编码的文件内容
+    pub content: String,
+}
+
+/// # 导出桥数据
+/// 按配置的存储方式读取桥用户和桥消息
+pub fn export() -> anyhow::Result<Archive> {
+    let (users, messages) = storage::export()?;
+    Ok(Archive {
+        version: SCHEMA_VERSION,
+        created_at: chrono::Local::now().timestamp(),
+        users,
+        messages,
+        files: read_files(Path::new("."))?,
+    })
+}
+
+/// 读取需要导出的文件
+fn read_files(root: &Path) -> anyhow::Result<Vec<ArchiveFile>> {
+    let mut files = vec![];
+    for path in archive_paths(root)? {
+        let content = fs::read(root.join(&path)).with_context(|| format!("读取文件失败: {path}"))?;
+        files.push(ArchiveFile {
+            path,
+            content: BASE64.encode(content),
+        });
+    }
+    Ok(files)
+}
+
+/// 列出需要导出的文件, 路径相对于 `root`
+fn archive_paths(root: &Path) -> anyhow::Result<Vec<String>> {
+    let mut paths: Vec<String> = SESSION_FILES
+        .iter()
+        .filter(|path| root.join(path).is_file())
+        .map(|path| path.to_string())
+        .collect();
+    for entry in fs::read_dir(root)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name.starts_with(TG_PACK_PREFIX) && entry.file_type()?.is_dir() {
+            for file in fs::read_dir(entry.path())? {
+                let file = file?;
+                if file.file_type()?.is_file() {
+                    paths.push(format!("{name}/{}", file.file_name().to_string_lossy()));
+                }
+            }
+        }
+    }
+    for dir in [DATA_DIR, QUEUE_DIR] {
+        if !root.join(dir).is_dir() {
+            continue;
+        }
+        for entry in fs::read_dir(root.join(dir))? {
+            let entry = entry?;
+            let path = format!("{dir}/{}", entry.file_name().to_string_lossy());
+            if entry.file_type()?.is_file() && is_archive_path(&path) {
+                paths.push(path);
+            }
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+/// # 读取导出文件
+/// 旧版本的导出文件会升级到当前版本, 并检查文件路径和内容
+pub fn parse(content: &str) -> anyhow::Result<Archive> {
+    let mut value: Value = serde_json::from_str(content).context("导出文件格式错误")?;
+    let version = value.get("version").and_then(Value::as_u64).context("导出文件缺少版本")?;
+    if version == 0 || version > SCHEMA_VERSION {
+        bail!("不支持的导出文件版本: {version}, 当前版本: {SCHEMA_VERSION}");
+    }
+    for (index, upgrade) in UPGRADES.iter().enumerate().skip(version as usize - 1) {
+        upgrade(&mut value).with_context(|| format!("导出文件从版本 {} 升级失败", index + 1))?;
+        value["version"] = Value::from(index as u64 + 2);
+    }
+    let archive: Archive = serde_json::from_value(value).context("导出文件格式错误")?;
+    archive.validate()?;
+    Ok(archive)
+}
+
+impl Archive {
+    fn validate(&self) -> anyhow::Result<()> {
+        let mut ids = HashSet::new();
+        let mut origins = HashSet::new();
+        for user in &self.users {
+            if !ids.insert(&user.id) {
+                bail!("重复的桥用户: {}", user.id);
+            }
+            if !origins.insert((&user.platform, &user.origin_id)) {
+                bail!("重复的平台用户: {user}");
+            }
+        }
+        let mut ids = HashSet::new();
+        for message in &self.messages {
+            if !ids.insert(&message.id) {
+                bail!("重复的桥消息: {}", message.id);
+            }
+        }
+        let mut paths = HashSet::new();
+        for file in &self.files {
+            if !is_archive_path(&file.path) {
+                bail!("不允许导入的文件: {}", file.path);
+            }
+            if !paths.insert(&file.path) {
+                bail!("重复的文件: {}", file.path);
+            }
+            BASE64
+                .decode(&file.content)
+                .with_context(|| format!("文件内容错误: {}", file.path))?;
+        }
+        Ok(())
+    }
+}
+
+/// 只允许导入会导出的文件, 避免写到工作目录之外
+fn is_archive_path(path: &str) -> bool {
+    let valid_name = |name: &str| !name.is_empty() && name != "." && name != ".." && !name.contains(['/', '\\']);
+    if SESSION_FILES.contains(&path) {
+        return true;
+    }
+    let Some((dir, name)) = path.rsplit_once('/') else {
+        return false;
+    };
+    if !valid_name(name) {
+        return false;
+    }
+    match dir {
+        DATA_DIR => name.ends_with(".json") && !STORAGE_FILES.contains(&name),
+        QUEUE_DIR => name.ends_with(".json"),
+        _ => dir.starts_with(TG_PACK_PREFIX) && valid_name(dir),
+    }
+}
+
+/// # 导入桥数据
+/// 桥用户和桥消息按配置的存储方式导入, 已存在的会被覆盖; 文件直接覆盖
+pub fn restore(archive: &Archive) -> anyhow::Result<()> {
+    write_files(Path::new("."), &archive.files)?;
+    storage::import(&archive.users, &archive.messages)
+}
+
+/// 写入导入的文件, 路径相对于 `root`
+fn write_files(root: &Path, files: &[ArchiveFile]) -> anyhow::Result<()> {
+    for file in files {
+        let path = root.join(&file.path);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, BASE64.decode(&file.content)?).with_context(|| format!("写入文件失败: {}", file.path))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bridge::test_util::temp_path;
+
+    #[test]
+    fn test_parse() {
+        let archive = Archive {
+            version: SCHEMA_VERSION,
+            created_at: 0,
+            users: vec![],
+            messages: vec![],
+            files: vec![ArchiveFile {
+                path: "tg.pack.123/456".to_string(),
+                content: BASE64.encode(b"packed"),
+            }],
+        };
+        let content = serde_json::to_string(&archive).unwrap();
+        let archive = parse(&content).unwrap();
+        assert_eq!(BASE64.decode(&archive.files[0].content).unwrap(), b"packed");
+
+        let future = content.replace(&format!("\"version\":{SCHEMA_VERSION}"), "\"version\":999");
+        assert!(parse(&future).is_err());
+        let escape = content.replace("tg.pack.123/456", "tg.pack.123/../../etc/passwd");
+        assert!(parse(&escape).is_err());
+    }
+
+    #[test]
+    fn test_is_archive_path() {
+        assert!(is_archive_path("session.token"));
+        assert!(is_archive_path("data/dead_letter.json"));
+        assert!(is_archive_path("tg.pack.123/456"));
+        assert!(is_archive_path("data/queue/bridge_qq_client.json"));
+        assert!(!is_archive_path("data/queue/../../config.json"));
+        assert!(!is_archive_path("data/other/x.json"));
+        assert!(!is_archive_path("data/bridge_user.json"));
+        assert!(!is_archive_path("data/../config.json"));
+        assert!(!is_archive_path("/etc/passwd"));
+        assert!(!is_archive_path("config.json"));
+    }
+
+    #[test]
+    fn test_files_round_trip() {
+        let from = temp_path("archive");
+        let to = temp_path("archive");
+        fs::create_dir_all(from.join(QUEUE_DIR)).unwrap();
+        fs::write(from.join("data/dead_letter.json"), "[]").unwrap();
+        fs::write(from.join("data/bridge_user.json"), "[]").unwrap();
+        fs::write(from.join("data/queue/bridge_qq_client.json"), r#"[{"type":"Message"}]"#).unwrap();
+
+        let files = read_files(&from).unwrap();
+        let paths: Vec<&str> = files.iter().map(|file| file.path.as_str()).collect();
+        // 存储中的数据单独导出
+        assert_eq!(paths, vec!["data/dead_letter.json", "data/queue/bridge_qq_client.json"]);
+
+        let archive = Archive {
+            version: SCHEMA_VERSION,
+            created_at: 0,
+            users: vec![],
+            messages: vec![],
+            files,
+        };
+        let archive = parse(&serde_json::to_string(&archive).unwrap()).unwrap();
+        write_files(&to, &archive.files).unwrap();
+        assert_eq!(
+            fs::read_to_string(to.join("data/queue/bridge_qq_client.json")).unwrap(),
+            r#"[{"type":"Message"}]"#
+        );
+        let _ = fs::remove_dir_all(from);
+        let _ = fs::remove_dir_all(to);
+    }
+}
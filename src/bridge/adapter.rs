@@ -0,0 +1,554 @@
+//! 平台适配器
+//!
+//! 各平台的桥(QQ、DC、TG、指令)实现 [`BridgeAdapter`]，由桥统一调度：
+//! - 入站：平台消息转为桥消息，见 [`BridgeAdapter::apply_bridge_user`]、[`BridgeAdapter::to_bridge_reply`]
+//! - 出站：桥消息渲染为平台消息并发送，见 [`BridgeAdapter::send_message`]
+//! - 编辑：见 [`BridgeAdapter::send_edit_to_bridge`]、[`BridgeAdapter::edit_message`]
+//! - 撤回：见 [`BridgeAdapter::send_recall_to_bridge`]、[`BridgeAdapter::recall_message`]
+//! - 回应：见 [`BridgeAdapter::send_reaction_to_bridge`]、[`BridgeAdapter::react_message`]
+//! - 关联：记录平台消息id，见 [`BridgeAdapter::record_origin`]
+//! - 重试：发送失败的事件移入死信，不阻塞后续事件，按指数退避重试，成功后移出死信，见 [`sync_message`]
+//! - 生命周期：[`BridgeAdapter::start`]、[`BridgeAdapter::stop`]
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use tokio::task::JoinSet;
+use tokio::time::Instant;
+use tracing::{error, warn};
+
+use crate::bridge::filter::{self, FilterContext};
+use crate::bridge::manager::{DeadLetter, BRIDGE_MESSAGE_MANAGER, BRIDGE_USER_MANAGER, DEAD_LETTER_MANAGER};
+use crate::bridge::pojo::{BridgeMessagePO, BridgeMessageRefMessageForm, BridgeMessageRefPO, BridgeSendMessageForm, BridgeUserSaveForm};
+use crate::bridge::user::BridgeUser;
+use crate::bridge::{
+    BridgeClient, BridgeClientPlatform, BridgeEvent, BridgeMessage, BridgeReaction, MessageChain, MessageContent, OriginMessageId,
+};
+use crate::config::{BridgeConfig, FilterDirection};
+
+/// 平台适配器
+#[async_trait]
+pub trait BridgeAdapter: Send + Sync {
+    /// 适配器所属平台
+    fn platform(&self) -> BridgeClientPlatform;
+
+    /// 适配器使用的桥客户端
+    fn bridge(&self) -> &Arc<BridgeClient>;
+
+    /// # 启动适配器
+    /// 登录平台客户端并持续收发消息，返回即表示该平台的桥已关闭
+    async fn start(self: Arc<Self>);
+
+    /// 适配器退出后的清理
+    async fn stop(&self) {}
+
+    /// # 出站：将桥消息渲染为平台消息并发送
+    /// ### Return
+    /// - `Ok(Some(origin_id))` 平台消息id，将与桥消息关联
+    /// - `Ok(None)` 无需关联
+    /// - `Err(..)` 发送失败
+    async fn send_message(&self, message: &BridgeMessage) -> anyhow::Result<Option<OriginMessageId>>;
+
+    /// # 出站：同步消息编辑
+    /// 默认忽略编辑
+    /// ### Arguments
+    /// - `message` 编辑后的桥消息
+    /// - `origin_id` 桥消息在本平台的消息id
+    async fn edit_message(&self, _message: &BridgeMessage, _origin_id: &OriginMessageId) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// # 出站：撤回/删除本平台的消息
+    /// 默认忽略撤回
+    /// ### Arguments
+    /// - `message` 被撤回的桥消息
+    /// - `origin_id` 要撤回的本平台消息id，同一桥消息可能在本平台有多条关联消息，会逐条调用
+    async fn recall_message(&self, _message: &BridgeMessage, _origin_id: &OriginMessageId) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// # 出站：同步表情回应
+    /// 默认忽略回应
+    /// ### Arguments
+    /// - `message` 收到回应的桥消息
+    /// - `origin_id` 桥消息在本平台的消息id
+    /// - `reaction` 添加或取消的回应
+    /// - `added` true: 添加回应, false: 取消回应
+    /// - `reactions` 桥消息当前的全部回应
+    async fn react_message(
+        &self,
+        _message: &BridgeMessage,
+        _origin_id: &OriginMessageId,
+        _reaction: &BridgeReaction,
+        _added: bool,
+        _reactions: &[BridgeReaction],
+    ) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// # 入站：为平台用户申请桥用户
+    /// ### Arguments
+    /// - `origin_id` 平台用户id
+    /// - `display_text` 用户显示文本
+    async fn apply_bridge_user(&self, origin_id: &str, display_text: &str) -> BridgeUser {
+        let bridge_user = BRIDGE_USER_MANAGER
+            .lock()
+            .await
+            .likeAndSave(BridgeUserSaveForm {
+                origin_id: origin_id.to_string(),
+                platform: self.platform().to_string(),
+                display_text: display_text.to_string(),
+            })
+            .await;
+        bridge_user.unwrap()
+    }
+
+    /// # 入站：将平台的回复转为桥的回复
+    /// ### Argument
+    /// `origin_id` 被回复的平台消息id
+    async fn to_bridge_reply(&self, origin_id: &OriginMessageId) -> MessageContent {
+        let platform = self.platform();
+        match self.find_by_origin(origin_id).await {
+            Ok(Some(reply)) => MessageContent::Reply { id: Some(reply.id) },
+            Ok(None) => MessageContent::Err {
+                message: format!("回复一条{platform}消息, 但是同步回复消息失败"),
+            },
+            Err(message) => MessageContent::Err { message },
+        }
+    }
+
+    /// # 入站：查询平台消息关联的桥消息
+    /// ### Argument
+    /// `origin_id` 平台消息id，按 [`OriginMessageId::is_same`] 匹配
+    async fn find_by_origin(&self, origin_id: &OriginMessageId) -> Result<Option<BridgeMessagePO>, String> {
+        let platform = self.platform().to_string();
+        BRIDGE_MESSAGE_MANAGER
+            .lock()
+            .await
+            .find_by_ref(&platform, &origin_id.ref_prefix(), |ref_id| ref_id.is_same(origin_id))
+            .await
+    }
+
+    /// 入站：向其它桥发送消息
+    async fn send_to_bridge(&self, form: BridgeSendMessageForm) {
+        self.bridge().send_message(form).await
+    }
+
+    /// # 入站：平台消息被编辑，同步到其它桥
+    /// 编辑只更新文本，原消息的回复和图片保持不变；编辑后的消息被过滤或内容没有变化时不同步编辑。
+    /// 与撤回一样只同步源消息的编辑
+    /// ### Arguments
+    /// - `origin_id` 被编辑的平台消息id
+    /// - `bridge_config` 消息所在的桥
+    /// - `message_chain` 编辑后的文本内容
+    async fn send_edit_to_bridge(&self, origin_id: &OriginMessageId, bridge_config: &BridgeConfig, message_chain: MessageChain) {
+        let platform = self.platform();
+        let message = match self.find_by_origin(origin_id).await {
+            Ok(Some(message)) => message,
+            Ok(None) => return,
+            Err(err) => {
+                warn!("[{platform}] 同步编辑失败: {err}");
+                return;
+            }
+        };
+        let Some(source) = message.refs.first() else {
+            return;
+        };
+        if source.platform != platform.to_string() || !source.origin_id.is_same(origin_id) {
+            return;
+        }
+        let context = FilterContext {
+            direction: FilterDirection::Inbound,
+            from: filter::to_names(platform, bridge_config.endpoint(platform)),
+            to: vec![],
+            sender: filter::to_sender_ids(&message.sender_id).await,
+        };
+        let Some(message_chain) = filter::filter_message(&bridge_config.filters, &context, message_chain) else {
+            return;
+        };
+        let is_text = |chain: &MessageContent| !matches!(chain, MessageContent::Reply { .. } | MessageContent::Image(..));
+        let replies = message
+            .message_chain
+            .iter()
+            .filter(|chain| matches!(chain, MessageContent::Reply { .. }));
+        let images = message
+            .message_chain
+            .iter()
+            .filter(|chain| matches!(chain, MessageContent::Image(..)));
+        let message_chain: MessageChain = replies
+            .cloned()
+            .chain(message_chain.into_iter().filter(is_text))
+            .chain(images.cloned())
+            .collect();
+        if message_chain == message.message_chain {
+            return;
+        }
+        BRIDGE_MESSAGE_MANAGER
+            .lock()
+            .await
+            .update_message_chain(&message.id, message_chain.clone())
+            .await;
+        let message = BridgeMessage {
+            id: message.id,
+            sender_id: message.sender_id,
+            avatar_url: message.avatar_url,
+            bridge_config: bridge_config.clone(),
+            message_chain,
+        };
+        self.bridge().send_event(BridgeEvent::Edit(message)).await
+    }
+
+    /// # 入站：平台消息被撤回，同步撤回其它桥的消息
+    /// 只同步源消息的撤回；撤回其它桥转发过来的消息不会继续同步，避免各平台之间循环撤回
+    /// ### Arguments
+    /// - `origin_id` 被撤回的平台消息id
+    /// - `bridge_config` 消息所在的桥
+    async fn send_recall_to_bridge(&self, origin_id: &OriginMessageId, bridge_config: &BridgeConfig) {
+        let platform = self.platform();
+        let message = match self.find_by_origin(origin_id).await {
+            Ok(Some(message)) => message,
+            Ok(None) => return,
+            Err(err) => {
+                warn!("[{platform}] 同步撤回失败: {err}");
+                return;
+            }
+        };
+        // 第一条关联是消息的来源
+        let Some(source) = message.refs.first() else {
+            return;
+        };
+        if source.platform != platform.to_string() || !source.origin_id.is_same(origin_id) {
+            return;
+        }
+        let message = BridgeMessage {
+            id: message.id,
+            sender_id: message.sender_id,
+            avatar_url: message.avatar_url,
+            bridge_config: bridge_config.clone(),
+            message_chain: message.message_chain,
+        };
+        self.bridge().send_event(BridgeEvent::Recall(message)).await
+    }
+
+    /// # 入站：平台消息收到或取消表情回应，记录到桥消息并同步到其它桥
+    /// ### Arguments
+    /// - `origin_id` 收到回应的平台消息id
+    /// - `bridge_config` 消息所在的桥
+    /// - `emoji` 表情
+    /// - `sender_id` 回应的桥用户
+    /// - `added` true: 添加回应, false: 取消回应
+    async fn send_reaction_to_bridge(
+        &self,
+        origin_id: &OriginMessageId,
+        bridge_config: &BridgeConfig,
+        emoji: String,
+        sender_id: String,
+        added: bool,
+    ) {
+        let platform = self.platform();
+        let message = match self.find_by_origin(origin_id).await {
+            Ok(Some(message)) => message,
+            Ok(None) => return,
+            Err(err) => {
+                warn!("[{platform}] 同步回应失败: {err}");
+                return;
+            }
+        };
+        let reaction = BridgeReaction {
+            emoji,
+            sender_id,
+            platform: platform.to_string(),
+        };
+        BRIDGE_MESSAGE_MANAGER
+            .lock()
+            .await
+            .update_reaction(&message.id, reaction.clone(), added)
+            .await;
+        let message = BridgeMessage {
+            id: message.id,
+            sender_id: message.sender_id,
+            avatar_url: message.avatar_url,
+            bridge_config: bridge_config.clone(),
+            message_chain: message.message_chain,
+        };
+        self.bridge().send_event(BridgeEvent::Reaction { message, reaction, added }).await
+    }
+
+    /// # 出站：查询桥消息在本平台的消息id
+    /// ### Arguments
+    /// - `message` 桥消息
+    /// - `bridge_config` 投递的目标，只查询目标端点的消息，见 [`BridgeConfig::to_target`]
+    fn find_origin_id(&self, message: &BridgeMessagePO, bridge_config: &BridgeConfig) -> Option<OriginMessageId> {
+        let platform = self.platform();
+        let endpoint = bridge_config.endpoint(platform).map(|endpoint| &endpoint.name);
+        let refs = message
+            .refs
+            .iter()
+            .filter(|refs| refs.platform == platform.to_string())
+            // 旧数据没有记录端点
+            .find(|refs| refs.endpoint.is_none() || refs.endpoint.as_ref() == endpoint)?;
+        Some(refs.origin_id.clone())
+    }
+
+    /// 出站：查询桥用户在本平台关联的用户
+    async fn find_ref_user(&self, user: &BridgeUser) -> Option<BridgeUser> {
+        user.find_by_platform(&self.platform().to_string()).await
+    }
+
+    /// # 关联：将平台消息和桥消息进行关联, 为以后进行回复功能
+    /// ### Arguments
+    /// - `message` 已投递的桥消息，平台消息所在端点为消息投递的目标
+    /// - `origin_id` 平台消息id
+    async fn record_origin(&self, message: &BridgeMessage, origin_id: OriginMessageId) -> bool {
+        let endpoint = message.bridge_config.endpoint(self.platform());
+        BRIDGE_MESSAGE_MANAGER
+            .lock()
+            .await
+            .ref_bridge_message(BridgeMessageRefMessageForm {
+                bridge_message_id: message.id.clone(),
+                platform: self.platform().to_string(),
+                origin_id,
+                endpoint: endpoint.map(|endpoint| endpoint.name.clone()),
+            })
+            .await
+    }
+}
+
+/// 发送失败后的最大重试次数
+const MAX_RETRIES: u32 = 5;
+/// 首次重试前的等待时间，此后每次翻倍
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// 等待重试的死信
+struct Retry {
+    at: Instant,
+    letter: DeadLetter,
+}
+
+/// # 同步桥事件
+/// 依次取出适配器桥客户端投递队列中的桥事件交由适配器处理，完成后应答；
+/// 处理失败的事件移入死信后同样应答，等待重试时继续处理后续事件。上次退出时还在重试的死信继续重试
+pub async fn sync_message(adapter: &dyn BridgeAdapter) {
+    let queue = &adapter.bridge().queue;
+    let client = &adapter.bridge().name;
+    let mut retries: Vec<Retry> = DEAD_LETTER_MANAGER
+        .lock()
+        .await
+        .list()
+        .iter()
+        .filter(|letter| &letter.client == client && letter.attempts <= MAX_RETRIES)
+        .map(|letter| Retry {
+            at: Instant::now(),
+            letter: letter.clone(),
+        })
+        .collect();
+    loop {
+        let next = retries
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, retry)| retry.at)
+            .map(|(index, retry)| (index, retry.at));
+        let retry_at = async {
+            match next {
+                Some((_, at)) => tokio::time::sleep_until(at).await,
+                None => std::future::pending().await,
+            }
+        };
+        tokio::select! {
+            event = queue.peek() => {
+                if let Err(err) = dispatch(adapter, &event).await {
+                    retries.push(dead_letter(adapter, &event, err).await);
+                }
+                queue.ack(event.id()).await;
+            }
+            _ = retry_at => {
+                let (index, _) = next.unwrap();
+                let retry = retries.swap_remove(index);
+                retries.extend(redeliver(adapter, retry.letter).await);
+            }
+        }
+    }
+}
+
+/// 首次投递失败，移入死信等待重试
+async fn dead_letter(adapter: &dyn BridgeAdapter, event: &BridgeEvent, err: anyhow::Error) -> Retry {
+    let platform = adapter.platform();
+    warn!(?err, "[{platform}] 消息发送失败，{}秒后重试: {}", RETRY_DELAY.as_secs(), event.id());
+    let letter = DeadLetter {
+        id: DeadLetter::new_id(),
+        client: adapter.bridge().name.clone(),
+        platform: platform.to_string(),
+        error: format!("{err:#}"),
+        attempts: 1,
+        time: chrono::Local::now().timestamp(),
+        event: event.clone(),
+    };
+    DEAD_LETTER_MANAGER.lock().await.save(letter.clone()).await;
+    Retry {
+        at: Instant::now() + RETRY_DELAY,
+        letter,
+    }
+}
+
+/// # 重试投递死信
+/// 成功后移出死信；失败时按指数退避安排下次重试，超过 [`MAX_RETRIES`] 次后留在死信中不再重试
+async fn redeliver(adapter: &dyn BridgeAdapter, mut letter: DeadLetter) -> Option<Retry> {
+    let platform = adapter.platform();
+    let Err(err) = dispatch(adapter, &letter.event).await else {
+        DEAD_LETTER_MANAGER.lock().await.remove(&letter.id).await;
+        return None;
+    };
+    letter.attempts += 1;
+    letter.error = format!("{err:#}");
+    DEAD_LETTER_MANAGER.lock().await.update(&letter).await;
+    if letter.attempts > MAX_RETRIES {
+        error!(
+            ?err,
+            "[{platform}] 消息发送失败{}次，不再重试: {}",
+            letter.attempts,
+            letter.event.id()
+        );
+        return None;
+    }
+    let delay = RETRY_DELAY * 2u32.pow(letter.attempts - 1);
+    warn!(
+        ?err,
+        "[{platform}] 消息发送失败，{}秒后重试: {}",
+        delay.as_secs(),
+        letter.event.id()
+    );
+    Some(Retry {
+        at: Instant::now() + delay,
+        letter,
+    })
+}
+
+/// 将桥事件交由适配器处理
+async fn dispatch(adapter: &dyn BridgeAdapter, event: &BridgeEvent) -> anyhow::Result<()> {
+    match event {
+        BridgeEvent::Message(message) => {
+            if let Some(origin_id) = adapter.send_message(message).await? {
+                adapter.record_origin(message, origin_id).await;
+            }
+        }
+        BridgeEvent::Edit(message) => {
+            let Some(po) = BRIDGE_MESSAGE_MANAGER.lock().await.get(&message.id).await else {
+                return Ok(());
+            };
+            let Some(source) = po.refs.first() else {
+                return Ok(());
+            };
+            let mut context = FilterContext {
+                direction: FilterDirection::Outbound,
+                from: vec![source.platform.clone()],
+                to: vec![],
+                sender: filter::to_sender_ids(&message.sender_id).await,
+            };
+            context.from.extend(source.endpoint.clone());
+            // 同一端点只编辑第一条关联消息 (QQ补发的编辑消息也关联在桥消息上)
+            let mut edited = vec![];
+            for (refs, mut message) in ref_targets(adapter, message, po.refs.clone(), true) {
+                if edited.contains(&refs.endpoint) {
+                    continue;
+                }
+                edited.push(refs.endpoint.clone());
+                context.to = filter::to_names(adapter.platform(), message.bridge_config.endpoint(adapter.platform()));
+                let message_chain = std::mem::take(&mut message.message_chain);
+                let Some(message_chain) = filter::filter_message(&message.bridge_config.filters, &context, message_chain) else {
+                    continue;
+                };
+                message.message_chain = message_chain;
+                adapter.edit_message(&message, &refs.origin_id).await?;
+            }
+        }
+        BridgeEvent::Recall(message) => {
+            let Some(po) = BRIDGE_MESSAGE_MANAGER.lock().await.get(&message.id).await else {
+                return Ok(());
+            };
+            for (refs, message) in ref_targets(adapter, message, po.refs, true) {
+                adapter.recall_message(&message, &refs.origin_id).await?;
+                // 已撤回的关联移除, 重试时不再重复撤回
+                BRIDGE_MESSAGE_MANAGER
+                    .lock()
+                    .await
+                    .unref_bridge_message(BridgeMessageRefMessageForm {
+                        bridge_message_id: message.id.clone(),
+                        platform: refs.platform,
+                        origin_id: refs.origin_id,
+                        endpoint: refs.endpoint,
+                    })
+                    .await;
+            }
+        }
+        BridgeEvent::Reaction { message, reaction, added } => {
+            // 回应按平台汇总，同一平台的端点之间不同步回应
+            if reaction.platform == adapter.platform().to_string() {
+                return Ok(());
+            }
+            let Some(po) = BRIDGE_MESSAGE_MANAGER.lock().await.get(&message.id).await else {
+                return Ok(());
+            };
+            let mut reacted = vec![];
+            for (refs, message) in ref_targets(adapter, message, po.refs, false) {
+                if reacted.contains(&refs.endpoint) {
+                    continue;
+                }
+                adapter
+                    .react_message(&message, &refs.origin_id, reaction, *added, &po.reactions)
+                    .await?;
+                reacted.push(refs.endpoint);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// # 桥消息在本平台的关联消息，以及投递到关联消息所在端点的桥消息
+/// ### Arguments
+/// - `message` 桥事件中的消息，桥配置指向消息来源
+/// - `refs` 桥消息的关联消息
+/// - `skip_source` 是否跳过来源消息 (第一条关联)
+fn ref_targets(
+    adapter: &dyn BridgeAdapter,
+    message: &BridgeMessage,
+    refs: Vec<BridgeMessageRefPO>,
+    skip_source: bool,
+) -> Vec<(BridgeMessageRefPO, BridgeMessage)> {
+    let platform = adapter.platform().to_string();
+    refs.into_iter()
+        .skip(usize::from(skip_source))
+        .filter(|refs| refs.platform == platform)
+        .filter_map(|refs| {
+            let bridge_config = match &refs.endpoint {
+                Some(name) => {
+                    // 端点已从配置中移除
+                    let endpoint = message.bridge_config.endpoints.iter().find(|endpoint| &endpoint.name == name)?;
+                    message.bridge_config.to_target(endpoint)
+                }
+                None => message.bridge_config.clone(),
+            };
+            let message = BridgeMessage {
+                bridge_config,
+                ..message.clone()
+            };
+            Some((refs, message))
+        })
+        .collect()
+}
+
+/// # 启动已注册的适配器
+/// 任一适配器退出时返回
+pub async fn run_adapters(adapters: Vec<Arc<dyn BridgeAdapter>>) {
+    let mut tasks = JoinSet::new();
+    for adapter in adapters {
+        tasks.spawn(async move {
+            adapter.clone().start().await;
+            adapter.stop().await;
+            adapter.platform()
+        });
+    }
+    if let Some(Ok(platform)) = tasks.join_next().await {
+        warn!("[{platform}] 桥已退出");
+    }
+}
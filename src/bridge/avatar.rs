@@ -0,0 +1,93 @@
+//! 头像缓存
+//!
+//! 不能直接用url访问的头像(如TG的头像)下载后缓存在 `./data/avatar`，文件名为 `<平台>_<用户id>_<头像id>.jpg`，
+//! 桥消息的 `avatar_url` 记为 `avatar://<文件名>`。投递时按平台转换：
+//! - 上传图片的平台(QQ)读取本地文件，见 [`to_image`]
+//! - dc webhook 只接受url，需要配置 `avatarBaseUrl` 并自行提供 `./data/avatar` 的访问，见 [`to_public_url`]
+
+use std::path::{Path, PathBuf};
+
+use crate::bridge::{BridgeClientPlatform, Image};
+
+pub const AVATAR_DIR: &str = "./data/avatar";
+const SCHEME: &str = "avatar://";
+
+fn file_name(platform: BridgeClientPlatform, user_id: &str, photo_id: i64) -> String {
+    format!("{platform}_{user_id}_{photo_id}.jpg")
+}
+
+/// 查询已缓存的头像
+pub fn find(platform: BridgeClientPlatform, user_id: &str, photo_id: i64) -> Option<String> {
+    let name = file_name(platform, user_id, photo_id);
+    Path::new(AVATAR_DIR).join(&name).is_file().then(|| format!("{SCHEME}{name}"))
+}
+
+/// # 缓存头像
+/// 同时删除该用户以前的头像
+/// ### Return
+/// 头像的 `avatar_url`
+pub async fn save(platform: BridgeClientPlatform, user_id: &str, photo_id: i64, data: &[u8]) -> anyhow::Result<String> {
+    tokio::fs::create_dir_all(AVATAR_DIR).await?;
+    let name = file_name(platform, user_id, photo_id);
+    let prefix = format!("{platform}_{user_id}_");
+    let mut dir = tokio::fs::read_dir(AVATAR_DIR).await?;
+    while let Some(entry) = dir.next_entry().await? {
+        let old = entry.file_name().to_string_lossy().to_string();
+        if old.starts_with(&prefix) && old != name {
+            let _ = tokio::fs::remove_file(entry.path()).await;
+        }
+    }
+    tokio::fs::write(Path::new(AVATAR_DIR).join(&name), data).await?;
+    Ok(format!("{SCHEME}{name}"))
+}
+
+/// 缓存头像的本地路径, 不是缓存头像时为空
+fn local_path(avatar_url: &str) -> Option<PathBuf> {
+    let name = avatar_url.strip_prefix(SCHEME)?;
+    // 只允许缓存目录下的文件
+    if name.is_empty() || name.contains(['/', '\\']) || name.starts_with('.') {
+        return None;
+    }
+    Some(Path::new(AVATAR_DIR).join(name))
+}
+
+/// 头像作为图片, 缓存头像读取本地文件
+pub fn to_image(avatar_url: &str) -> Image {
+    match local_path(avatar_url) {
+        Some(path) => Image::Path(path.to_string_lossy().to_string()),
+        None => Image::Url(avatar_url.to_string()),
+    }
+}
+
+/// # 头像的公开url
+/// 缓存头像拼接为 `<avatarBaseUrl>/<文件名>`, 没有配置 `avatarBaseUrl` 时为空
+pub fn to_public_url(avatar_url: &str, base_url: Option<&str>) -> Option<String> {
+    let Some(name) = avatar_url.strip_prefix(SCHEME) else {
+        return Some(avatar_url.to_string());
+    };
+    local_path(avatar_url)?;
+    Some(format!("{}/{}", base_url?.trim_end_matches('/'), name))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_to_public_url() {
+        let url = format!("{SCHEME}{}", file_name(BridgeClientPlatform::Telegram, "1", 2));
+        assert_eq!(url, "avatar://TG_1_2.jpg");
+        assert_eq!(
+            to_public_url(&url, Some("https://example.com/avatar/")).as_deref(),
+            Some("https://example.com/avatar/TG_1_2.jpg")
+        );
+        assert_eq!(to_public_url(&url, None), None);
+        assert_eq!(
+            to_public_url("https://q1.qlogo.cn/a", None).as_deref(),
+            Some("https://q1.qlogo.cn/a")
+        );
+        assert_eq!(to_public_url("avatar://../config.json", Some("https://example.com")), None);
+        assert!(matches!(to_image(&url), Image::Path(path) if path.ends_with("TG_1_2.jpg")));
+        assert!(matches!(to_image("avatar://../x"), Image::Url(..)));
+    }
+}
@@ -15,8 +15,64 @@ pub struct BridgeMessage {
 
 pub type MessageChain = Vec<MessageContent>;
 
+/// 桥事件，经投递队列同步到其它桥
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type")]
+pub enum BridgeEvent {
+    /// 新消息
+    Message(BridgeMessage),
+    /// 消息被编辑，桥消息id不变，消息链为编辑后的内容
+    Edit(BridgeMessage),
+    /// 消息被撤回或删除
+    Recall(BridgeMessage),
+    /// 消息收到或取消表情回应
+    Reaction {
+        message: BridgeMessage,
+        reaction: BridgeReaction,
+        /// true: 添加回应, false: 取消回应
+        added: bool,
+    },
+}
+
+impl BridgeEvent {
+    /// 事件关联的桥消息id
+    pub fn id(&self) -> &str {
+        match self {
+            BridgeEvent::Message(message) | BridgeEvent::Edit(message) | BridgeEvent::Recall(message) => &message.id,
+            BridgeEvent::Reaction { message, .. } => &message.id,
+        }
+    }
+}
+
+/// 表情回应
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct BridgeReaction {
+    /// 表情: unicode emoji, DC自定义表情为 `<:name:id>` 格式
+    pub emoji: String,
+    /// 回应的桥用户
+    pub sender_id: String,
+    /// 回应来自的平台
+    pub platform: String,
+}
+
+impl BridgeReaction {
+    /// 是否 unicode emoji (自定义表情只能在原平台使用)
+    pub fn is_unicode(&self) -> bool {
+        !self.emoji.starts_with('<')
+    }
+
+    /// 显示文本, 自定义表情显示为 `:name:`
+    pub fn display(&self) -> String {
+        if self.is_unicode() {
+            return self.emoji.clone();
+        }
+        // <:name:id> 或 <a:name:id>
+        format!(":{}:", self.emoji.split(':').nth(1).unwrap_or_default())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type")]
 pub enum MessageContent {
     /**
      * 回复
@@ -60,7 +116,7 @@ pub enum MessageContent {
     Othen,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum Image {
     Url(String),
     Path(String),
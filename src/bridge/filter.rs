@@ -0,0 +1,191 @@
+//! 消息过滤
+//!
+//! 按桥配置的过滤规则 ([`FilterRule`]) 过滤消息：
+//! - inbound：消息进入桥时过滤，见 [`crate::bridge::BridgeClient::send_message`]
+//! - outbound：消息投递到各端点前过滤
+
+use regex::Regex;
+
+use crate::bridge::manager::BRIDGE_USER_MANAGER;
+use crate::bridge::{MessageChain, MessageContent};
+use crate::config::{BridgeEndpoint, FilterAction, FilterDirection, FilterRule};
+
+/// 过滤时消息的来源、去向和发送者
+pub struct FilterContext {
+    pub direction: FilterDirection,
+    /// 消息来源的平台和端点名
+    pub from: Vec<String>,
+    /// 投递目标的平台和端点名, inbound 时为空
+    pub to: Vec<String>,
+    /// 发送者的桥用户id和平台用户id
+    pub sender: Vec<String>,
+}
+
+/// 平台和端点名, 用于匹配规则的 `from`、`to`
+pub fn to_names(platform: impl ToString, endpoint: Option<&BridgeEndpoint>) -> Vec<String> {
+    let mut names = vec![platform.to_string()];
+    names.extend(endpoint.map(|endpoint| endpoint.name.clone()));
+    names
+}
+
+/// 桥用户id和平台用户id, 用于匹配规则的发送者名单
+pub async fn to_sender_ids(sender_id: &str) -> Vec<String> {
+    let mut ids = vec![sender_id.to_string()];
+    let user = BRIDGE_USER_MANAGER.lock().await.get(sender_id).await;
+    ids.extend(user.map(|user| user.origin_id));
+    ids
+}
+
+/// # 按规则过滤消息
+/// ### Return
+/// 过滤后的消息链，消息被丢弃或过滤后没有内容时返回 `None`
+pub fn filter_message(rules: &[FilterRule], context: &FilterContext, mut message_chain: MessageChain) -> Option<MessageChain> {
+    let mut modified = false;
+    for rule in rules.iter().filter(|rule| rule.direction == context.direction) {
+        let regex = rule.text.as_deref();
+        if !is_match(rule, regex, context, &message_chain) {
+            continue;
+        }
+        match &rule.action {
+            FilterAction::Drop => return None,
+            FilterAction::Strip => replace_text(&mut message_chain, regex, ""),
+            FilterAction::Replace { with } => replace_text(&mut message_chain, regex, with),
+        }
+        modified = true;
+    }
+    if modified && is_empty(&message_chain) {
+        return None;
+    }
+    Some(message_chain)
+}
+
+fn is_match(rule: &FilterRule, regex: Option<&Regex>, context: &FilterContext, message_chain: &MessageChain) -> bool {
+    let contains = |list: &Vec<String>, names: &Vec<String>| list.iter().any(|name| names.contains(name));
+    if !rule.from.is_empty() && !contains(&rule.from, &context.from) {
+        return false;
+    }
+    if !rule.to.is_empty() && !contains(&rule.to, &context.to) {
+        return false;
+    }
+    if !rule.allowSenders.is_empty() && contains(&rule.allowSenders, &context.sender) {
+        return false;
+    }
+    if !rule.denySenders.is_empty() && !contains(&rule.denySenders, &context.sender) {
+        return false;
+    }
+    if rule.imageOnly && !is_image_only(message_chain) {
+        return false;
+    }
+    if let Some(regex) = regex {
+        let text: String = message_chain
+            .iter()
+            .filter_map(|chain| match chain {
+                MessageContent::Plain { text } => Some(text.as_str()),
+                _ => None,
+            })
+            .collect();
+        return regex.is_match(&text);
+    }
+    true
+}
+
+/// 替换文本, 没有正则时替换全部文本
+fn replace_text(message_chain: &mut MessageChain, regex: Option<&Regex>, with: &str) {
+    let Some(regex) = regex else {
+        let is_text = |chain: &MessageContent| matches!(chain, MessageContent::Plain { .. });
+        let first = message_chain.iter().position(is_text);
+        message_chain.retain(|chain| !is_text(chain));
+        if let (Some(index), false) = (first, with.is_empty()) {
+            message_chain.insert(index, MessageContent::Plain { text: with.to_string() });
+        }
+        return;
+    };
+    for chain in message_chain.iter_mut() {
+        if let MessageContent::Plain { text } = chain {
+            *text = regex.replace_all(text, with).to_string();
+        }
+    }
+}
+
+/// 是否空白文本或回复
+fn is_blank(chain: &MessageContent) -> bool {
+    match chain {
+        MessageContent::Plain { text } => text.trim().is_empty(),
+        MessageContent::Reply { .. } => true,
+        _ => false,
+    }
+}
+
+fn is_empty(message_chain: &MessageChain) -> bool {
+    message_chain.iter().all(is_blank)
+}
+
+fn is_image_only(message_chain: &MessageChain) -> bool {
+    let mut images = message_chain.iter().filter(|chain| !is_blank(chain)).peekable();
+    images.peek().is_some() && images.all(|chain| matches!(chain, MessageContent::Image(..)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bridge::Image;
+
+    fn rules(json: &str) -> Vec<FilterRule> {
+        serde_json::from_str(json).unwrap()
+    }
+
+    fn context(direction: FilterDirection, from: &str, to: &str, sender: &str) -> FilterContext {
+        FilterContext {
+            direction,
+            from: vec![from.to_string()],
+            to: vec![to.to_string()],
+            sender: vec![sender.to_string()],
+        }
+    }
+
+    fn plain(text: &str) -> MessageContent {
+        MessageContent::Plain { text: text.to_string() }
+    }
+
+    #[test]
+    fn test_filter_text() {
+        let rules = rules(
+            r#"[
+                {"from": ["DC"], "text": "Issue labeled by .*", "action": {"type": "drop"}},
+                {"text": "[0-9]{11}", "action": {"type": "replace", "with": "***"}},
+                {"direction": "outbound", "to": ["QQ"], "text": "!\\S+", "action": {"type": "strip"}}
+            ]"#,
+        );
+        let inbound = context(FilterDirection::Inbound, "DC", "", "1");
+        assert!(filter_message(&rules, &inbound, vec![plain("Issue labeled by bot")]).is_none());
+        let message_chain = filter_message(&rules, &inbound, vec![plain("call 13800000000 !ping")]).unwrap();
+        assert!(matches!(&message_chain[0], MessageContent::Plain { text } if text == "call *** !ping"));
+        // 其它平台不匹配 from
+        let inbound = context(FilterDirection::Inbound, "QQ", "", "1");
+        assert!(filter_message(&rules, &inbound, vec![plain("Issue labeled by bot")]).is_some());
+
+        let outbound = context(FilterDirection::Outbound, "DC", "QQ", "1");
+        let message_chain = filter_message(&rules, &outbound, vec![plain("hi !ping")]).unwrap();
+        assert!(matches!(&message_chain[0], MessageContent::Plain { text } if text == "hi "));
+        // 删除后没有内容的消息不再同步
+        assert!(filter_message(&rules, &outbound, vec![plain("!ping")]).is_none());
+    }
+
+    #[test]
+    fn test_filter_sender_and_image() {
+        let rules = rules(
+            r#"[
+                {"allowSenders": ["1", "2"], "imageOnly": true, "action": {"type": "drop"}},
+                {"denySenders": ["3"], "action": {"type": "replace", "with": "[已屏蔽]"}}
+            ]"#,
+        );
+        let image = || MessageContent::Image(Image::Url("https://example.com/a.png".to_string()));
+        let inbound = |sender| context(FilterDirection::Inbound, "QQ", "", sender);
+        assert!(filter_message(&rules, &inbound("4"), vec![plain(" "), image()]).is_none());
+        assert!(filter_message(&rules, &inbound("1"), vec![image()]).is_some());
+        assert!(filter_message(&rules, &inbound("4"), vec![plain("看"), image()]).is_some());
+        let message_chain = filter_message(&rules, &inbound("3"), vec![plain("a"), image(), plain("b")]).unwrap();
+        assert_eq!(message_chain.len(), 2);
+        assert!(matches!(&message_chain[0], MessageContent::Plain { text } if text == "[已屏蔽]"));
+    }
+}
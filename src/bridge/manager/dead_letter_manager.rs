@@ -0,0 +1,167 @@
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use tokio::sync::Mutex;
+use tracing::error;
+
+use crate::bridge::storage::json_file;
+use crate::bridge::BridgeEvent;
+
+/// 投递失败的桥事件, 重试期间也保存在死信中, 重试成功后移除
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeadLetter {
+    #[serde(default = "DeadLetter::new_id")]
+    pub id: String,
+    /// 投递目标的桥客户端名
+    pub client: String,
+    /// 投递目标的平台
+    pub platform: String,
+    /// 最后一次失败的原因
+    pub error: String,
+    /// 已尝试的次数
+    pub attempts: u32,
+    /// 移入死信的时间 (秒)
+    pub time: i64,
+    pub event: BridgeEvent,
+}
+
+impl DeadLetter {
+    pub fn new_id() -> String {
+        uuid::Uuid::new_v4().to_string()
+    }
+}
+
+pub struct DeadLetterManager {
+    path: PathBuf,
+    letters: Vec<DeadLetter>,
+}
+
+impl DeadLetterManager {
+    pub fn new() -> DeadLetterManager {
+        Self::open_at(PathBuf::from("./data/dead_letter.json"))
+    }
+
+    pub fn open_at(path: PathBuf) -> DeadLetterManager {
+        DeadLetterManager {
+            letters: json_file::load(&path).unwrap_or_default(),
+            path,
+        }
+    }
+
+    /// 查询全部死信
+    pub fn list(&self) -> &[DeadLetter] {
+        &self.letters
+    }
+
+    /// 保存一条死信
+    pub async fn save(&mut self, letter: DeadLetter) {
+        self.letters.push(letter);
+        self.serialize().await;
+    }
+
+    /// 更新重试后的死信
+    pub async fn update(&mut self, letter: &DeadLetter) {
+        let Some(saved) = self.letters.iter_mut().find(|saved| saved.id == letter.id) else {
+            return;
+        };
+        *saved = letter.clone();
+        self.serialize().await;
+    }
+
+    /// 移除重试成功的死信
+    pub async fn remove(&mut self, id: &str) {
+        let len = self.letters.len();
+        self.letters.retain(|letter| letter.id != id);
+        if self.letters.len() != len {
+            self.serialize().await;
+        }
+    }
+
+    /// # 查询要重新投递的死信
+    /// 放回投递队列后再用 [`DeadLetterManager::remove`] 逐条移除
+    /// ### Arguments
+    /// - `client` 只查询投递到该桥客户端的死信
+    /// - `message_id` 只查询关联该桥消息的死信
+    pub fn filter(&self, client: Option<&str>, message_id: Option<&str>) -> Vec<DeadLetter> {
+        self.letters
+            .iter()
+            .filter(|letter| client.map_or(true, |client| letter.client == client))
+            .filter(|letter| message_id.map_or(true, |id| letter.event.id() == id))
+            .cloned()
+            .collect()
+    }
+
+    async fn serialize(&self) {
+        if let Err(err) = json_file::save_blocking(self.path.clone(), self.letters.clone()).await {
+            error!(?err, "死信保存失败: {}", self.path.display());
+        }
+    }
+}
+
+lazy_static! {
+    pub static ref DEAD_LETTER_MANAGER: Mutex<DeadLetterManager> = Mutex::new(DeadLetterManager::new());
+}
+
+#[cfg(test)]
+mod test {
+    use super::{DeadLetter, DeadLetterManager};
+    use crate::bridge::test_util::{message, temp_json};
+
+    fn letter(client: &str, id: &str) -> DeadLetter {
+        DeadLetter {
+            id: DeadLetter::new_id(),
+            client: client.to_string(),
+            platform: "QQ".to_string(),
+            error: "timeout".to_string(),
+            attempts: 6,
+            time: 0,
+            event: message(id),
+        }
+    }
+
+    #[test]
+    fn test_filter() {
+        let path = temp_json("dead_letter");
+        tokio_test::block_on(async {
+            let mut manager = DeadLetterManager::open_at(path.clone());
+            manager.save(letter("bridge_qq_client", "1")).await;
+            manager.save(letter("bridge_dc_client", "1")).await;
+            manager.save(letter("bridge_qq_client", "2")).await;
+
+            let found = manager.filter(Some("bridge_qq_client"), Some("1"));
+            assert_eq!(found.len(), 1);
+            assert_eq!(manager.filter(None, Some("1")).len(), 2);
+            // 查询不会移除死信
+            assert_eq!(manager.list().len(), 3);
+
+            manager.remove(&found[0].id).await;
+        });
+        // 重新打开仍能读到剩余的死信
+        let manager = DeadLetterManager::open_at(path.clone());
+        assert_eq!(manager.filter(None, Some("1"))[0].client, "bridge_dc_client");
+        assert_eq!(manager.filter(None, None).len(), 2);
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn test_retry() {
+        let path = temp_json("dead_letter");
+        let mut retry = letter("bridge_qq_client", "1");
+        tokio_test::block_on(async {
+            let mut manager = DeadLetterManager::open_at(path.clone());
+            manager.save(retry.clone()).await;
+            manager.save(letter("bridge_qq_client", "1")).await;
+
+            retry.attempts += 1;
+            manager.update(&retry).await;
+            assert_eq!(manager.list()[0].attempts, 7);
+
+            // 同一桥消息的其它死信不受影响
+            manager.remove(&retry.id).await;
+        });
+        let manager = DeadLetterManager::open_at(path.clone());
+        assert_eq!(manager.list().len(), 1);
+        assert_ne!(manager.list()[0].id, retry.id);
+        let _ = std::fs::remove_file(path);
+    }
+}
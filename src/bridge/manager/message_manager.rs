@@ -1,35 +1,40 @@
 use lazy_static::lazy_static;
-use std::fs;
+use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::Mutex;
+use tracing::{error, info};
 
 use crate::bridge;
+use crate::bridge::storage::{self, MessageStorage, RetentionPolicy};
+use crate::bridge::{BridgeReaction, OriginMessageId};
+use crate::config::SharedConfig;
 use bridge::pojo::{BridgeMessagePO, BridgeMessageRefMessageForm};
+
+/// 清理桥消息的间隔
+const RETENTION_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
 pub struct BridgeMessageManager {
-    messages: Vec<bridge::pojo::BridgeMessagePO>,
+    storage: Box<dyn MessageStorage>,
 }
 
 impl BridgeMessageManager {
     pub fn new() -> BridgeMessageManager {
-        let path = "./data/bridge_message.json";
-        if let Ok(true) = fs::try_exists(path) {
-            let file = fs::read_to_string(path).unwrap();
-            return BridgeMessageManager {
-                messages: serde_json::from_str(file.as_str()).unwrap(),
-            };
+        BridgeMessageManager {
+            storage: storage::open_message_storage(),
         }
-        BridgeMessageManager { messages: vec![] }
     }
 
     /**
      * 查询指定消息
      */
     pub async fn get(&self, id: &str) -> Option<BridgeMessagePO> {
-        for message in &self.messages {
-            if id.eq(&message.id) {
-                return Some(message.clone());
+        match self.storage.get(id) {
+            Ok(message) => message,
+            Err(err) => {
+                error!("查询桥消息失败: {err:?}");
+                None
             }
         }
-        None
     }
     /**
      * 保存消息
@@ -42,10 +47,14 @@ impl BridgeMessageManager {
             sender_id: form.sender_id,
             avatar_url: form.avatar_url,
             message_chain: form.message_chain,
+            reactions: vec![],
+            bridge: form.bridge_config.name,
+            created_at: chrono::Local::now().timestamp(),
         };
         bridge_message.refs.push(form.origin_message);
-        self.messages.push(bridge_message);
-        self.serialize();
+        if let Err(err) = self.storage.save(&bridge_message) {
+            error!("保存桥消息失败: {err:?}");
+        }
         id
     }
 
@@ -53,37 +62,46 @@ impl BridgeMessageManager {
      * 关联消息桥消息
      */
     pub async fn ref_bridge_message(&mut self, form: BridgeMessageRefMessageForm) -> bool {
-        let message = self
-            .messages
-            .iter_mut()
-            .find(|message| form.bridge_message_id.eq(&message.id));
-        match message {
-            Some(message) => {
-                message.refs.push(bridge::pojo::BridgeMessageRefPO {
-                    origin_id: form.origin_id,
-                    platform: form.platform,
-                });
-                self.serialize();
-                true
+        self.update(&form.bridge_message_id, |message| {
+            message.refs.push(bridge::pojo::BridgeMessageRefPO {
+                origin_id: form.origin_id,
+                platform: form.platform,
+                endpoint: form.endpoint,
+            });
+        })
+        .is_some()
+    }
+
+    /**
+     * 更新消息内容 (消息被编辑)
+     */
+    pub async fn update_message_chain(&mut self, id: &str, message_chain: bridge::MessageChain) -> bool {
+        self.update(id, |message| message.message_chain = message_chain).is_some()
+    }
+
+    /**
+     * 取消关联 (平台消息已撤回)
+     */
+    pub async fn unref_bridge_message(&mut self, form: BridgeMessageRefMessageForm) -> bool {
+        self.update(&form.bridge_message_id, |message| {
+            message
+                .refs
+                .retain(|refs| !(refs.origin_id.eq(&form.origin_id) && refs.platform.eq(&form.platform)));
+        })
+        .is_some()
+    }
+
+    /**
+     * 添加或移除表情回应
+     * @return 更新后的桥消息, 消息不存在时返回None
+     */
+    pub async fn update_reaction(&mut self, id: &str, reaction: BridgeReaction, added: bool) -> Option<BridgeMessagePO> {
+        self.update(id, |message| {
+            message.reactions.retain(|r| r != &reaction);
+            if added {
+                message.reactions.push(reaction);
             }
-            None => false,
-        }
-        // for user in &self.bridge_users {
-        //     if origin_id.eq(&user.origin_id) && platform.eq(&user.platform) {
-        //         return Some(user.clone());
-        //     }
-        // }
-        // let mut bridge_message = bridge::pojo::BridgeMessagePO {
-        //     id: id.clone(),
-        //     refs: vec![],
-        // };
-        // bridge_message.refs.push(bridge::pojo::BridgeMessageRefPO {
-        //     platform: form.platform,
-        //     origin_id: form.origin_id,
-        // });
-        // self.messages.push(bridge_message);
-        // self.serialize();
-        // id
+        })
     }
 
     /**
@@ -91,33 +109,95 @@ impl BridgeMessageManager {
      */
     pub async fn find_by_ref_and_platform(
         &self,
-        origin_id: &str,
+        origin_id: &OriginMessageId,
         platform: &str,
     ) -> Result<Option<BridgeMessagePO>, String> {
-        let refs: Vec<&BridgeMessagePO> = self
-            .messages
-            .iter()
+        self.find_by_ref(platform, &origin_id.to_string(), |ref_id| ref_id.eq(origin_id)).await
+    }
+
+    /**
+     * 根据平台和关联id的匹配规则查询桥消息
+     * @param prefix 关联id的前缀, 用于缩小查询范围
+     */
+    pub async fn find_by_ref(
+        &self,
+        platform: &str,
+        prefix: &str,
+        matches: impl Fn(&OriginMessageId) -> bool,
+    ) -> Result<Option<BridgeMessagePO>, String> {
+        let messages = self
+            .storage
+            .find_by_ref(platform, prefix)
+            .map_err(|err| format!("查询关联的消息失败: {err}"))?;
+        let refs: Vec<BridgeMessagePO> = messages
+            .into_iter()
             .filter(|message| {
                 message
                     .refs
                     .iter()
-                    .find(|refs| refs.origin_id.eq(origin_id) && refs.platform.eq(platform))
+                    .find(|refs| refs.platform.eq(platform) && matches(&refs.origin_id))
                     .is_some()
             })
             .collect();
         if refs.len() > 1 {
             return Err("关联的消息查询到了多条".to_string());
         }
-        if refs.len() == 1 {
-            let po = refs.get(0).unwrap().clone().clone();
-            return Ok(Some(po));
+        Ok(refs.into_iter().next())
+    }
+
+    /**
+     * 按清理条件清理桥消息
+     * @return 清理的消息数
+     */
+    pub async fn prune(&mut self, policy: &RetentionPolicy) -> usize {
+        self.storage.prune(policy).unwrap_or_else(|err| {
+            error!("清理桥消息失败: {err:?}");
+            0
+        })
+    }
+
+    /**
+     * 修改并保存消息
+     * @return 修改后的桥消息, 消息不存在或保存失败时返回None
+     */
+    fn update(&mut self, id: &str, modify: impl FnOnce(&mut BridgeMessagePO)) -> Option<BridgeMessagePO> {
+        let mut message = match self.storage.get(id) {
+            Ok(message) => message?,
+            Err(err) => {
+                error!("查询桥消息失败: {err:?}");
+                return None;
+            }
+        };
+        modify(&mut message);
+        if let Err(err) = self.storage.save(&message) {
+            error!("保存桥消息失败: {err:?}");
+            return None;
         }
-        Ok(None)
+        Some(message)
     }
+}
 
-    fn serialize(&self) {
-        let content = serde_json::to_string(&self.messages).unwrap();
-        fs::write("./data/bridge_message.json", content).unwrap();
+/**
+ * 按配置的保留策略定时清理桥消息, 保留策略可以热重载
+ */
+pub async fn prune_messages(config: Arc<SharedConfig>) {
+    let mut interval = tokio::time::interval(RETENTION_INTERVAL);
+    loop {
+        interval.tick().await;
+        let retention = config.current().retention.clone();
+        if retention.maxDays.is_none() && retention.maxCount.is_none() {
+            continue;
+        }
+        let policy = RetentionPolicy {
+            before: retention
+                .maxDays
+                .map(|days| chrono::Local::now().timestamp() - days as i64 * 24 * 60 * 60),
+            max_count: retention.maxCount,
+        };
+        let pruned = BRIDGE_MESSAGE_MANAGER.lock().await.prune(&policy).await;
+        if pruned > 0 {
+            info!("已清理 {pruned} 条桥消息");
+        }
     }
 }
 
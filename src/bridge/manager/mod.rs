@@ -1,5 +1,7 @@
+pub mod dead_letter_manager;
 pub mod message_manager;
 pub mod user_manager;
 
+pub use dead_letter_manager::*;
 pub use message_manager::*;
 pub use user_manager::*;
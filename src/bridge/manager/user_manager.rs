@@ -1,46 +1,38 @@
 #![allow(non_snake_case)]
 
 use lazy_static::lazy_static;
-use std::fs;
 use tokio::sync::Mutex;
+use tracing::error;
 
 use crate::bridge::pojo::BridgeUserSaveForm;
+use crate::bridge::storage::{self, UserStorage};
 use crate::bridge::user::BridgeUser;
-use crate::elo;
 
 pub struct BridgeUserManager {
-    bridge_users: Vec<BridgeUser>,
+    storage: Box<dyn UserStorage>,
 }
 
 impl BridgeUserManager {
     pub fn new() -> BridgeUserManager {
-        let path = "./data/bridge_user.json";
-        if let Ok(true) = fs::try_exists(path) {
-            let file = fs::read_to_string(path).unwrap();
-            let bridge_users: Vec<BridgeUser> = serde_json::from_str(file.as_str()).unwrap();
-            return BridgeUserManager { bridge_users };
+        BridgeUserManager {
+            storage: storage::open_user_storage(),
         }
-        BridgeUserManager { bridge_users: vec![] }
     }
 
     /// 根据id查询指定用户
     pub async fn get(&self, id: &str) -> Option<BridgeUser> {
-        for user in &self.bridge_users {
-            if id.eq(&user.id) {
-                return Some(user.clone());
-            }
-        }
-        None
+        self.storage.get(id).unwrap_or_else(|err| {
+            error!("查询桥用户失败: {err:?}");
+            None
+        })
     }
 
     /// 模糊查询用户 (源id和平台)
     pub async fn like(&self, origin_id: &str, platform: &str) -> Option<BridgeUser> {
-        for user in &self.bridge_users {
-            if origin_id.eq(&user.origin_id) && platform.eq(&user.platform) {
-                return Some(user.clone());
-            }
-        }
-        None
+        self.storage.find_by_origin(origin_id, platform).unwrap_or_else(|err| {
+            error!("查询桥用户失败: {err:?}");
+            None
+        })
     }
 
     pub async fn likeAndSave(&mut self, form: BridgeUserSaveForm) -> Result<BridgeUser, String> {
@@ -50,17 +42,24 @@ impl BridgeUserManager {
         }
     }
 
+    /// 查询用户, 不存在时保存; 已存在且显示文本有变化时更新显示文本
+    pub async fn likeAndUpdate(&mut self, form: BridgeUserSaveForm) -> Result<BridgeUser, String> {
+        let Some(mut user) = self.like(&form.origin_id, &form.platform).await else {
+            return self.save(form).await;
+        };
+        if user.display_text != form.display_text {
+            user.display_text = form.display_text;
+            self.storage.save(&[user.clone()]).map_err(|err| format!("保存桥用户失败: {err}"))?;
+        }
+        Ok(user)
+    }
+
     /// 通过关联id和平台查询绑定的另一个账号
     pub async fn findByRefAndPlatform(&self, ref_id: &str, platform: &str) -> Option<BridgeUser> {
-        for user in &self.bridge_users {
-            if let None = user.ref_id {
-                continue;
-            }
-            if ref_id.eq(user.ref_id.as_ref().unwrap()) && platform.eq(&user.platform) {
-                return Some(user.clone());
-            }
-        }
-        None
+        self.storage.find_by_ref(ref_id, platform).unwrap_or_else(|err| {
+            error!("查询桥用户失败: {err:?}");
+            None
+        })
     }
 
     /// 保存一条新的用户
@@ -77,8 +76,7 @@ impl BridgeUserManager {
             display_text: form.display_text,
             ref_id: None,
         };
-        self.bridge_users.push(user.clone());
-        self.serialize();
+        self.storage.save(&[user.clone()]).map_err(|err| format!("保存桥用户失败: {err}"))?;
         Ok(user)
     }
 
@@ -89,26 +87,14 @@ impl BridgeUserManager {
     /// - `Ok(..)` 更新行数
     /// - `Err(..)` 失败描述
     pub async fn batch_update(&mut self, queue: &[BridgeUser]) -> Result<usize, String> {
-        let mut count = 0;
+        let mut users = vec![];
         for item in queue {
-            let old = elo! {
-                self.bridge_users.iter_mut().find(|t| t.id == item.id)
-                ;;// else
-                continue
-            };
-            old.display_text = item.display_text.clone();
-            old.origin_id = item.origin_id.clone();
-            old.platform = item.platform.clone();
-            old.ref_id = item.ref_id.clone();
-            count += 1;
+            if self.get(&item.id).await.is_some() {
+                users.push(item.clone());
+            }
         }
-        self.serialize();
-        Ok(count)
-    }
-
-    fn serialize(&self) {
-        let content = serde_json::to_string(&self.bridge_users).unwrap();
-        fs::write("./data/bridge_user.json", content).unwrap();
+        self.storage.save(&users).map_err(|err| format!("保存桥用户失败: {err}"))?;
+        Ok(users.len())
     }
 }
 
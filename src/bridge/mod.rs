@@ -6,16 +6,28 @@ use tokio::sync::Mutex;
 
 use serde::Deserialize;
 use serde::Serialize;
-use tokio::sync::broadcast;
 
 use crate::bridge;
 use crate::bridge::BridgeClientPlatform::*;
+use crate::config::FilterDirection;
+use filter::FilterContext;
 
-pub use bridge_message::{BridgeMessage, Image, MessageChain, MessageContent};
+pub use adapter::BridgeAdapter;
+pub use bridge_message::{BridgeEvent, BridgeMessage, BridgeReaction, Image, MessageChain, MessageContent};
+pub use origin_message_id::{GroupMessageId, OriginMessageId};
+pub use queue::MessageQueue;
 
+pub mod adapter;
+pub mod avatar;
 pub mod bridge_message;
+pub mod filter;
 pub mod manager;
+pub mod origin_message_id;
 pub mod pojo;
+pub mod queue;
+pub mod storage;
+#[cfg(test)]
+pub mod test_util;
 pub mod user;
 
 /// 解析枚举文本错误
@@ -113,12 +125,12 @@ impl BridgeService {
         BridgeService { clients: vec![] }
     }
 
-    pub async fn create_client(name: &str, service: Arc<Mutex<BridgeService>>) -> Arc<BridgeClient> {
+    pub async fn create_client(name: &str, platform: BridgeClientPlatform, service: Arc<Mutex<BridgeService>>) -> Arc<BridgeClient> {
         let clients = &mut service.lock().await.clients;
         if clients.iter().any(|client| client.name == name) {
             panic!("存在同一个桥名: {}", name);
         }
-        let client = Arc::new(BridgeClient::new(name, service.clone()));
+        let client = Arc::new(BridgeClient::new(name, platform, service.clone()));
         clients.push(client.clone());
         client
     }
@@ -126,33 +138,50 @@ impl BridgeService {
 
 pub struct BridgeClient {
     pub name: String,
+    /// 桥所属平台, 按平台路由消息
+    pub platform: BridgeClientPlatform,
     pub bridge: Arc<Mutex<BridgeService>>,
-    pub sender: broadcast::Sender<BridgeMessage>,
-    pub receiver: broadcast::Receiver<BridgeMessage>,
+    /// 待投递到该桥的事件
+    pub queue: MessageQueue,
 }
 
 impl BridgeClient {
-    pub fn new(name: &str, bridge: Arc<Mutex<BridgeService>>) -> Self {
-        let (sender, receiver) = broadcast::channel(32);
+    pub fn new(name: &str, platform: BridgeClientPlatform, bridge: Arc<Mutex<BridgeService>>) -> Self {
         BridgeClient {
             bridge,
             name: name.to_string(),
-            sender,
-            receiver,
+            platform,
+            queue: MessageQueue::open(name),
         }
     }
 
     /**
      * 向其它桥发送消息
+     * 消息沿桥配置的连接投递到目标端点所在平台的桥, 指令桥收到所有消息
+     * 投递前按桥配置的规则过滤消息, 见 [`filter`]
      */
-    pub async fn send_message(&self, message: bridge::pojo::BridgeSendMessageForm) {
-        let bridge = self.bridge.lock().await;
-
-        // let client = bridge
-        //     .clients
-        //     .iter()
-        //     .filter(|client| &client.name != &self.name);
-
+    pub async fn send_message(&self, mut message: bridge::pojo::BridgeSendMessageForm) {
+        let from = message
+            .origin_message
+            .platform
+            .parse::<BridgeClientPlatform>()
+            .ok()
+            .and_then(|platform| message.bridge_config.endpoint(platform))
+            .cloned();
+        message.origin_message.endpoint = from.as_ref().map(|endpoint| endpoint.name.clone());
+        let mut context = FilterContext {
+            direction: FilterDirection::Inbound,
+            from: filter::to_names(&message.origin_message.platform, from.as_ref()),
+            to: vec![],
+            sender: filter::to_sender_ids(&message.sender_id).await,
+        };
+        let rules = &message.bridge_config.filters;
+        let Some(message_chain) = filter::filter_message(rules, &context, std::mem::take(&mut message.message_chain)) else {
+            tracing::info!("[{}] 消息已被过滤: {}", message.origin_message.platform, message.origin_message.origin_id);
+            return;
+        };
+        message.message_chain = message_chain;
+        context.direction = FilterDirection::Outbound;
         let bridge_message = bridge::BridgeMessage {
             id: bridge::manager::BRIDGE_MESSAGE_MANAGER.lock().await.save(message.clone()).await,
             sender_id: message.sender_id,
@@ -160,13 +189,40 @@ impl BridgeClient {
             bridge_config: message.bridge_config,
             message_chain: message.message_chain,
         };
-
+        let targets = bridge_message.bridge_config.route(from.as_ref());
+        let bridge = self.bridge.lock().await;
         for client in bridge.clients.iter() {
-            if &client.name != &self.name {
-                if let Err(e) = client.sender.send(bridge_message.clone()) {
-                    tracing::error!("消息中转异常：{:#?}", e);
+            if client.platform == Cmd {
+                if client.name != self.name {
+                    // 落盘失败已记录日志, 事件仍会投递
+                    let _ = client.queue.push(BridgeEvent::Message(bridge_message.clone())).await;
                 }
+                continue;
             }
+            for target in targets.iter().filter(|target| target.target.platform() == client.platform) {
+                context.to = filter::to_names(client.platform, Some(target));
+                let rules = &bridge_message.bridge_config.filters;
+                let Some(message_chain) = filter::filter_message(rules, &context, bridge_message.message_chain.clone()) else {
+                    continue;
+                };
+                let message = bridge::BridgeMessage {
+                    bridge_config: bridge_message.bridge_config.to_target(target),
+                    message_chain,
+                    ..bridge_message.clone()
+                };
+                let _ = client.queue.push(BridgeEvent::Message(message)).await;
+            }
+        }
+    }
+
+    /**
+     * 向所有桥发送事件 (编辑、撤回等)
+     * 同一平台可能有多个端点, 事件也会发给自己, 由各桥处理消息在本平台的其它关联消息
+     */
+    pub async fn send_event(&self, event: BridgeEvent) {
+        let bridge = self.bridge.lock().await;
+        for client in bridge.clients.iter() {
+            let _ = client.queue.push(event.clone()).await;
         }
     }
 }
@@ -0,0 +1,223 @@
+//! 平台消息id
+//!
+//! 关联中保存为文本，格式与旧数据相同：
+//! - DC：消息id，如 `1084767470392872970`
+//! - QQ：`|群号|seqs|` 或 `|群号|seqs|rand|`，见 [`GroupMessageId`]
+//! - TG：`会话id:消息id`，会话id为0表示删除事件中未知的会话
+//!
+//! 其它文本(指令消息的uuid、TG早期记录的空id等)保留原文，只按原文比较
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::str::FromStr;
+
+use crate::bridge::ParseEnumErr;
+
+/// 平台消息id
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OriginMessageId {
+    Discord(u64),
+    QQ(GroupMessageId),
+    Telegram {
+        chat_id: i64,
+        message_id: i32,
+    },
+    /// 无法识别的id, 保留原文
+    Raw(String),
+}
+
+/**
+ * 由于没有唯一值, 只能由qq群号+seqs组成唯一值
+ * 撤回消息还需要rand, 记录在seqs之后: "|群号|seqs|rand|"
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GroupMessageId {
+    pub group_id: u64,
+    pub seqs: i32,
+    pub rand: Option<i32>,
+}
+
+impl GroupMessageId {
+    pub fn new(group_id: u64, seqs: i32) -> GroupMessageId {
+        GroupMessageId {
+            group_id,
+            seqs,
+            rand: None,
+        }
+    }
+
+    pub fn with_rand(group_id: u64, seqs: i32, rand: Option<i32>) -> GroupMessageId {
+        GroupMessageId { group_id, seqs, rand }
+    }
+
+    /**
+     * 是否同一条消息 (忽略rand)
+     */
+    pub fn is_same(&self, other: &GroupMessageId) -> bool {
+        self.group_id == other.group_id && self.seqs == other.seqs
+    }
+}
+
+impl Display for GroupMessageId {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self.rand {
+            Some(rand) => write!(f, "|{}|{}|{}|", self.group_id, self.seqs, rand),
+            None => write!(f, "|{}|{}|", self.group_id, self.seqs),
+        }
+    }
+}
+
+impl FromStr for GroupMessageId {
+    type Err = ParseEnumErr;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || ParseEnumErr(format!("无法解析QQ消息id: {s}"));
+        let splits: Vec<&str> = s
+            .strip_prefix('|')
+            .and_then(|s| s.strip_suffix('|'))
+            .ok_or_else(err)?
+            .split('|')
+            .collect();
+        match splits[..] {
+            [group_id, seqs] => Ok(GroupMessageId::new(
+                group_id.parse().map_err(|_| err())?,
+                seqs.parse().map_err(|_| err())?,
+            )),
+            [group_id, seqs, rand] => Ok(GroupMessageId::with_rand(
+                group_id.parse().map_err(|_| err())?,
+                seqs.parse().map_err(|_| err())?,
+                Some(rand.parse().map_err(|_| err())?),
+            )),
+            _ => Err(err()),
+        }
+    }
+}
+
+impl OriginMessageId {
+    /// 读取已保存的关联id, 格式错误时保留原文
+    pub fn from_stored(s: &str) -> OriginMessageId {
+        s.parse().unwrap_or_else(|err| {
+            tracing::warn!("{err}");
+            OriginMessageId::Raw(s.to_string())
+        })
+    }
+
+    /// # 是否指向同一条平台消息
+    /// - QQ：忽略rand
+    /// - TG：任一方会话id为0时只比较消息id
+    pub fn is_same(&self, other: &OriginMessageId) -> bool {
+        match (self, other) {
+            (OriginMessageId::QQ(a), OriginMessageId::QQ(b)) => a.is_same(b),
+            (
+                OriginMessageId::Telegram { chat_id, message_id },
+                OriginMessageId::Telegram {
+                    chat_id: other_chat_id,
+                    message_id: other_message_id,
+                },
+            ) => message_id == other_message_id && (chat_id == other_chat_id || *chat_id == 0 || *other_chat_id == 0),
+            _ => self == other,
+        }
+    }
+
+    /// # 可能匹配该id的关联文本前缀
+    /// 用于在存储中缩小查询范围，再按 [`OriginMessageId::is_same`] 匹配
+    pub fn ref_prefix(&self) -> String {
+        match self {
+            OriginMessageId::QQ(id) => GroupMessageId::new(id.group_id, id.seqs).to_string(),
+            // 不带会话id时需要查询所有会话的关联
+            OriginMessageId::Telegram { chat_id: 0, .. } => String::new(),
+            _ => self.to_string(),
+        }
+    }
+}
+
+impl Display for OriginMessageId {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            OriginMessageId::Discord(id) => write!(f, "{id}"),
+            OriginMessageId::QQ(id) => write!(f, "{id}"),
+            OriginMessageId::Telegram { chat_id, message_id } => write!(f, "{chat_id}:{message_id}"),
+            OriginMessageId::Raw(id) => write!(f, "{id}"),
+        }
+    }
+}
+
+/// 按格式识别平台, 格式错误时返回错误; 不属于任何平台的文本解析为 [`OriginMessageId::Raw`]
+impl FromStr for OriginMessageId {
+    type Err = ParseEnumErr;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.starts_with('|') {
+            return Ok(OriginMessageId::QQ(s.parse()?));
+        }
+        if let Some((chat_id, message_id)) = s.split_once(':') {
+            return match (chat_id.parse(), message_id.parse()) {
+                (Ok(chat_id), Ok(message_id)) => Ok(OriginMessageId::Telegram { chat_id, message_id }),
+                _ => Err(ParseEnumErr(format!("无法解析TG消息id: {s}"))),
+            };
+        }
+        if !s.is_empty() && s.bytes().all(|byte| byte.is_ascii_digit()) {
+            return s
+                .parse()
+                .map(OriginMessageId::Discord)
+                .map_err(|_| ParseEnumErr(format!("无法解析DC消息id: {s}")));
+        }
+        Ok(OriginMessageId::Raw(s.to_string()))
+    }
+}
+
+impl Serialize for OriginMessageId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// 已保存的关联不会因为格式错误而无法读取, 格式错误的id保留原文
+impl<'de> Deserialize<'de> for OriginMessageId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(OriginMessageId::from_stored(&String::deserialize(deserializer)?))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        //     reply_seq: 6539,
+        //     sender: 243249439,
+        let i1 = GroupMessageId::new(243249439, 6539);
+        assert_eq!(i1.to_string(), format!("|{}|{}|", 243249439, 6539));
+        let i2: OriginMessageId = i1.to_string().parse().unwrap();
+        assert_eq!(i2, OriginMessageId::QQ(i1.clone()));
+
+        let i3 = OriginMessageId::from_stored("|243249439|6539|1442369605|");
+        assert_eq!(
+            i3,
+            OriginMessageId::QQ(GroupMessageId::with_rand(243249439, 6539, Some(1442369605)))
+        );
+        assert!(i3.is_same(&i2));
+        assert_eq!(i3.ref_prefix(), i1.to_string());
+
+        let tg = OriginMessageId::from_stored("-100123:45");
+        assert!(tg.is_same(&OriginMessageId::Telegram {
+            chat_id: 0,
+            message_id: 45
+        }));
+        assert!(!tg.is_same(&OriginMessageId::Telegram {
+            chat_id: 7,
+            message_id: 45
+        }));
+        assert_eq!(
+            "1084767470392872970".parse::<OriginMessageId>().unwrap(),
+            OriginMessageId::Discord(1084767470392872970)
+        );
+
+        // 格式错误和旧数据
+        assert!("|abc|1|".parse::<OriginMessageId>().is_err());
+        assert!("1:x".parse::<OriginMessageId>().is_err());
+        assert_eq!(OriginMessageId::from_stored("|abc|1|"), OriginMessageId::Raw("|abc|1|".to_string()));
+        assert_eq!(OriginMessageId::from_stored(""), OriginMessageId::Raw(String::new()));
+        let id: OriginMessageId = serde_json::from_str("\"|1|2|\"").unwrap();
+        assert_eq!(serde_json::to_string(&id).unwrap(), "\"|1|2|\"");
+    }
+}
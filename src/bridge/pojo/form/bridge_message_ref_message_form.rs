@@ -1,3 +1,5 @@
+use crate::bridge::OriginMessageId;
+
 pub struct BridgeMessageRefMessageForm {
     /**
      * 桥消息id
@@ -10,5 +12,9 @@ pub struct BridgeMessageRefMessageForm {
     /**
      * 来源id
      */
-    pub origin_id: String,
+    pub origin_id: OriginMessageId,
+    /**
+     * 所在的端点名
+     */
+    pub endpoint: Option<String>,
 }
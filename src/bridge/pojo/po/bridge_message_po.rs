@@ -1,4 +1,4 @@
-use crate::bridge::MessageChain;
+use crate::bridge::{BridgeReaction, MessageChain, OriginMessageId};
 use serde::Deserialize;
 use serde::Serialize;
 
@@ -24,6 +24,21 @@ pub struct BridgeMessagePO {
      * 消息内容
      */
     pub message_chain: MessageChain,
+    /**
+     * 表情回应 (各平台用户的回应, 旧数据没有该字段)
+     */
+    #[serde(default)]
+    pub reactions: Vec<BridgeReaction>,
+    /**
+     * 来源桥的桥名 (旧数据为空)
+     */
+    #[serde(default)]
+    pub bridge: String,
+    /**
+     * 创建时间 (秒, 旧数据为0)
+     */
+    #[serde(default)]
+    pub created_at: i64,
 }
 
 #[derive(Deserialize, Serialize, Debug, Eq, PartialEq, Clone)]
@@ -35,5 +50,10 @@ pub struct BridgeMessageRefPO {
     /**
      * 来源id
      */
-    pub origin_id: String,
+    pub origin_id: OriginMessageId,
+    /**
+     * 所在的端点名, 为空时是消息来源或旧数据, 使用桥消息的配置
+     */
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub endpoint: Option<String>,
 }
@@ -0,0 +1,120 @@
+//! 桥事件投递队列
+//!
+//! 每个桥客户端持有一个队列，转发到该平台的桥事件先写入队列并落盘到 `./data/queue/<桥名>.json`，
+//! 适配器处理完成后应答([`MessageQueue::ack`])才会移出队列。
+//! 发送缓慢或程序中途退出都不会丢失消息，重启后继续投递未应答的事件。
+
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+
+use tokio::sync::{Mutex, Notify};
+use tracing::error;
+
+use crate::bridge::storage::json_file;
+use crate::bridge::BridgeEvent;
+
+pub struct MessageQueue {
+    path: PathBuf,
+    pending: Mutex<VecDeque<BridgeEvent>>,
+    notify: Notify,
+}
+
+impl MessageQueue {
+    /// 打开桥客户端的投递队列
+    pub fn open(name: &str) -> Self {
+        Self::open_at(Path::new("./data/queue").join(format!("{name}.json")))
+    }
+
+    /// 打开指定文件的投递队列，文件不存在时为空队列
+    pub fn open_at(path: PathBuf) -> Self {
+        let pending: VecDeque<BridgeEvent> = json_file::load(&path).unwrap_or_default();
+        let notify = Notify::new();
+        if !pending.is_empty() {
+            notify.notify_one();
+        }
+        MessageQueue {
+            path,
+            pending: Mutex::new(pending),
+            notify,
+        }
+    }
+
+    /// # 事件入队
+    /// ### Return
+    /// 落盘失败时返回错误(已记录日志), 事件仍在内存中的队列里投递
+    pub async fn push(&self, event: BridgeEvent) -> anyhow::Result<()> {
+        let mut pending = self.pending.lock().await;
+        pending.push_back(event);
+        let saved = self.serialize(&pending).await;
+        self.notify.notify_one();
+        saved
+    }
+
+    /// # 取出队首的事件
+    /// 队列为空时等待新事件；事件仍留在队列中，直到 [`MessageQueue::ack`]
+    pub async fn peek(&self) -> BridgeEvent {
+        loop {
+            if let Some(event) = self.pending.lock().await.front() {
+                return event.clone();
+            }
+            self.notify.notified().await;
+        }
+    }
+
+    /// # 应答事件
+    /// 事件已投递，移出队列
+    /// ### Argument
+    /// `id` 事件关联的桥消息id
+    pub async fn ack(&self, id: &str) {
+        let mut pending = self.pending.lock().await;
+        let Some(index) = pending.iter().position(|event| event.id() == id) else {
+            return;
+        };
+        pending.remove(index);
+        let _ = self.serialize(&pending).await;
+    }
+
+    async fn serialize(&self, pending: &VecDeque<BridgeEvent>) -> anyhow::Result<()> {
+        let saved = json_file::save_blocking(self.path.clone(), pending.clone()).await;
+        if let Err(err) = &saved {
+            error!(?err, "投递队列保存失败: {}", self.path.display());
+        }
+        saved
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::MessageQueue;
+    use crate::bridge::test_util::{message, temp_json};
+
+    #[test]
+    fn test_queue_ack() {
+        let path = temp_json("queue");
+        tokio_test::block_on(async {
+            let queue = MessageQueue::open_at(path.clone());
+            queue.push(message("1")).await.unwrap();
+            queue.push(message("2")).await.unwrap();
+            assert_eq!(queue.peek().await.id(), "1");
+            queue.ack("1").await;
+            assert_eq!(queue.peek().await.id(), "2");
+        });
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn test_queue_reopen() {
+        let path = temp_json("queue");
+        tokio_test::block_on(async {
+            let queue = MessageQueue::open_at(path.clone());
+            queue.push(message("1")).await.unwrap();
+            queue.push(message("2")).await.unwrap();
+            queue.ack("1").await;
+
+            // 未应答的消息在重启后继续投递
+            let queue = MessageQueue::open_at(path.clone());
+            assert_eq!(queue.peek().await.id(), "2");
+        });
+        let _ = std::fs::remove_file(path);
+    }
+}
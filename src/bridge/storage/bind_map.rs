@@ -0,0 +1,164 @@
+//! 旧版绑定映射 `./data/BindMap.json` 的迁移
+//!
+//! 旧版的每条映射是一对 `(平台, unique_id, display_id)`，迁移时按平台和 unique_id 查找(或创建)桥用户，
+//! 一对用户使用相同的 `ref_id`；与已有关联冲突的映射不会修改，只在结果中列出
+
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+use crate::bridge::storage::{json_file, UserStorage};
+use crate::bridge::user::BridgeUser;
+use crate::bridge::BridgeClientPlatform;
+
+pub const BIND_MAP_PATH: &str = "./data/BindMap.json";
+
+/// 平台枚举, unique_id, display_id
+type BindKey = (u64, u64, u64);
+type BindData = Vec<(BindKey, BindKey)>;
+
+/// 迁移结果
+#[derive(Debug, Default)]
+pub struct BindMapReport {
+    /// 新建立的关联数
+    pub bound: usize,
+    /// 已经关联, 跳过的映射数
+    pub skipped: usize,
+    /// 新创建的桥用户数
+    pub created: usize,
+    /// 无法迁移的映射及原因
+    pub conflicts: Vec<String>,
+}
+
+/// 映射中的一方
+struct Side {
+    platform: BridgeClientPlatform,
+    unique_id: u64,
+}
+
+impl Display for Side {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "[{}] {}", self.platform, self.unique_id)
+    }
+}
+
+impl Side {
+    fn parse((platform, unique_id, _): BindKey) -> Result<Self, String> {
+        match BridgeClientPlatform::by(platform) {
+            Some(platform @ (BridgeClientPlatform::Discord | BridgeClientPlatform::QQ | BridgeClientPlatform::Telegram)) => {
+                Ok(Side { platform, unique_id })
+            }
+            _ => Err(format!("未知的平台: {platform}")),
+        }
+    }
+}
+
+/// # 读取旧版绑定映射并导入桥用户
+/// 已迁移的映射会被跳过, 可以重复执行
+pub fn migrate(storage: &mut dyn UserStorage) -> anyhow::Result<BindMapReport> {
+    let Some(data) = json_file::load::<BindData>(BIND_MAP_PATH) else {
+        anyhow::bail!("找不到旧版绑定映射: {BIND_MAP_PATH}");
+    };
+    migrate_pairs(&data, storage)
+}
+
+fn migrate_pairs(data: &BindData, storage: &mut dyn UserStorage) -> anyhow::Result<BindMapReport> {
+    let mut report = BindMapReport::default();
+    for (a, b) in data {
+        let (a, b) = match (Side::parse(*a), Side::parse(*b)) {
+            (Ok(a), Ok(b)) => (a, b),
+            (Err(err), _) | (_, Err(err)) => {
+                report.conflicts.push(format!("{a:?} <-> {b:?}: {err}"));
+                continue;
+            }
+        };
+        if a.platform == b.platform {
+            report.conflicts.push(format!("{a} <-> {b}: 不能关联同一平台的用户"));
+            continue;
+        }
+        let mut user_a = find_or_create(storage, &a, &mut report)?;
+        let mut user_b = find_or_create(storage, &b, &mut report)?;
+        let ref_id = match (&user_a.ref_id, &user_b.ref_id) {
+            (Some(ref_a), Some(ref_b)) if ref_a == ref_b => {
+                report.skipped += 1;
+                continue;
+            }
+            (Some(_), Some(_)) => {
+                report.conflicts.push(format!("{user_a} <-> {user_b}: 双方已分别关联了其他用户"));
+                continue;
+            }
+            (Some(ref_id), None) | (None, Some(ref_id)) => ref_id.clone(),
+            (None, None) => uuid::Uuid::new_v4().to_string(),
+        };
+        // 关联组中每个平台只能有一个用户
+        let mut occupied = None;
+        for user in [&user_a, &user_b] {
+            if let Some(other) = storage.find_by_ref(&ref_id, &user.platform)? {
+                if other.id != user.id {
+                    occupied = Some(other);
+                }
+            }
+        }
+        if let Some(other) = occupied {
+            report
+                .conflicts
+                .push(format!("{user_a} <-> {user_b}: 已关联了该平台的其他用户 {other}"));
+            continue;
+        }
+        user_a.ref_id = Some(ref_id.clone());
+        user_b.ref_id = Some(ref_id);
+        storage.save(&[user_a, user_b])?;
+        report.bound += 1;
+    }
+    Ok(report)
+}
+
+/// 查找平台用户, 不存在时创建; 显示文本暂用平台id
+fn find_or_create(storage: &mut dyn UserStorage, side: &Side, report: &mut BindMapReport) -> anyhow::Result<BridgeUser> {
+    let origin_id = side.unique_id.to_string();
+    let platform = side.platform.to_string();
+    if let Some(user) = storage.find_by_origin(&origin_id, &platform)? {
+        return Ok(user);
+    }
+    let user = BridgeUser {
+        id: uuid::Uuid::new_v4().to_string(),
+        platform,
+        display_text: origin_id.clone(),
+        origin_id,
+        ref_id: None,
+    };
+    storage.save(&[user.clone()])?;
+    report.created += 1;
+    Ok(user)
+}
+
+#[cfg(test)]
+mod test {
+    use rusqlite::Connection;
+
+    use super::*;
+    use crate::bridge::storage::SqliteStorage;
+
+    #[test]
+    fn test_migrate_pairs() {
+        let mut storage = SqliteStorage::open(Connection::open_in_memory().unwrap()).unwrap();
+        let data: BindData = vec![
+            ((1, 111, 1), (2, 222, 222)),
+            ((8, 333, 333), (1, 111, 1)),
+            ((2, 444, 444), (1, 111, 1)),
+            ((1, 555, 5), (1, 666, 6)),
+            ((4, 1, 1), (2, 222, 222)),
+        ];
+        let report = migrate_pairs(&data, &mut storage).unwrap();
+        assert_eq!((report.bound, report.skipped, report.created), (2, 0, 4));
+        assert_eq!(report.conflicts.len(), 3);
+
+        let dc = storage.find_by_origin("111", "DC").unwrap().unwrap();
+        let ref_id = dc.ref_id.unwrap();
+        assert_eq!(UserStorage::find_by_ref(&storage, &ref_id, "QQ").unwrap().unwrap().origin_id, "222");
+        assert_eq!(UserStorage::find_by_ref(&storage, &ref_id, "TG").unwrap().unwrap().origin_id, "333");
+        assert_eq!(storage.find_by_origin("444", "QQ").unwrap().unwrap().ref_id, None);
+
+        // 重复执行时跳过已迁移的映射
+        let report = migrate_pairs(&data, &mut storage).unwrap();
+        assert_eq!((report.bound, report.skipped, report.created), (0, 2, 0));
+    }
+}
@@ -0,0 +1,206 @@
+use std::collections::HashMap;
+
+use crate::bridge::pojo::BridgeMessagePO;
+use crate::bridge::storage::{json_file, MessageStorage, RetentionPolicy, UserStorage};
+use crate::bridge::user::BridgeUser;
+
+const MESSAGE_PATH: &str = "./data/bridge_message.json";
+const USER_PATH: &str = "./data/bridge_user.json";
+
+/// 桥消息保存在json文件, 每次修改重写整个文件
+pub struct JsonMessageStorage {
+    messages: Vec<BridgeMessagePO>,
+}
+
+impl JsonMessageStorage {
+    pub fn new() -> Self {
+        JsonMessageStorage {
+            messages: json_file::load(MESSAGE_PATH).unwrap_or_default(),
+        }
+    }
+
+    /// 全部桥消息
+    pub fn all(self) -> Vec<BridgeMessagePO> {
+        self.messages
+    }
+
+    /// 导入桥消息, 已存在时覆盖, 全部导入后写入一次
+    pub fn import(&mut self, messages: &[BridgeMessagePO]) -> anyhow::Result<()> {
+        let mut index: HashMap<String, usize> = self
+            .messages
+            .iter()
+            .enumerate()
+            .map(|(i, message)| (message.id.clone(), i))
+            .collect();
+        for message in messages {
+            match index.get(&message.id) {
+                Some(&i) => self.messages[i] = message.clone(),
+                None => {
+                    index.insert(message.id.clone(), self.messages.len());
+                    self.messages.push(message.clone());
+                }
+            }
+        }
+        self.serialize()
+    }
+
+    fn serialize(&self) -> anyhow::Result<()> {
+        json_file::save(MESSAGE_PATH, &self.messages)
+    }
+}
+
+impl MessageStorage for JsonMessageStorage {
+    fn get(&self, id: &str) -> anyhow::Result<Option<BridgeMessagePO>> {
+        Ok(self.messages.iter().find(|message| message.id == id).cloned())
+    }
+
+    fn save(&mut self, message: &BridgeMessagePO) -> anyhow::Result<()> {
+        match self.messages.iter_mut().find(|old| old.id == message.id) {
+            Some(old) => *old = message.clone(),
+            None => self.messages.push(message.clone()),
+        }
+        self.serialize()
+    }
+
+    fn find_by_ref(&self, platform: &str, prefix: &str) -> anyhow::Result<Vec<BridgeMessagePO>> {
+        Ok(self
+            .messages
+            .iter()
+            .filter(|message| {
+                message
+                    .refs
+                    .iter()
+                    .any(|refs| refs.platform == platform && refs.origin_id.to_string().starts_with(prefix))
+            })
+            .cloned()
+            .collect())
+    }
+
+    fn prune(&mut self, policy: &RetentionPolicy) -> anyhow::Result<usize> {
+        let pruned = prune_messages(&mut self.messages, policy);
+        if pruned > 0 {
+            self.serialize()?;
+        }
+        Ok(pruned)
+    }
+}
+
+/// 按清理条件移除消息, 消息按保存顺序排列
+fn prune_messages(messages: &mut Vec<BridgeMessagePO>, policy: &RetentionPolicy) -> usize {
+    let len = messages.len();
+    if let Some(before) = policy.before {
+        messages.retain(|message| message.created_at == 0 || message.created_at >= before);
+    }
+    if let Some(max_count) = policy.max_count {
+        // 从新到旧计数
+        let mut counts: HashMap<&str, usize> = HashMap::new();
+        let mut keep: Vec<bool> = messages
+            .iter()
+            .rev()
+            .map(|message| {
+                let count = counts.entry(message.bridge.as_str()).or_default();
+                *count += 1;
+                *count <= max_count
+            })
+            .collect();
+        keep.reverse();
+        let mut keep = keep.into_iter();
+        messages.retain(|_| keep.next().unwrap_or(true));
+    }
+    len - messages.len()
+}
+
+/// 桥用户保存在json文件, 每次修改重写整个文件
+pub struct JsonUserStorage {
+    bridge_users: Vec<BridgeUser>,
+}
+
+impl JsonUserStorage {
+    pub fn new() -> Self {
+        JsonUserStorage {
+            bridge_users: json_file::load(USER_PATH).unwrap_or_default(),
+        }
+    }
+
+    /// 全部桥用户
+    pub fn all(self) -> Vec<BridgeUser> {
+        self.bridge_users
+    }
+
+    fn serialize(&self) -> anyhow::Result<()> {
+        json_file::save(USER_PATH, &self.bridge_users)
+    }
+}
+
+impl UserStorage for JsonUserStorage {
+    fn get(&self, id: &str) -> anyhow::Result<Option<BridgeUser>> {
+        Ok(self.bridge_users.iter().find(|user| user.id == id).cloned())
+    }
+
+    fn find_by_origin(&self, origin_id: &str, platform: &str) -> anyhow::Result<Option<BridgeUser>> {
+        Ok(self
+            .bridge_users
+            .iter()
+            .find(|user| user.origin_id == origin_id && user.platform == platform)
+            .cloned())
+    }
+
+    fn find_by_ref(&self, ref_id: &str, platform: &str) -> anyhow::Result<Option<BridgeUser>> {
+        Ok(self
+            .bridge_users
+            .iter()
+            .find(|user| user.ref_id.as_deref() == Some(ref_id) && user.platform == platform)
+            .cloned())
+    }
+
+    fn save(&mut self, users: &[BridgeUser]) -> anyhow::Result<()> {
+        for user in users {
+            match self.bridge_users.iter_mut().find(|old| old.id == user.id) {
+                Some(old) => *old = user.clone(),
+                None => self.bridge_users.push(user.clone()),
+            }
+        }
+        self.serialize()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn message(id: &str, bridge: &str, created_at: i64) -> BridgeMessagePO {
+        BridgeMessagePO {
+            id: id.to_string(),
+            sender_id: "sender".to_string(),
+            avatar_url: None,
+            refs: vec![],
+            message_chain: vec![],
+            reactions: vec![],
+            bridge: bridge.to_string(),
+            created_at,
+        }
+    }
+
+    #[test]
+    fn test_prune_messages() {
+        let mut messages = vec![
+            message("legacy", "", 0),
+            message("a1", "a", 100),
+            message("a2", "a", 200),
+            message("b1", "b", 300),
+            message("a3", "a", 400),
+        ];
+        let policy = RetentionPolicy {
+            before: Some(150),
+            max_count: None,
+        };
+        assert_eq!(prune_messages(&mut messages, &policy), 1);
+        let policy = RetentionPolicy {
+            before: None,
+            max_count: Some(1),
+        };
+        assert_eq!(prune_messages(&mut messages, &policy), 1);
+        let ids: Vec<&str> = messages.iter().map(|message| message.id.as_str()).collect();
+        assert_eq!(ids, vec!["legacy", "b1", "a3"]);
+    }
+}
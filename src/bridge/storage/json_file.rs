@@ -0,0 +1,173 @@
+//! json数据文件的读写
+//!
+//! - 写入：先写临时文件并同步到磁盘，再替换原文件，写入中途崩溃不会损坏原文件；替换前原文件轮转为备份 `<文件>.1` ~ `<文件>.3`，
+//!   启动后第一次写入时轮转，之后每个文件每小时最多轮转一次
+//! - 读取：文件损坏时另存为 `<文件>.corrupt`，并依次尝试备份，使用最近一份完好的数据
+
+use lazy_static::lazy_static;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::{error, warn};
+
+/// 保留的备份数
+const BACKUPS: usize = 3;
+/// 同一文件两次轮转备份的最短间隔
+const BACKUP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+lazy_static! {
+    /// 文件 -> 上次轮转备份的时间
+    static ref ROTATED_AT: Mutex<HashMap<PathBuf, Instant>> = Mutex::new(HashMap::new());
+}
+
+/// 在文件名后追加后缀, 如 `bridge_user.json.1`
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{suffix}"));
+    PathBuf::from(name)
+}
+
+/// # 读取json文件
+/// ### Returns
+/// 文件和备份都不存在, 或都已损坏时返回 `None`
+pub fn load<T: DeserializeOwned>(path: impl AsRef<Path>) -> Option<T> {
+    let path = path.as_ref();
+    let mut corrupt = false;
+    let candidates = std::iter::once(path.to_path_buf()).chain((1..=BACKUPS).map(|index| with_suffix(path, &index.to_string())));
+    for (index, candidate) in candidates.enumerate() {
+        let content = match fs::read_to_string(&candidate) {
+            Ok(content) => content,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(err) => {
+                error!(?err, "读取数据文件失败: {}", candidate.display());
+                corrupt = true;
+                continue;
+            }
+        };
+        match serde_json::from_str(&content) {
+            Ok(data) => {
+                if corrupt || index > 0 {
+                    warn!("数据文件 {} 已损坏或丢失, 使用备份: {}", path.display(), candidate.display());
+                }
+                return Some(data);
+            }
+            Err(err) => {
+                error!(?err, "数据文件已损坏: {}", candidate.display());
+                if index == 0 {
+                    // 保留损坏的文件以便排查, 之后的写入不会再覆盖它
+                    let _ = fs::rename(&candidate, with_suffix(path, "corrupt"));
+                }
+                corrupt = true;
+            }
+        }
+    }
+    if corrupt {
+        error!("数据文件 {} 及其备份都已损坏, 使用空数据", path.display());
+    }
+    None
+}
+
+/// # 写入json文件
+/// 先写入临时文件再替换原文件, 距上次轮转超过 [`BACKUP_INTERVAL`] 时原文件轮转为备份
+pub fn save<T: Serialize + ?Sized>(path: impl AsRef<Path>, data: &T) -> anyhow::Result<()> {
+    save_with_interval(path.as_ref(), data, BACKUP_INTERVAL)
+}
+
+/// # 在阻塞线程中写入json文件
+/// 文件同步到磁盘较慢, 不占用异步运行时的线程; 调用时仍持有数据的锁, 保证按顺序写入
+pub async fn save_blocking<T: Serialize + Send + 'static>(path: PathBuf, data: T) -> anyhow::Result<()> {
+    match tokio::task::spawn_blocking(move || save(path, &data)).await {
+        Ok(saved) => saved,
+        Err(err) => Err(err.into()),
+    }
+}
+
+fn save_with_interval<T: Serialize + ?Sized>(path: &Path, data: &T, interval: Duration) -> anyhow::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let content = serde_json::to_vec(data)?;
+    let temp = with_suffix(path, "tmp");
+    let mut file = fs::File::create(&temp)?;
+    file.write_all(&content)?;
+    file.sync_all()?;
+    drop(file);
+
+    rotate_if_due(path, interval)?;
+    fs::rename(&temp, path)?;
+    Ok(())
+}
+
+/// 本次启动还没有轮转过, 或距上次轮转超过间隔时轮转备份
+fn rotate_if_due(path: &Path, interval: Duration) -> anyhow::Result<()> {
+    if !path.exists() {
+        return Ok(());
+    }
+    let mut rotated_at = ROTATED_AT.lock().unwrap();
+    if rotated_at.get(path).map_or(false, |at| at.elapsed() < interval) {
+        return Ok(());
+    }
+    rotate_backups(path)?;
+    rotated_at.insert(path.to_path_buf(), Instant::now());
+    Ok(())
+}
+
+/// 备份依次后移, 原文件链接(或复制)为第一份备份, 原文件在替换前始终存在
+fn rotate_backups(path: &Path) -> anyhow::Result<()> {
+    for index in (1..BACKUPS).rev() {
+        let from = with_suffix(path, &index.to_string());
+        if from.exists() {
+            fs::rename(&from, with_suffix(path, &(index + 1).to_string()))?;
+        }
+    }
+    let backup = with_suffix(path, "1");
+    if fs::hard_link(path, &backup).is_err() {
+        fs::copy(path, &backup)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bridge::test_util::temp_path;
+
+    #[test]
+    fn test_recover_from_backup() {
+        let dir = temp_path("json_file");
+        let path = dir.join("data.json");
+        assert_eq!(load::<Vec<u32>>(&path), None);
+        for data in [vec![1], vec![1, 2], vec![1, 2, 3], vec![1, 2, 3, 4], vec![1, 2, 3, 4, 5]] {
+            save_with_interval(&path, &data, Duration::ZERO).unwrap();
+        }
+        assert_eq!(load::<Vec<u32>>(&path), Some(vec![1, 2, 3, 4, 5]));
+        assert!(!with_suffix(&path, "4").exists());
+
+        // 写入中途崩溃, 文件内容不完整
+        fs::write(&path, "[1, 2, 3").unwrap();
+        assert_eq!(load::<Vec<u32>>(&path), Some(vec![1, 2, 3, 4]));
+        assert!(with_suffix(&path, "corrupt").exists());
+        fs::write(with_suffix(&path, "1"), "").unwrap();
+        assert_eq!(load::<Vec<u32>>(&path), Some(vec![1, 2, 3]));
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_rotate_interval() {
+        let dir = temp_path("json_file");
+        let path = dir.join("data.json");
+        for data in [vec![1], vec![1, 2], vec![1, 2, 3]] {
+            save(&path, &data).unwrap();
+        }
+        // 只在第一次替换原文件时轮转
+        assert_eq!(load::<Vec<u32>>(with_suffix(&path, "1")), Some(vec![1]));
+        assert!(!with_suffix(&path, "2").exists());
+        assert_eq!(load::<Vec<u32>>(&path), Some(vec![1, 2, 3]));
+        let _ = fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,204 @@
+//! 桥用户和桥消息的存储
+//!
+//! - json：全部数据保存在 `./data/bridge_user.json`、`./data/bridge_message.json`，每次修改重写整个文件，见 [`json_file`]
+//! - sqlite：保存在 `./data/bridge.db`，按 (平台, 关联id) 建立索引，修改在事务中写入
+//!
+//! 存储方式由配置的 `storage` 选择，启动时打开 (见 [`init`])，修改后需要重启；已有的 json 数据可用 `storage migrate` 子命令导入 sqlite，
+//! 旧版的绑定映射可用 `storage migrate-bind-map` 子命令导入桥用户，见 [`bind_map`]
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::sync::OnceLock;
+
+use crate::bridge::pojo::BridgeMessagePO;
+use crate::bridge::user::BridgeUser;
+
+pub use json::{JsonMessageStorage, JsonUserStorage};
+pub use sqlite::SqliteStorage;
+
+pub mod bind_map;
+pub mod json;
+pub mod json_file;
+pub mod sqlite;
+
+/// 存储方式
+#[derive(Clone, Copy, Default, Deserialize, Serialize, Debug, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageKind {
+    #[default]
+    Json,
+    Sqlite,
+}
+
+/// 已打开的存储
+enum Storage {
+    Json,
+    Sqlite(SqliteStorage),
+}
+
+static STORAGE: OnceLock<Storage> = OnceLock::new();
+
+/// # 按存储方式打开存储
+/// 需要在使用桥用户、桥消息之前调用, 未调用时使用json; sqlite在此时打开数据库并升级表结构
+pub fn init(kind: StorageKind) -> anyhow::Result<()> {
+    let storage = match kind {
+        StorageKind::Json => Storage::Json,
+        StorageKind::Sqlite => Storage::Sqlite(SqliteStorage::new().context("打开sqlite数据库失败")?),
+    };
+    if STORAGE.set(storage).is_err() {
+        tracing::warn!("存储已经打开, 忽略: {kind:?}");
+    }
+    Ok(())
+}
+
+fn storage() -> &'static Storage {
+    STORAGE.get().unwrap_or(&Storage::Json)
+}
+
+/// 桥消息的存储
+pub trait MessageStorage: Send + Sync {
+    /// 查询指定消息
+    fn get(&self, id: &str) -> anyhow::Result<Option<BridgeMessagePO>>;
+
+    /// 保存消息, 已存在时覆盖 (包括关联和表情回应)
+    fn save(&mut self, message: &BridgeMessagePO) -> anyhow::Result<()>;
+
+    /// # 查询关联了该平台消息的桥消息
+    /// ### Arguments
+    /// - `platform` 关联所在的平台
+    /// - `prefix` 关联id的前缀, 由调用方再按平台规则精确匹配
+    fn find_by_ref(&self, platform: &str, prefix: &str) -> anyhow::Result<Vec<BridgeMessagePO>>;
+
+    /// # 清理桥消息
+    /// ### Returns
+    /// 清理的消息数
+    fn prune(&mut self, policy: &RetentionPolicy) -> anyhow::Result<usize>;
+}
+
+/// 桥消息的清理条件
+pub struct RetentionPolicy {
+    /// 清理早于该时间(秒)的消息, 没有创建时间的旧消息不按时间清理
+    pub before: Option<i64>,
+    /// 每个桥保留的最新消息条数
+    pub max_count: Option<usize>,
+}
+
+/// 桥用户的存储
+pub trait UserStorage: Send + Sync {
+    /// 根据id查询用户
+    fn get(&self, id: &str) -> anyhow::Result<Option<BridgeUser>>;
+
+    /// 根据平台和源id查询用户
+    fn find_by_origin(&self, origin_id: &str, platform: &str) -> anyhow::Result<Option<BridgeUser>>;
+
+    /// 通过关联id查询该平台的用户
+    fn find_by_ref(&self, ref_id: &str, platform: &str) -> anyhow::Result<Option<BridgeUser>>;
+
+    /// 保存用户, 已存在时覆盖; 同一批用户一起写入
+    fn save(&mut self, users: &[BridgeUser]) -> anyhow::Result<()>;
+}
+
+/// 按配置的存储方式打开桥消息存储
+pub fn open_message_storage() -> Box<dyn MessageStorage> {
+    match storage() {
+        Storage::Json => Box::new(JsonMessageStorage::new()),
+        Storage::Sqlite(sqlite) => Box::new(sqlite.clone()),
+    }
+}
+
+/// 按配置的存储方式打开桥用户存储
+pub fn open_user_storage() -> Box<dyn UserStorage> {
+    match storage() {
+        Storage::Json => Box::new(JsonUserStorage::new()),
+        Storage::Sqlite(sqlite) => Box::new(sqlite.clone()),
+    }
+}
+
+/// 按配置的存储方式读取全部桥用户和桥消息
+pub fn export() -> anyhow::Result<(Vec<BridgeUser>, Vec<BridgeMessagePO>)> {
+    match storage() {
+        Storage::Json => Ok((JsonUserStorage::new().all(), JsonMessageStorage::new().all())),
+        Storage::Sqlite(sqlite) => sqlite.export(),
+    }
+}
+
+/// 按配置的存储方式导入桥用户和桥消息, 已存在时覆盖
+pub fn import(users: &[BridgeUser], messages: &[BridgeMessagePO]) -> anyhow::Result<()> {
+    match storage() {
+        Storage::Json => {
+            UserStorage::save(&mut JsonUserStorage::new(), users)?;
+            JsonMessageStorage::new().import(messages)
+        }
+        Storage::Sqlite(sqlite) => sqlite.clone().import(users, messages),
+    }
+}
+
+/// json数据导入sqlite的结果
+#[derive(Debug, Default)]
+pub struct MigrateReport {
+    /// 导入的桥用户数
+    pub users: usize,
+    /// 导入的桥消息数
+    pub messages: usize,
+    /// 与其他桥用户的 (平台, 源id) 重复, 没有导入的桥用户
+    pub duplicates: Vec<BridgeUser>,
+}
+
+/// # 将json文件的数据导入sqlite
+/// 已导入的数据会被覆盖, 可以重复执行
+pub fn migrate_json_to_sqlite() -> anyhow::Result<MigrateReport> {
+    let mut sqlite = SqliteStorage::new()?;
+    let (users, duplicates) = split_duplicates(&sqlite, JsonUserStorage::new().all())?;
+    let messages = JsonMessageStorage::new().all();
+    sqlite.import(&users, &messages)?;
+    Ok(MigrateReport {
+        users: users.len(),
+        messages: messages.len(),
+        duplicates,
+    })
+}
+
+/// # 分出 (平台, 源id) 重复的桥用户
+/// sqlite中 (平台, 源id) 唯一, 同一平台用户只保留第一个桥用户, 已导入其他桥用户时全部视为重复
+fn split_duplicates(sqlite: &SqliteStorage, users: Vec<BridgeUser>) -> anyhow::Result<(Vec<BridgeUser>, Vec<BridgeUser>)> {
+    let mut seen = HashSet::new();
+    let mut unique = vec![];
+    let mut duplicates = vec![];
+    for user in users {
+        let saved = sqlite.find_by_origin(&user.origin_id, &user.platform)?;
+        let first = seen.insert((user.platform.clone(), user.origin_id.clone()));
+        if first && saved.map_or(true, |saved| saved.id == user.id) {
+            unique.push(user);
+        } else {
+            duplicates.push(user);
+        }
+    }
+    Ok((unique, duplicates))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rusqlite::Connection;
+
+    #[test]
+    fn test_split_duplicates() {
+        let user = |id: &str, origin_id: &str| BridgeUser {
+            id: id.to_string(),
+            platform: "QQ".to_string(),
+            origin_id: origin_id.to_string(),
+            display_text: id.to_string(),
+            ref_id: None,
+        };
+        let mut sqlite = SqliteStorage::open(Connection::open_in_memory().unwrap()).unwrap();
+        UserStorage::save(&mut sqlite, &[user("a", "1")]).unwrap();
+
+        let users = vec![user("a", "1"), user("b", "2"), user("c", "2"), user("d", "1")];
+        let (unique, duplicates) = split_duplicates(&sqlite, users).unwrap();
+        let ids = |users: &[BridgeUser]| users.iter().map(|user| user.id.clone()).collect::<Vec<_>>();
+        assert_eq!(ids(&unique), vec!["a", "b"]);
+        assert_eq!(ids(&duplicates), vec!["c", "d"]);
+        sqlite.import(&unique, &[]).unwrap();
+    }
+}
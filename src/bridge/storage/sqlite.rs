@@ -0,0 +1,376 @@
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use std::sync::{Arc, Mutex};
+
+use crate::bridge::pojo::{BridgeMessagePO, BridgeMessageRefPO};
+use crate::bridge::storage::{MessageStorage, RetentionPolicy, UserStorage};
+use crate::bridge::user::BridgeUser;
+use crate::bridge::OriginMessageId;
+
+const DB_PATH: &str = "./data/bridge.db";
+
+const SCHEMA: &str = "
+PRAGMA journal_mode = WAL;
+CREATE TABLE IF NOT EXISTS bridge_user (
+    id TEXT PRIMARY KEY,
+    platform TEXT NOT NULL,
+    origin_id TEXT NOT NULL,
+    display_text TEXT NOT NULL,
+    ref_id TEXT
+);
+CREATE UNIQUE INDEX IF NOT EXISTS bridge_user_origin ON bridge_user (platform, origin_id);
+CREATE INDEX IF NOT EXISTS bridge_user_ref ON bridge_user (ref_id, platform);
+CREATE TABLE IF NOT EXISTS bridge_message (
+    id TEXT PRIMARY KEY,
+    sender_id TEXT NOT NULL,
+    avatar_url TEXT,
+    message_chain TEXT NOT NULL,
+    reactions TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS bridge_message_ref (
+    message_id TEXT NOT NULL,
+    position INTEGER NOT NULL,
+    platform TEXT NOT NULL,
+    origin_id TEXT NOT NULL,
+    endpoint TEXT,
+    PRIMARY KEY (message_id, position)
+);
+CREATE INDEX IF NOT EXISTS bridge_message_ref_origin ON bridge_message_ref (platform, origin_id);
+";
+
+/// 表结构的升级, 已执行的版本记录在 `user_version`
+const MIGRATIONS: &[&str] = &[
+    // 1: 桥消息的来源桥和创建时间
+    "
+ALTER TABLE bridge_message ADD COLUMN bridge TEXT NOT NULL DEFAULT '';
+ALTER TABLE bridge_message ADD COLUMN created_at INTEGER NOT NULL DEFAULT 0;
+CREATE INDEX IF NOT EXISTS bridge_message_created ON bridge_message (bridge, created_at);
+",
+];
+
+const USER_COLUMNS: &str = "id, platform, origin_id, display_text, ref_id";
+
+/// # 桥用户和桥消息保存在sqlite
+/// 消息内容和表情回应以json保存, 关联单独建表并按 (平台, 关联id) 建立索引; 克隆的存储共享同一个连接
+#[derive(Clone)]
+pub struct SqliteStorage {
+    connection: Arc<Mutex<Connection>>,
+}
+
+impl SqliteStorage {
+    pub fn new() -> anyhow::Result<Self> {
+        Self::open(Connection::open(DB_PATH)?)
+    }
+
+    pub fn open(mut connection: Connection) -> anyhow::Result<Self> {
+        connection.execute_batch(SCHEMA)?;
+        let version: usize = connection.query_row("PRAGMA user_version", params![], |row| row.get(0))?;
+        for (index, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+            let transaction = connection.transaction()?;
+            transaction.execute_batch(migration)?;
+            transaction.execute_batch(&format!("PRAGMA user_version = {}", index + 1))?;
+            transaction.commit()?;
+        }
+        Ok(SqliteStorage {
+            connection: Arc::new(Mutex::new(connection)),
+        })
+    }
+
+    /// 在一个事务中导入桥用户和桥消息
+    pub fn import(&mut self, users: &[BridgeUser], messages: &[BridgeMessagePO]) -> anyhow::Result<()> {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+        for user in users {
+            save_user(&transaction, user)?;
+        }
+        for message in messages {
+            save_message(&transaction, message)?;
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// 读取全部桥用户和桥消息
+    pub fn export(&self) -> anyhow::Result<(Vec<BridgeUser>, Vec<BridgeMessagePO>)> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare(&format!("SELECT {USER_COLUMNS} FROM bridge_user ORDER BY rowid"))?;
+        let users = statement.query_map(params![], to_user)?.collect::<Result<Vec<_>, _>>()?;
+        let mut statement = connection.prepare("SELECT id FROM bridge_message ORDER BY rowid")?;
+        let ids = statement
+            .query_map(params![], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut messages = vec![];
+        for id in ids {
+            messages.extend(get_message(&connection, &id)?);
+        }
+        Ok((users, messages))
+    }
+}
+
+impl MessageStorage for SqliteStorage {
+    fn get(&self, id: &str) -> anyhow::Result<Option<BridgeMessagePO>> {
+        get_message(&self.connection.lock().unwrap(), id)
+    }
+
+    fn save(&mut self, message: &BridgeMessagePO) -> anyhow::Result<()> {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+        save_message(&transaction, message)?;
+        transaction.commit()?;
+        Ok(())
+    }
+
+    fn find_by_ref(&self, platform: &str, prefix: &str) -> anyhow::Result<Vec<BridgeMessagePO>> {
+        // 前缀查询转为范围查询, 可以使用索引
+        let end = format!("{prefix}\u{10FFFF}");
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare_cached(
+            "SELECT DISTINCT message_id FROM bridge_message_ref WHERE platform = ?1 AND origin_id >= ?2 AND origin_id < ?3",
+        )?;
+        let ids = statement
+            .query_map(params![platform, prefix, end], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut messages = vec![];
+        for id in ids {
+            messages.extend(get_message(&connection, &id)?);
+        }
+        Ok(messages)
+    }
+
+    fn prune(&mut self, policy: &RetentionPolicy) -> anyhow::Result<usize> {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+        let mut pruned = 0;
+        if let Some(before) = policy.before {
+            pruned += transaction.execute("DELETE FROM bridge_message WHERE created_at > 0 AND created_at < ?1", [before])?;
+        }
+        if let Some(max_count) = policy.max_count {
+            pruned += transaction.execute(
+                "DELETE FROM bridge_message WHERE id IN (
+                    SELECT id FROM (
+                        SELECT id, ROW_NUMBER() OVER (PARTITION BY bridge ORDER BY created_at DESC, rowid DESC) AS row FROM bridge_message
+                    ) WHERE row > ?1
+                )",
+                [max_count as i64],
+            )?;
+        }
+        transaction.execute(
+            "DELETE FROM bridge_message_ref WHERE message_id NOT IN (SELECT id FROM bridge_message)",
+            params![],
+        )?;
+        transaction.commit()?;
+        Ok(pruned)
+    }
+}
+
+impl UserStorage for SqliteStorage {
+    fn get(&self, id: &str) -> anyhow::Result<Option<BridgeUser>> {
+        let sql = format!("SELECT {USER_COLUMNS} FROM bridge_user WHERE id = ?1");
+        Ok(self.connection.lock().unwrap().query_row(&sql, [id], to_user).optional()?)
+    }
+
+    fn find_by_origin(&self, origin_id: &str, platform: &str) -> anyhow::Result<Option<BridgeUser>> {
+        let sql = format!("SELECT {USER_COLUMNS} FROM bridge_user WHERE platform = ?1 AND origin_id = ?2");
+        Ok(self
+            .connection
+            .lock()
+            .unwrap()
+            .query_row(&sql, [platform, origin_id], to_user)
+            .optional()?)
+    }
+
+    fn find_by_ref(&self, ref_id: &str, platform: &str) -> anyhow::Result<Option<BridgeUser>> {
+        let sql = format!("SELECT {USER_COLUMNS} FROM bridge_user WHERE ref_id = ?1 AND platform = ?2 LIMIT 1");
+        Ok(self
+            .connection
+            .lock()
+            .unwrap()
+            .query_row(&sql, [ref_id, platform], to_user)
+            .optional()?)
+    }
+
+    fn save(&mut self, users: &[BridgeUser]) -> anyhow::Result<()> {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+        for user in users {
+            save_user(&transaction, user)?;
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+}
+
+fn to_user(row: &Row) -> rusqlite::Result<BridgeUser> {
+    Ok(BridgeUser {
+        id: row.get(0)?,
+        platform: row.get(1)?,
+        origin_id: row.get(2)?,
+        display_text: row.get(3)?,
+        ref_id: row.get(4)?,
+    })
+}
+
+fn save_user(connection: &Connection, user: &BridgeUser) -> anyhow::Result<()> {
+    connection.execute(
+        "INSERT INTO bridge_user (id, platform, origin_id, display_text, ref_id) VALUES (?1, ?2, ?3, ?4, ?5)
+         ON CONFLICT (id) DO UPDATE SET platform = excluded.platform, origin_id = excluded.origin_id,
+         display_text = excluded.display_text, ref_id = excluded.ref_id",
+        params![user.id, user.platform, user.origin_id, user.display_text, user.ref_id],
+    )?;
+    Ok(())
+}
+
+fn get_message(connection: &Connection, id: &str) -> anyhow::Result<Option<BridgeMessagePO>> {
+    let row = connection
+        .query_row(
+            "SELECT sender_id, avatar_url, message_chain, reactions, bridge, created_at FROM bridge_message WHERE id = ?1",
+            [id],
+            |row| {
+                Ok((
+                    row.get(0)?,
+                    row.get(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, String>(3)?,
+                    row.get(4)?,
+                    row.get(5)?,
+                ))
+            },
+        )
+        .optional()?;
+    let Some((sender_id, avatar_url, message_chain, reactions, bridge, created_at)) = row else {
+        return Ok(None);
+    };
+    let mut statement = connection
+        .prepare_cached("SELECT platform, origin_id, endpoint FROM bridge_message_ref WHERE message_id = ?1 ORDER BY position")?;
+    let refs = statement
+        .query_map([id], |row| {
+            Ok(BridgeMessageRefPO {
+                platform: row.get(0)?,
+                origin_id: OriginMessageId::from_stored(&row.get::<_, String>(1)?),
+                endpoint: row.get(2)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(Some(BridgeMessagePO {
+        id: id.to_string(),
+        sender_id,
+        avatar_url,
+        refs,
+        message_chain: serde_json::from_str(&message_chain)?,
+        reactions: serde_json::from_str(&reactions)?,
+        bridge,
+        created_at,
+    }))
+}
+
+fn save_message(connection: &Connection, message: &BridgeMessagePO) -> anyhow::Result<()> {
+    connection.execute(
+        "INSERT INTO bridge_message (id, sender_id, avatar_url, message_chain, reactions, bridge, created_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+         ON CONFLICT (id) DO UPDATE SET sender_id = excluded.sender_id, avatar_url = excluded.avatar_url,
+         message_chain = excluded.message_chain, reactions = excluded.reactions, bridge = excluded.bridge, created_at = excluded.created_at",
+        params![
+            message.id,
+            message.sender_id,
+            message.avatar_url,
+            serde_json::to_string(&message.message_chain)?,
+            serde_json::to_string(&message.reactions)?,
+            message.bridge,
+            message.created_at
+        ],
+    )?;
+    connection.execute("DELETE FROM bridge_message_ref WHERE message_id = ?1", [&message.id])?;
+    for (position, refs) in message.refs.iter().enumerate() {
+        connection.execute(
+            "INSERT INTO bridge_message_ref (message_id, position, platform, origin_id, endpoint) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                message.id,
+                position as i64,
+                refs.platform,
+                refs.origin_id.to_string(),
+                refs.endpoint
+            ],
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bridge::MessageContent;
+
+    fn message(id: &str, refs: &[(&str, &str)]) -> BridgeMessagePO {
+        BridgeMessagePO {
+            id: id.to_string(),
+            sender_id: "sender".to_string(),
+            avatar_url: None,
+            refs: refs
+                .iter()
+                .map(|(platform, origin_id)| BridgeMessageRefPO {
+                    platform: platform.to_string(),
+                    origin_id: OriginMessageId::from_stored(origin_id),
+                    endpoint: None,
+                })
+                .collect(),
+            message_chain: vec![MessageContent::Plain { text: "hi".to_string() }],
+            reactions: vec![],
+            bridge: "bridge".to_string(),
+            created_at: id.parse().unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_message_storage() {
+        let mut storage = SqliteStorage::open(Connection::open_in_memory().unwrap()).unwrap();
+        MessageStorage::save(&mut storage, &message("1", &[("QQ", "|1|100|7|"), ("DC", "200")])).unwrap();
+        MessageStorage::save(&mut storage, &message("2", &[("QQ", "|1|1000|")])).unwrap();
+        let po = MessageStorage::get(&storage, "1").unwrap().unwrap();
+        assert_eq!(po.refs.len(), 2);
+        assert_eq!(po.refs[1].origin_id, OriginMessageId::Discord(200));
+
+        let found = MessageStorage::find_by_ref(&storage, "QQ", "|1|100|").unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].id, "1");
+        assert!(MessageStorage::find_by_ref(&storage, "DC", "|1|100|").unwrap().is_empty());
+        assert_eq!(MessageStorage::find_by_ref(&storage, "QQ", "").unwrap().len(), 2);
+
+        // 覆盖保存时更新关联
+        MessageStorage::save(&mut storage, &message("1", &[("QQ", "|1|100|7|")])).unwrap();
+        assert!(MessageStorage::find_by_ref(&storage, "DC", "200").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_prune() {
+        let mut storage = SqliteStorage::open(Connection::open_in_memory().unwrap()).unwrap();
+        for id in 1..=5 {
+            MessageStorage::save(&mut storage, &message(&id.to_string(), &[("QQ", &format!("|1|{id}|"))])).unwrap();
+        }
+        let policy = RetentionPolicy {
+            before: Some(2),
+            max_count: Some(3),
+        };
+        assert_eq!(storage.prune(&policy).unwrap(), 2);
+        assert!(MessageStorage::get(&storage, "2").unwrap().is_none());
+        assert!(MessageStorage::get(&storage, "3").unwrap().is_some());
+        assert!(MessageStorage::find_by_ref(&storage, "QQ", "|1|2|").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_user_storage() {
+        let mut storage = SqliteStorage::open(Connection::open_in_memory().unwrap()).unwrap();
+        let user = |id: &str, platform: &str, ref_id: Option<&str>| BridgeUser {
+            id: id.to_string(),
+            platform: platform.to_string(),
+            origin_id: format!("{platform}{id}"),
+            display_text: id.to_string(),
+            ref_id: ref_id.map(str::to_string),
+        };
+        UserStorage::save(&mut storage, &[user("a", "QQ", None), user("b", "DC", None)]).unwrap();
+        assert_eq!(storage.find_by_origin("QQa", "QQ").unwrap().unwrap().id, "a");
+        assert!(UserStorage::find_by_ref(&storage, "r", "DC").unwrap().is_none());
+
+        UserStorage::save(&mut storage, &[user("a", "QQ", Some("r")), user("b", "DC", Some("r"))]).unwrap();
+        assert_eq!(UserStorage::find_by_ref(&storage, "r", "DC").unwrap().unwrap().id, "b");
+        assert_eq!(UserStorage::get(&storage, "a").unwrap().unwrap().ref_id.as_deref(), Some("r"));
+    }
+}
@@ -0,0 +1,46 @@
+//! 测试用的临时数据路径和桥配置
+
+use std::path::PathBuf;
+
+use crate::bridge::{BridgeEvent, BridgeMessage, MessageContent};
+use crate::config::{BridgeConfig, DiscordBridgeConfig};
+
+/// 不重复的临时路径 `<临时目录>/bridge_<name>_<uuid>`, 用作数据目录
+pub fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("bridge_{name}_{}", uuid::Uuid::new_v4()))
+}
+
+/// 不重复的临时json数据文件
+pub fn temp_json(name: &str) -> PathBuf {
+    temp_path(name).with_extension("json")
+}
+
+/// 只有dc频道的桥配置
+pub fn bridge_config() -> BridgeConfig {
+    BridgeConfig {
+        name: "bridge".to_string(),
+        discord: DiscordBridgeConfig {
+            id: 1,
+            token: "token".to_string(),
+            channelId: 2,
+        },
+        qqGroup: 3,
+        tgGroup: 4,
+        tgTopic: None,
+        endpoints: vec![],
+        links: vec![],
+        filters: vec![],
+        enable: true,
+    }
+}
+
+/// 文本为桥消息id的桥消息事件
+pub fn message(id: &str) -> BridgeEvent {
+    BridgeEvent::Message(BridgeMessage {
+        id: id.to_string(),
+        sender_id: "sender".to_string(),
+        avatar_url: None,
+        bridge_config: bridge_config(),
+        message_chain: vec![MessageContent::Plain { text: id.to_string() }],
+    })
+}
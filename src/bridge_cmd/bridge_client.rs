@@ -1,18 +1,13 @@
 // TODO 交互式操作的上下文
 
-use clap::{FromArgMatches, Subcommand};
-use std::sync::Arc;
+use clap::FromArgMatches;
 
-use crate::{
-    bridge::{
-        manager::BRIDGE_USER_MANAGER,
-        pojo::{BridgeMessageRefPO, BridgeSendMessageForm},
-        BridgeClient, BridgeMessage, MessageContent,
-    },
-    elr,
+use crate::bridge::{
+    pojo::{BridgeMessageRefPO, BridgeSendMessageForm},
+    BridgeAdapter, BridgeMessage, MessageContent, OriginMessageId,
 };
 
-use super::{BridgeCommand, CommandCentext, CommandMessageParser};
+use super::{BridgeCommand, CmdAdapter, CommandCentext, CommandMessageParser};
 
 /// 识别解析以 BridgeMessage 为载体的指令
 impl CommandMessageParser<BridgeMessage> for BridgeMessage {
@@ -27,7 +22,7 @@ impl CommandMessageParser<BridgeMessage> for BridgeMessage {
             return Err("空消息；或前缀错误！");
         }
         let args = text.split_whitespace();
-        let patter = BridgeCommand::augment_subcommands(clap::Command::new("cc").no_binary_name(true));
+        let patter = super::command_parser();
         // let mat = elr!(patter.clone().try_get_matches_from(args) ;; return None);
         // let cmd = elr!(BridgeCommand::from_arg_matches(&mat) ;; return None);
         let Ok(mat) = patter.clone().try_get_matches_from(args) else {
@@ -47,41 +42,35 @@ impl CommandMessageParser<BridgeMessage> for BridgeMessage {
 
 /// 接收桥内消息，尝试处理
 #[tracing::instrument(skip_all)]
-pub async fn listen(bridge: Arc<BridgeClient>) {
-    let mut subs = bridge.sender.subscribe();
-    loop {
-        let message = elr!(subs.recv().await ;; continue);
-        // 匹配消息是否是命令
-        let cmd = match message.try_parse(&bridge.name) {
-            Ok(cmd) => cmd,
-            Err(e) => {
-                tracing::debug!("{e}");
-                continue;
-            }
-        };
-        tracing::info!("[指令] {:?}", cmd.token);
-        // 指令反馈
-        let feedback = match cmd.process_command().await {
-            Ok(fb) => fb,
-            Err(e) => {
-                tracing::error!("{e}");
-                continue;
-            }
-        };
-        let Some(user) = BRIDGE_USER_MANAGER.lock().await.like("00000001", "CMD").await else {
-            tracing::warn!("无法获取CMD用户！");
-            continue;
-        };
-        let bridge_msg = BridgeSendMessageForm {
-            origin_message: BridgeMessageRefPO {
-                origin_id: uuid::Uuid::new_v4().to_string(),
-                platform: "CMD".to_string(),
-            },
-            avatar_url: Some(format!("https://q1.qlogo.cn/g?b=qq&nk=3245538509&s=100")),
-            bridge_config: message.bridge_config.clone(),
-            message_chain: feedback,
-            sender_id: user.id,
-        };
-        bridge.send_message(bridge_msg).await
-    } // loop
+pub async fn process_message(adapter: &CmdAdapter, message: &BridgeMessage) {
+    // 匹配消息是否是命令
+    let cmd = match message.try_parse(&adapter.bridge().name) {
+        Ok(cmd) => cmd,
+        Err(e) => {
+            tracing::debug!("{e}");
+            return;
+        }
+    };
+    tracing::info!("[指令] {:?}", cmd.token);
+    // 指令反馈
+    let feedback = match cmd.process_command().await {
+        Ok(fb) => fb,
+        Err(e) => {
+            tracing::error!("{e}");
+            return;
+        }
+    };
+    let user = adapter.apply_cmd_user().await;
+    let bridge_msg = BridgeSendMessageForm {
+        origin_message: BridgeMessageRefPO {
+            origin_id: OriginMessageId::Raw(uuid::Uuid::new_v4().to_string()),
+            platform: adapter.platform().to_string(),
+            endpoint: None,
+        },
+        avatar_url: Some(format!("https://q1.qlogo.cn/g?b=qq&nk=3245538509&s=100")),
+        bridge_config: message.bridge_config.clone(),
+        message_chain: feedback,
+        sender_id: user.id,
+    };
+    adapter.send_to_bridge(bridge_msg).await
 }
@@ -1,5 +1,7 @@
-use crate::{bridge, Config};
-use clap::Parser;
+use crate::bridge::{BridgeAdapter, BridgeClient, BridgeClientPlatform, BridgeMessage, OriginMessageId};
+use crate::bridge;
+use async_trait::async_trait;
+use clap::{Parser, Subcommand};
 use std::sync::Arc;
 
 pub mod bridge_client;
@@ -28,6 +30,11 @@ pub enum BridgeCommand {
     },
 }
 
+/// 指令的clap解析器, 不带程序名
+pub fn command_parser() -> clap::Command {
+    BridgeCommand::augment_subcommands(clap::Command::new("cc").no_binary_name(true))
+}
+
 /// 指令内容
 pub struct CommandCentext<M> {
     /// 基础内容
@@ -51,24 +58,42 @@ pub trait CommandMessageParser<M> {
     fn try_parse(&self, client: &str) -> Result<CommandCentext<M>, &'static str>;
 }
 
-pub async fn start(_config: Arc<Config>, bridge: Arc<bridge::BridgeClient>) {
-    tracing::info!("[CMD] 初始化指令处理器");
-    apply_bridge_user().await;
-    bridge_client::listen(bridge.clone()).await;
+/// 指令处理器适配器
+pub struct CmdAdapter {
+    bridge: Arc<BridgeClient>,
+}
+
+impl CmdAdapter {
+    pub fn new(bridge: Arc<BridgeClient>) -> Self {
+        CmdAdapter { bridge }
+    }
+
+    /**
+     * 申请桥用户
+     */
+    pub async fn apply_cmd_user(&self) -> bridge::user::BridgeUser {
+        self.apply_bridge_user("00000001", "桥命令Bot").await
+    }
 }
 
-/**
- * 申请桥用户
- */
-pub async fn apply_bridge_user() -> bridge::user::BridgeUser {
-    let bridge_user = bridge::manager::BRIDGE_USER_MANAGER
-        .lock()
-        .await
-        .likeAndSave(bridge::pojo::BridgeUserSaveForm {
-            origin_id: "00000001".to_string(),
-            platform: "CMD".to_string(),
-            display_text: "桥命令Bot".to_string(),
-        })
-        .await;
-    bridge_user.unwrap()
+#[async_trait]
+impl BridgeAdapter for CmdAdapter {
+    fn platform(&self) -> BridgeClientPlatform {
+        BridgeClientPlatform::Cmd
+    }
+
+    fn bridge(&self) -> &Arc<BridgeClient> {
+        &self.bridge
+    }
+
+    async fn start(self: Arc<Self>) {
+        tracing::info!("[CMD] 初始化指令处理器");
+        self.apply_cmd_user().await;
+        bridge::adapter::sync_message(self.as_ref()).await;
+    }
+
+    async fn send_message(&self, message: &BridgeMessage) -> anyhow::Result<Option<OriginMessageId>> {
+        bridge_client::process_message(self, message).await;
+        Ok(None)
+    }
 }
@@ -1,9 +1,7 @@
 //! 定义桥的数据结构，读写方法
 
+use crate::bridge::storage::json_file;
 use crate::bridge::{BridgeClientPlatform as BCP, User};
-use std::fs::{create_dir_all, OpenOptions};
-use std::io::{Read, Write};
-use std::path::Path;
 use tracing::error;
 
 ///! 定义绑定映射
@@ -150,88 +148,21 @@ pub mod bind_map {
         }
     }
 
-    /// 初始化数据文件目录
-    /// # return
-    /// 检查与创建是否成功
-    fn init_dir() -> bool {
-        let dat_dir = Path::new(BIND_MAP_PATH).parent().unwrap();
-        if dat_dir.as_os_str().is_empty() || dat_dir.exists() {
-            return true;
-        }
-        if let Err(e) = create_dir_all(dat_dir) {
-            error!("目录'{}'创建失败！{:#?}", dat_dir.to_str().unwrap(), e);
-            return false;
-        }
-        true
-    }
-
     /// 读取，加载本地数据
     fn load() -> BindData {
-        if !init_dir() {
-            return BindData::new();
-        }
-        let mut json = String::new();
-        let file = OpenOptions::new()
-            .read(true)
-            .write(true)
-            .create(true)
-            .open(BIND_MAP_PATH);
-        match file {
-            Ok(mut f) => {
-                if let Err(e) = f.read_to_string(&mut json) {
-                    error!("Can not read file({}); {:#?}", BIND_MAP_PATH, e);
-                }
-            }
-            Err(e) => error!("Can not open/create data file({}); {:#?}", BIND_MAP_PATH, e),
-        };
-
-        if !json.is_empty() {
-            match serde_json::from_str::<BindData>(&json.as_str()) {
-                Ok(mut data) => {
-                    // 删除无平台映射
-                    data.retain(|((p1, ..), (p2, ..))| *p1 > 0 && *p2 > 0);
-                    return data;
-                }
-                Err(e) => error!("BindMap load fail, data can not be parsed; {:#?}", e),
-            }
-        }
-        BindData::new()
+        let mut data: BindData = json_file::load(BIND_MAP_PATH).unwrap_or_default();
+        // 删除无平台映射
+        data.retain(|((p1, ..), (p2, ..))| *p1 > 0 && *p2 > 0);
+        data
     }
 
     /// 数据写入本地
     /// TODO 异步读写
     fn save(data: &BindData) -> bool {
-        if !init_dir() {
+        if let Err(e) = json_file::save(BIND_MAP_PATH, data) {
+            error!("Can not write to file({}); {:#?}", BIND_MAP_PATH, e);
             return false;
         }
-        let raw: String;
-        match serde_json::to_string(data) {
-            Ok(json) => {
-                raw = json;
-            }
-            Err(e) => {
-                error!("Fail to parse BindMap to JSON; {:#?}", e);
-                return false;
-            }
-        }
-
-        let file = OpenOptions::new()
-            .truncate(true)
-            .write(true)
-            .create(true)
-            .open(BIND_MAP_PATH);
-        match file {
-            Ok(mut f) => {
-                if let Err(e) = f.write_all(raw.as_bytes()) {
-                    error!("Can not write to file({}); {:#?}", BIND_MAP_PATH, e);
-                    return false;
-                }
-            }
-            Err(e) => {
-                error!("Can not open/create data file({}); {:#?}", BIND_MAP_PATH, e);
-                return false;
-            }
-        };
         true
     }
 
@@ -1,166 +1,256 @@
 use std::path::Path;
-use std::sync::Arc;
 
 use proc_qq::re_exports::image;
 use serenity::builder::CreateButton;
 use serenity::http::Http;
 use serenity::model::application::component::ButtonStyle;
-use serenity::model::channel::AttachmentType;
+use serenity::model::channel::{AttachmentType, ReactionType};
+use serenity::model::id::{GuildId, MessageId};
 use serenity::model::webhook::Webhook;
 
-use crate::bridge;
+use crate::bridge::{self, avatar, BridgeAdapter, BridgeMessage, BridgeReaction, OriginMessageId};
 
-use super::{find_member_by_name, parse_text_mention_rule, to_reply_content, MentionText};
+use super::{find_member_by_name, parse_text_mention_rule, to_reply_content, webhook, DiscordAdapter, MentionText};
+
+/// 组装完成的dc消息
+struct DcContent<'a> {
+    content: Vec<String>,
+    reply_content: Vec<String>,
+    reply_message_id: String,
+    fils: Vec<AttachmentType<'a>>,
+}
+
+/**
+ * 获取桥配置的webhook和所属的服务器
+ * 没有配置webhook时使用自动创建的webhook, 见 [`webhook::get_webhook`]
+ */
+async fn get_webhook(http: &Http, message: &BridgeMessage) -> anyhow::Result<(Webhook, GuildId)> {
+    let webhook = webhook::get_webhook(http, &message.bridge_config.discord).await?;
+    tracing::debug!("discord info: {:#?}", webhook);
+    let Some(guild_id) = webhook.guild_id else {
+        return Err(anyhow::anyhow!("webhook 没有所属的服务器"));
+    };
+    Ok((webhook, guild_id))
+}
 
 #[tracing::instrument(name = "bridge_dc_sync", skip_all)]
-pub async fn listen(bridge: Arc<bridge::BridgeClient>, http: Arc<Http>) {
-    let mut subs = bridge.sender.subscribe();
-    loop {
-        let message = &subs.recv().await.unwrap();
-        tracing::info!("收到桥的消息, 同步到discord上");
-        let webhook = Webhook::from_id_with_token(
-            &http,
-            message.bridge_config.discord.id,
-            message.bridge_config.discord.token.as_str(),
-        )
+pub async fn send_message(adapter: &DiscordAdapter, http: &Http, message: &BridgeMessage) -> anyhow::Result<Option<OriginMessageId>> {
+    tracing::info!("收到桥的消息, 同步到discord上");
+    let (webhook, guild_id) = get_webhook(http, message).await?;
+    let DcContent {
+        mut content,
+        mut reply_content,
+        reply_message_id,
+        fils,
+    } = to_dc_content(adapter, http, guild_id, message).await;
+    let bridge_user = bridge::manager::BRIDGE_USER_MANAGER
+        .lock()
+        .await
+        .get(&message.sender_id)
         .await
         .unwrap();
-        tracing::debug!("discord info: {:#?}", webhook);
-        let guild_id = webhook.guild_id.unwrap();
+    // 缓存的头像需要转为公开url
+    let avatar_url = message
+        .avatar_url
+        .as_deref()
+        .and_then(|url| avatar::to_public_url(url, adapter.config.current().avatar_base_url.as_deref()));
+    let resp = webhook
+        .execute(http, true, |w| {
+            // 配置发送者头像
+            if let Some(url) = &avatar_url {
+                w.avatar_url(url.as_str());
+            }
+            tracing::debug!("消息头像url：{:?}", message.avatar_url);
+            // 配置发送者用户名
+            w.username(bridge_user.to_string());
+            if content.len() == 0 && fils.len() == 0 {
+                content.push("{本次发送的消息没有内容}".to_string());
+            }
+            // w.components(|c| c.add_action_row());
+            w.add_files(fils);
+            reply_content.append(&mut content);
+            w.content(reply_content.join(""));
+            if reply_content.len() > 0 {
+                w.components(|c| {
+                    c.create_action_row(|row| {
+                        let mut button = CreateButton::default();
+                        button.style(ButtonStyle::Link);
+                        button.url(format!(
+                            "https://discord.com/channels/{}/{}/{}",
+                            guild_id, message.bridge_config.discord.channelId, reply_message_id
+                        ));
+                        button.label("跳转回复");
+                        row.add_button(button)
+                    })
+                });
+            }
+            tracing::debug!("add_button: {:?}", w);
+            // w.content(content.join(""));
+            // .content(content.join("")).components(f).content(content.join(""))
+            w
+        })
+        .await;
+    if resp.is_err() {
+        // webhook可能已失效, 下次重新获取
+        webhook::forget(message.bridge_config.discord.channelId).await;
+    }
+
+    match resp? {
+        Some(msg) => {
+            tracing::info!("已同步消息");
+            Ok(Some(OriginMessageId::Discord(msg.id.0)))
+        }
+        None => {
+            tracing::error!("同步的消息没有返回消息id");
+            Ok(None)
+        }
+    }
+}
+
+#[tracing::instrument(name = "bridge_dc_edit", skip_all)]
+pub async fn edit_message(
+    adapter: &DiscordAdapter,
+    http: &Http,
+    message: &BridgeMessage,
+    origin_id: &OriginMessageId,
+) -> anyhow::Result<()> {
+    tracing::info!("收到桥的编辑, 同步到discord上");
+    let (webhook, guild_id) = get_webhook(http, message).await?;
+    let DcContent {
+        content,
+        mut reply_content,
+        ..
+    } = to_dc_content(adapter, http, guild_id, message).await;
+    reply_content.extend(content);
+    let message_id = to_message_id(origin_id)?;
+    webhook
+        .edit_message(http, message_id, |m| m.content(reply_content.join("")))
+        .await?;
+    Ok(())
+}
+
+#[tracing::instrument(name = "bridge_dc_recall", skip_all)]
+pub async fn recall_message(http: &Http, message: &BridgeMessage, origin_id: &OriginMessageId) -> anyhow::Result<()> {
+    tracing::info!("收到桥的撤回, 删除discord上的消息");
+    let (webhook, _) = get_webhook(http, message).await?;
+    let message_id = to_message_id(origin_id)?;
+    webhook.delete_message(http, message_id).await?;
+    Ok(())
+}
+
+/**
+ * 其它平台的回应由bot在dc消息上回应同样的表情, 该表情的回应都取消后bot再取消回应
+ */
+#[tracing::instrument(name = "bridge_dc_reaction", skip_all)]
+pub async fn react_message(
+    http: &Http,
+    message: &BridgeMessage,
+    origin_id: &OriginMessageId,
+    reaction: &BridgeReaction,
+    added: bool,
+    reactions: &[BridgeReaction],
+) -> anyhow::Result<()> {
+    let platform = bridge::BridgeClientPlatform::Discord.to_string();
+    let channel_id = message.bridge_config.discord.channelId;
+    let message_id = to_message_id(origin_id)?.0;
+    let reaction_type = ReactionType::try_from(reaction.emoji.as_str())?;
+    if added {
+        http.create_reaction(channel_id, message_id, &reaction_type).await?;
+        return Ok(());
+    }
+    if reactions.iter().any(|r| r.emoji == reaction.emoji && r.platform != platform) {
+        return Ok(());
+    }
+    http.delete_reaction(channel_id, message_id, None, &reaction_type).await?;
+    Ok(())
+}
+
+/// 关联中记录的dc消息id
+fn to_message_id(origin_id: &OriginMessageId) -> anyhow::Result<MessageId> {
+    match origin_id {
+        OriginMessageId::Discord(id) => Ok(MessageId(*id)),
+        _ => Err(anyhow::anyhow!("不是DC消息id: {origin_id}")),
+    }
+}
 
-        // 组装dc消息
-        let mut content: Vec<String> = Vec::new();
-        let mut reply_content: Vec<String> = Vec::new();
-        let mut reply_message_id = "".to_string();
-        let mut fils: Vec<AttachmentType> = Vec::new();
-        for chain in &message.message_chain {
-            match chain {
-                bridge::MessageContent::Plain { text } => {
-                    let mention_text_list = parse_text_mention_rule(text.to_string());
-                    for mention_text in mention_text_list {
-                        match mention_text {
-                            MentionText::Text(text) => content.push(text),
-                            MentionText::MentionText { name, discriminator } => {
-                                let member = find_member_by_name(&http, guild_id.0, &name, &discriminator).await;
-                                if let Some(member) = member {
-                                    content.push(format!("<@{}>", member.user.id.0));
-                                } else {
-                                    content.push(format!("@[DC] {}#{}", name, discriminator));
-                                }
+/**
+ * 桥消息链转dc消息
+ */
+async fn to_dc_content<'a>(adapter: &DiscordAdapter, http: &Http, guild_id: GuildId, message: &'a BridgeMessage) -> DcContent<'a> {
+    // 组装dc消息
+    let mut content: Vec<String> = Vec::new();
+    let mut reply_content: Vec<String> = Vec::new();
+    let mut reply_message_id = "".to_string();
+    let mut fils: Vec<AttachmentType> = Vec::new();
+    for chain in &message.message_chain {
+        match chain {
+            bridge::MessageContent::Plain { text } => {
+                let mention_text_list = parse_text_mention_rule(text.to_string());
+                for mention_text in mention_text_list {
+                    match mention_text {
+                        MentionText::Text(text) => content.push(text),
+                        MentionText::MentionText { name, discriminator } => {
+                            let member = find_member_by_name(http, guild_id.0, &name, discriminator.as_deref()).await;
+                            if let Some(member) = member {
+                                content.push(format!("<@{}>", member.id));
+                            } else if let Some(discriminator) = discriminator {
+                                content.push(format!("@[DC] {}#{}", name, discriminator));
+                            } else {
+                                content.push(format!("@[DC] {}", name));
                             }
                         }
                     }
                 }
-                bridge::MessageContent::Image(image) => match image {
-                    bridge::Image::Url(url) => fils.push(AttachmentType::Image(url::Url::parse(url).unwrap())),
-                    bridge::Image::Path(path) => fils.push(AttachmentType::Path(Path::new(path))),
-                    bridge::Image::Buff(data) => {
-                        match image::guess_format(data) {
-                            Ok(format) => fils.push(AttachmentType::Bytes {
-                                data: data.into(),
-                                filename: format!("file.{}", format.extensions_str()[0]),
-                            }),
-                            Err(_) => {}
-                        };
-                    }
-                },
-                bridge::MessageContent::Reply { id } => {
-                    if let Some(id) = id {
-                        let reply_message = bridge::manager::BRIDGE_MESSAGE_MANAGER.lock().await.get(id).await;
-                        if let Some(reply_message) = reply_message {
-                            let refs = reply_message.refs.iter().find(|refs| refs.platform.eq("DC"));
-                            if let Some(refs) = refs {
-                                reply_message_id = refs.origin_id.clone();
-                            }
-                            reply_content = to_reply_content(reply_message).await;
-                        } else {
-                            content.push("> {回复消息}\n".to_string());
-                        }
-                    }
+            }
+            bridge::MessageContent::Image(image) => match image {
+                bridge::Image::Url(url) => fils.push(AttachmentType::Image(url::Url::parse(url).unwrap())),
+                bridge::Image::Path(path) => fils.push(AttachmentType::Path(Path::new(path))),
+                bridge::Image::Buff(data) => {
+                    match image::guess_format(data) {
+                        Ok(format) => fils.push(AttachmentType::Bytes {
+                            data: data.into(),
+                            filename: format!("file.{}", format.extensions_str()[0]),
+                        }),
+                        Err(_) => {}
+                    };
                 }
-                bridge::MessageContent::At { id } => {
-                    let bridge_user = bridge::manager::BRIDGE_USER_MANAGER.lock().await.get(id).await;
-                    if let None = bridge_user {
-                        content.push(format!("@[UN] {}", id));
-                        continue;
-                    }
-                    let bridge_user = bridge_user.unwrap();
-                    // 查看桥关联的本平台用户id
-                    if let Some(ref_user) = bridge_user.find_by_platform("DC").await {
-                        content.push(format!("<@{}>", ref_user.origin_id));
-                        continue;
+            },
+            bridge::MessageContent::Reply { id } => {
+                if let Some(id) = id {
+                    let reply_message = bridge::manager::BRIDGE_MESSAGE_MANAGER.lock().await.get(id).await;
+                    if let Some(reply_message) = reply_message {
+                        if let Some(origin_id) = adapter.find_origin_id(&reply_message, &message.bridge_config) {
+                            reply_message_id = origin_id.to_string();
+                        }
+                        reply_content = to_reply_content(reply_message).await;
+                    } else {
+                        content.push("> {回复消息}\n".to_string());
                     }
-                    // 没有关联账号用标准格式发送消息
-                    content.push(format!("@{}", bridge_user.to_string()));
-                }
-                _ => tracing::warn!(unit = ?chain, "无法识别的MessageChain"),
-            };
-        }
-        tracing::debug!(?content, ?fils, "桥内消息链组装完成");
-        let bridge_user = bridge::manager::BRIDGE_USER_MANAGER
-            .lock()
-            .await
-            .get(&message.sender_id)
-            .await
-            .unwrap();
-        let resp = webhook
-            .execute(&http, true, |w| {
-                // 配置发送者头像
-                if let Some(url) = &message.avatar_url {
-                    w.avatar_url(url.as_str());
-                }
-                tracing::debug!("消息头像url：{:?}", message.avatar_url);
-                // 配置发送者用户名
-                w.username(bridge_user.to_string());
-                if content.len() == 0 && fils.len() == 0 {
-                    content.push("{本次发送的消息没有内容}".to_string());
                 }
-                // w.components(|c| c.add_action_row());
-                w.add_files(fils);
-                reply_content.append(&mut content);
-                w.content(reply_content.join(""));
-                if reply_content.len() > 0 {
-                    w.components(|c| {
-                        c.create_action_row(|row| {
-                            let mut button = CreateButton::default();
-                            button.style(ButtonStyle::Link);
-                            button.url(format!(
-                                "https://discord.com/channels/{}/{}/{}",
-                                guild_id, message.bridge_config.discord.channelId, reply_message_id
-                            ));
-                            button.label("跳转回复");
-                            row.add_button(button)
-                        })
-                    });
+            }
+            bridge::MessageContent::At { id } => {
+                let bridge_user = bridge::manager::BRIDGE_USER_MANAGER.lock().await.get(id).await;
+                if let None = bridge_user {
+                    content.push(format!("@[UN] {}", id));
+                    continue;
                 }
-                println!("add_button: {:?}", w);
-                // w.content(content.join(""));
-                // .content(content.join("")).components(f).content(content.join(""))
-                w
-            })
-            .await;
-
-        match resp {
-            Ok(result) => {
-                if let Some(msg) = result {
-                    // 发送成功后, 将平台消息和桥消息进行关联, 为以后进行回复功能
-                    bridge::manager::BRIDGE_MESSAGE_MANAGER
-                        .lock()
-                        .await
-                        .ref_bridge_message(bridge::pojo::BridgeMessageRefMessageForm {
-                            bridge_message_id: message.id.clone(),
-                            platform: "DC".to_string(),
-                            origin_id: msg.id.0.to_string(),
-                        })
-                        .await;
-                } else {
-                    tracing::error!("同步的消息没有返回消息id")
+                let bridge_user = bridge_user.unwrap();
+                // 查看桥关联的本平台用户id
+                if let Some(ref_user) = adapter.find_ref_user(&bridge_user).await {
+                    content.push(format!("<@{}>", ref_user.origin_id));
+                    continue;
                 }
-                tracing::info!("已同步消息")
-            }
-            Err(err) => {
-                tracing::error!(?err, "消息同步失败！")
+                // 没有关联账号用标准格式发送消息
+                content.push(format!("@{}", bridge_user.to_string()));
             }
-        }
+            _ => tracing::warn!(unit = ?chain, "无法识别的MessageChain"),
+        };
+    }
+    tracing::debug!(?content, ?fils, "桥内消息链组装完成");
+    DcContent {
+        content,
+        reply_content,
+        reply_message_id,
+        fils,
     }
 }
@@ -1,105 +1,76 @@
 use std::sync::Arc;
 
 use serenity::async_trait;
+use serenity::model::application::interaction::Interaction;
 use serenity::model::channel::Message;
 use serenity::model::channel::MessageReference;
+use serenity::model::channel::Reaction;
+use serenity::model::event::{GuildMemberUpdateEvent, MessageUpdateEvent};
 use serenity::model::gateway::Ready;
+use serenity::model::guild::{Guild, Member};
+use serenity::model::id::{ChannelId, GuildId, MessageId};
+use serenity::model::user::User;
 use serenity::model::Timestamp;
 use serenity::prelude::*;
-use tracing::{debug, error, info, instrument, trace};
+use tracing::{debug, error, info, instrument, trace, warn};
 
-use crate::bridge::Image;
-use crate::bridge_dc::apply_bridge_user;
-use crate::{bridge, Config};
+use crate::bridge;
+use crate::bridge::{BridgeAdapter, Image, OriginMessageId};
+use crate::bridge_dc::member_cache::{self, DC_MEMBERS};
+use crate::bridge_dc::{slash_command, webhook, DcMember, DiscordAdapter};
+use crate::config::{EndpointTarget, SharedConfig};
 
 pub struct Handler {
-    pub config: Arc<Config>,
-    pub bridge: Arc<bridge::BridgeClient>,
+    pub config: Arc<SharedConfig>,
+    pub adapter: Arc<DiscordAdapter>,
 }
 
 #[async_trait]
 impl EventHandler for Handler {
     #[instrument(skip_all, name = "bridge_dc_recv")]
     async fn message(&self, ctx: Context, msg: Message) {
-        if msg.author.id == self.config.discord_config.botId {
+        let config = self.config.current();
+        if msg.author.id == config.discord_config.botId {
             // 收到自己bot的消息, 不要继续以免消息循环
             return;
         }
-        if filter_message(&msg) {
-            return;
-        }
 
         // 收到桥配置的webhook消息, 不要继续以免消息循环
-        if self.config.bridges.iter().any(|bridge| msg.author.id == bridge.discord.id) {
+        if webhook::is_bridge_webhook(&config, msg.author.id.0).await {
             return;
         }
-        let bridge_config = match self
-            .config
-            .bridges
-            .iter()
-            .find(|bridge| msg.channel_id == bridge.discord.channelId && bridge.enable)
-        {
+        let bridge_config = match config.find_by_discord_channel(msg.channel_id.0) {
             Some(c) => c,
             // 该消息的频道没有配置桥, 忽略这个消息
             None => return,
         };
-        let bridge_user = apply_bridge_user(msg.author.id.0, msg.author.name.as_str(), msg.author.discriminator).await;
+        let author = member_cache::of_user(msg.guild_id.map(|guild_id| guild_id.0), &msg.author).await;
+        let bridge_user = match self.adapter.apply_dc_user(&author).await {
+            Ok(bridge_user) => bridge_user,
+            Err(err) => {
+                error!(?err, "[DC] 消息同步失败");
+                return;
+            }
+        };
         let mut bridge_message = bridge::pojo::BridgeSendMessageForm {
             sender_id: bridge_user.id,
             avatar_url: None,
-            bridge_config: bridge_config.clone(),
+            bridge_config,
             message_chain: Vec::new(),
             origin_message: bridge::pojo::BridgeMessageRefPO {
-                origin_id: msg.id.0.to_string(),
-                platform: "DC".to_string(),
+                origin_id: OriginMessageId::Discord(msg.id.0),
+                platform: self.adapter.platform().to_string(),
+                endpoint: None,
             },
         };
         if let Some(url) = msg.author.avatar_url() {
             bridge_message.avatar_url = Some(url.replace(".webp?size=1024", ".png?size=40").to_string());
         }
         if let Some(reply) = msg.message_reference {
-            bridge_message.message_chain.push(to_reply_bridge_message(reply).await);
-        }
-        let result = crate::utils::parser_message(&msg.content).await;
-        for ast in result {
-            match ast {
-                crate::utils::MarkdownAst::Plain { text } => {
-                    bridge_message.message_chain.push(bridge::MessageContent::Plain { text });
-                }
-                crate::utils::MarkdownAst::At { username } => {
-                    trace!("用户'{}'收到@", username);
-                    bridge_message.message_chain.push(bridge::MessageContent::Plain { text: username });
-                    // bridge_message
-                    //     .message_chain
-                    //     .push(bridge::MessageContent::At {
-                    //         bridge_user_id: None,
-                    //         username,
-                    //     });
-                }
-                crate::utils::MarkdownAst::DiscordAtUser { id } => {
-                    let id: u64 = id.parse::<u64>().unwrap();
-                    let member = ctx.http.get_member(msg.guild_id.unwrap().0, id).await.unwrap();
-                    let bridge_user = apply_bridge_user(id, member.user.name.as_str(), member.user.discriminator).await;
-                    // let member_name =
-                    //     format!("[DC] {}#{}", member.user.name, member.user.discriminator);
-                    // trace!("用户'{}'收到@", member_name);
-                    bridge_message.message_chain.push(bridge::MessageContent::At { id: bridge_user.id });
-                }
-                crate::utils::MarkdownAst::DiscordAtEveryone {} => {
-                    bridge_message.message_chain.push(bridge::MessageContent::AtAll);
-                }
-                crate::utils::MarkdownAst::DiscordAtHere {} => {
-                    bridge_message.message_chain.push(bridge::MessageContent::AtAll);
-                }
-                crate::utils::MarkdownAst::DiscordEmoji { id, animated, .. } => {
-                    let suffix = if animated { "gif" } else { "png" };
-                    bridge_message.message_chain.push(bridge::MessageContent::Image(Image::Url(format!(
-                        "https://cdn.discordapp.com/emojis/{}.{}",
-                        id, suffix
-                    ))));
-                }
-            }
+            bridge_message.message_chain.push(self.to_reply_bridge_message(reply).await);
         }
+        let mut message_chain = self.to_bridge_chain(&ctx, msg.guild_id, &msg.content).await;
+        bridge_message.message_chain.append(&mut message_chain);
         // 将附件一股脑的放进图片里面 TODO: 以后在区分非图片的附件
         for attachment in msg.attachments {
             trace!(attachment.url);
@@ -109,7 +80,7 @@ impl EventHandler for Handler {
         }
         debug!("dc 桥的消息链：{:#?}", bridge_message.message_chain);
 
-        self.bridge.send_message(bridge_message).await;
+        self.adapter.send_to_bridge(bridge_message).await;
         if msg.content == "!hello" {
             // The create message builder allows you to easily create embeds and messages
             // using a builder syntax.
@@ -143,11 +114,103 @@ impl EventHandler for Handler {
         }
     }
 
+    #[instrument(skip_all, name = "bridge_dc_edit")]
+    async fn message_update(&self, ctx: Context, event: MessageUpdateEvent) {
+        // 只处理内容的编辑, 嵌入内容等的更新没有 content
+        let Some(content) = event.content else {
+            return;
+        };
+        let config = self.config.current();
+        if let Some(author) = &event.author {
+            if author.id == config.discord_config.botId || webhook::is_bridge_webhook(&config, author.id.0).await {
+                return;
+            }
+        }
+        let Some(bridge_config) = config.find_by_discord_channel(event.channel_id.0) else {
+            return;
+        };
+        let message_chain = self.to_bridge_chain(&ctx, event.guild_id, &content).await;
+        debug!("dc 桥的编辑消息链：{:#?}", message_chain);
+        self.adapter
+            .send_edit_to_bridge(&OriginMessageId::Discord(event.id.0), &bridge_config, message_chain)
+            .await;
+    }
+
+    #[instrument(skip_all, name = "bridge_dc_delete")]
+    async fn message_delete(&self, _ctx: Context, channel_id: ChannelId, deleted_message_id: MessageId, _guild_id: Option<GuildId>) {
+        self.recall(channel_id, deleted_message_id).await;
+    }
+
+    #[instrument(skip_all, name = "bridge_dc_delete")]
+    async fn message_delete_bulk(
+        &self,
+        _ctx: Context,
+        channel_id: ChannelId,
+        deleted_message_ids: Vec<MessageId>,
+        _guild_id: Option<GuildId>,
+    ) {
+        for message_id in deleted_message_ids {
+            self.recall(channel_id, message_id).await;
+        }
+    }
+
+    #[instrument(skip_all, name = "bridge_dc_reaction")]
+    async fn reaction_add(&self, ctx: Context, reaction: Reaction) {
+        self.react(&ctx, reaction, true).await;
+    }
+
+    #[instrument(skip_all, name = "bridge_dc_reaction")]
+    async fn reaction_remove(&self, ctx: Context, reaction: Reaction) {
+        self.react(&ctx, reaction, false).await;
+    }
+
+    async fn guild_create(&self, ctx: Context, guild: Guild) {
+        if let Err(err) = member_cache::load_guild(&ctx.http, guild.id.0).await {
+            warn!(?err, "[DC] 服务器 {} 的成员拉取失败", guild.id);
+        }
+    }
+
+    async fn guild_member_addition(&self, ctx: Context, new_member: Member) {
+        member_cache::refresh(&ctx.http, new_member.guild_id.0, (&new_member).into()).await;
+    }
+
+    async fn guild_member_update(&self, ctx: Context, event: GuildMemberUpdateEvent) {
+        let member = DcMember::new(&event.user, event.nick);
+        member_cache::refresh(&ctx.http, event.guild_id.0, member).await;
+    }
+
+    async fn guild_member_removal(&self, _ctx: Context, guild_id: GuildId, kicked: User) {
+        DC_MEMBERS.lock().await.remove(guild_id.0, kicked.id.0);
+    }
+
+    /// 频道的webhook被创建、修改或删除
+    async fn webhook_update(&self, _ctx: Context, _guild_id: GuildId, belongs_to_channel_id: ChannelId) {
+        webhook::forget(belongs_to_channel_id.0).await;
+    }
+
+    /// 斜杠指令, 见 [`slash_command`]
+    #[instrument(skip_all, name = "bridge_dc_command")]
+    async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
+        if let Interaction::ApplicationCommand(command) = interaction {
+            slash_command::handle(&self.adapter, &ctx, command).await;
+        }
+    }
+
     #[instrument(skip_all, target = "bridge_dc")]
     async fn ready(&self, ctx: Context, ready: Ready) {
         tracing::warn!("(Guild={:?})准备连接Discord伺服器", ready.guilds);
-        for bridge_config in self.config.bridges.iter() {
-            match ctx.http.get_channel(bridge_config.discord.channelId).await {
+        slash_command::register(&ctx.http).await;
+        let config = self.config.current();
+        let channels = config
+            .bridges
+            .iter()
+            .flat_map(|bridge| bridge.endpoints.iter())
+            .filter_map(|endpoint| match &endpoint.target {
+                EndpointTarget::Discord { discord } => Some(discord.channelId),
+                _ => None,
+            });
+        for channel_id in channels {
+            match ctx.http.get_channel(channel_id).await {
                 Ok(channel) => {
                     let msg = "Message Bridge正在运行中...";
                     let resp = channel
@@ -160,11 +223,11 @@ impl EventHandler for Handler {
                     if let Err(e) = resp {
                         error!(msg, err = ?e, "消息发送失败！")
                     } else {
-                        info!("已连接到 discord 频道 {}", bridge_config.discord.channelId);
+                        info!("已连接到 discord 频道 {}", channel_id);
                     }
                 }
                 Err(e) => error!(
-                    channel = bridge_config.discord.channelId,
+                    channel = channel_id,
                     err = ?e,
                     "获取 discord 频道失败！",
                 ),
@@ -173,46 +236,131 @@ impl EventHandler for Handler {
     }
 }
 
-/**
- * DC的回复消息处理成桥的回复消息
- */
-async fn to_reply_bridge_message(reply: MessageReference) -> bridge::MessageContent {
-    use bridge::MessageContent;
-    if let None = reply.message_id {
-        return MessageContent::Err {
-            message: "回复一条DC消息, 但是DC没有提供消息id, 同步回复消息失败".to_string(),
+impl Handler {
+    /**
+     * DC的消息被删除, 撤回其它桥的消息
+     */
+    async fn recall(&self, channel_id: ChannelId, message_id: MessageId) {
+        let Some(bridge_config) = self.config.current().find_by_discord_channel(channel_id.0) else {
+            return;
         };
+        self.adapter
+            .send_recall_to_bridge(&OriginMessageId::Discord(message_id.0), &bridge_config)
+            .await;
     }
-    let message_id = reply.message_id.unwrap().0.to_string();
-    let result = bridge::manager::BRIDGE_MESSAGE_MANAGER
-        .lock()
-        .await
-        .find_by_ref_and_platform(&message_id, "DC")
-        .await;
-    let result = result.unwrap();
-    if let Some(reply) = result {
-        return MessageContent::Reply {
-            id: Some(reply.id.clone()),
+
+    /**
+     * DC的消息收到或取消回应, 同步到其它桥
+     */
+    async fn react(&self, ctx: &Context, reaction: Reaction, added: bool) {
+        let Some(user_id) = reaction.user_id else {
+            return;
         };
+        // bot自己的回应是从其它桥同步过来的, 不要继续以免循环
+        let config = self.config.current();
+        if user_id == config.discord_config.botId {
+            return;
+        }
+        let Some(bridge_config) = config.find_by_discord_channel(reaction.channel_id.0) else {
+            return;
+        };
+        let user = match reaction.user(ctx).await {
+            Ok(user) => user,
+            Err(err) => {
+                warn!(?err, "获取回应的dc用户失败");
+                return;
+            }
+        };
+        let user = member_cache::of_user(reaction.guild_id.map(|guild_id| guild_id.0), &user).await;
+        let bridge_user = match self.adapter.apply_dc_user(&user).await {
+            Ok(bridge_user) => bridge_user,
+            Err(err) => {
+                error!(?err, "[DC] 回应同步失败");
+                return;
+            }
+        };
+        self.adapter
+            .send_reaction_to_bridge(
+                &OriginMessageId::Discord(reaction.message_id.0),
+                &bridge_config,
+                reaction.emoji.to_string(),
+                bridge_user.id,
+                added,
+            )
+            .await;
     }
-    MessageContent::Err {
-        message: "回复一条DC消息, 但是同步回复消息失败".to_string(),
-    }
-}
 
-/**
- * 过滤不同步的消息
- * @return true: 不同步
- * TODO: 从配置文件读取
- */
-fn filter_message(msg: &Message) -> bool {
-    let mut regex_config: Vec<String> = vec![];
-    regex_config.push("Issue labeled by .*".to_string());
-    for regex_str in regex_config {
-        let regex = regex::Regex::new(regex_str.as_str()).unwrap();
-        if regex.is_match(msg.content.as_str()) {
-            return true;
+    /**
+     * DC的消息文本处理成桥的消息链
+     */
+    async fn to_bridge_chain(&self, ctx: &Context, guild_id: Option<GuildId>, content: &str) -> bridge::MessageChain {
+        let mut message_chain = Vec::new();
+        let result = crate::utils::parser_message(content);
+        for ast in result {
+            match ast {
+                crate::utils::MarkdownAst::Plain { text } => {
+                    message_chain.push(bridge::MessageContent::Plain { text });
+                }
+                crate::utils::MarkdownAst::At { username } => {
+                    trace!("用户'{}'收到@", username);
+                    message_chain.push(bridge::MessageContent::Plain { text: username });
+                    // bridge_message
+                    //     .message_chain
+                    //     .push(bridge::MessageContent::At {
+                    //         bridge_user_id: None,
+                    //         username,
+                    //     });
+                }
+                crate::utils::MarkdownAst::DiscordAtUser { id } => {
+                    // id超出u64或者私聊没有服务器时, 保留原文
+                    let (Ok(user_id), Some(guild_id)) = (id.parse::<u64>(), guild_id) else {
+                        message_chain.push(bridge::MessageContent::Plain { text: format!("<@{id}>") });
+                        continue;
+                    };
+                    let Some(member) = member_cache::get_member(&ctx.http, guild_id.0, user_id).await else {
+                        message_chain.push(bridge::MessageContent::Plain { text: format!("<@{id}>") });
+                        continue;
+                    };
+                    let bridge_user = match self.adapter.apply_dc_user(&member).await {
+                        Ok(bridge_user) => bridge_user,
+                        Err(err) => {
+                            warn!(?err, "[DC] 提及的用户 {id} 同步失败");
+                            message_chain.push(bridge::MessageContent::Plain { text: format!("<@{id}>") });
+                            continue;
+                        }
+                    };
+                    // let member_name =
+                    //     format!("[DC] {}#{}", member.user.name, member.user.discriminator);
+                    // trace!("用户'{}'收到@", member_name);
+                    message_chain.push(bridge::MessageContent::At { id: bridge_user.id });
+                }
+                crate::utils::MarkdownAst::DiscordAtEveryone {} => {
+                    message_chain.push(bridge::MessageContent::AtAll);
+                }
+                crate::utils::MarkdownAst::DiscordAtHere {} => {
+                    message_chain.push(bridge::MessageContent::AtAll);
+                }
+                crate::utils::MarkdownAst::DiscordEmoji { id, animated, .. } => {
+                    let suffix = if animated { "gif" } else { "png" };
+                    message_chain.push(bridge::MessageContent::Image(Image::Url(format!(
+                        "https://cdn.discordapp.com/emojis/{}.{}",
+                        id, suffix
+                    ))));
+                }
+            }
         }
+        message_chain
+    }
+
+    /**
+     * DC的回复消息处理成桥的回复消息
+     */
+    async fn to_reply_bridge_message(&self, reply: MessageReference) -> bridge::MessageContent {
+        let Some(message_id) = reply.message_id else {
+            return bridge::MessageContent::Err {
+                message: "回复一条DC消息, 但是DC没有提供消息id, 同步回复消息失败".to_string(),
+            };
+        };
+        self.adapter.to_bridge_reply(&OriginMessageId::Discord(message_id.0)).await
     }
-    false
 }
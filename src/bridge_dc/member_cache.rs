@@ -0,0 +1,363 @@
+//! dc成员缓存
+//!
+//! 连接服务器时(`guild_create`)分页拉取全部成员, 之后由 `guild_member_add/update/remove` 事件更新；
+//! 桥消息中的 `@[DC] 用户名` 和dc消息中的 `<@id>` 直接查询缓存, 不再每条消息请求成员列表
+//!
+//! serenity 0.11 的 `User` 没有全局显示名(`global_name`), 成员接口直接解析原始json；
+//! 网关事件中的成员没有全局显示名, 沿用缓存中的全局显示名, 缓存中没有时才请求该成员 (全局显示名的修改在重新连接服务器时更新)
+
+use lazy_static::lazy_static;
+use serde::Deserialize;
+use serenity::http::request::{Request, RequestBuilder};
+use serenity::http::routing::RouteInfo;
+use serenity::http::Http;
+use serenity::model::guild::Member;
+use serenity::model::user::User;
+use std::collections::HashMap;
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+
+/// 每页拉取的成员数, dc接口的上限
+const PAGE_SIZE: u64 = 1000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DcMember {
+    pub id: u64,
+    /// 用户名, 迁移到新用户名的账号是唯一的小写用户名
+    pub name: String,
+    /// 迁移到新用户名的账号为0
+    pub discriminator: u16,
+    /// 全局显示名
+    pub global_name: Option<String>,
+    /// 服务器昵称
+    pub nick: Option<String>,
+}
+
+impl DcMember {
+    pub fn new(user: &User, nick: Option<String>) -> Self {
+        DcMember {
+            id: user.id.0,
+            name: user.name.clone(),
+            discriminator: user.discriminator,
+            global_name: None,
+            nick,
+        }
+    }
+
+    /// 桥上显示的名称, 见 [`super::to_display_text`]
+    pub fn display_text(&self) -> String {
+        super::to_display_text(&self.name, self.discriminator, self.global_name.as_deref())
+    }
+}
+
+impl From<&Member> for DcMember {
+    fn from(member: &Member) -> Self {
+        DcMember::new(&member.user, member.nick.clone())
+    }
+}
+
+/// 成员接口返回的原始json
+#[derive(Deserialize)]
+struct RawMember {
+    nick: Option<String>,
+    user: RawUser,
+}
+
+#[derive(Deserialize)]
+struct RawUser {
+    id: String,
+    username: String,
+    discriminator: String,
+    global_name: Option<String>,
+}
+
+impl TryFrom<RawMember> for DcMember {
+    type Error = anyhow::Error;
+
+    fn try_from(member: RawMember) -> anyhow::Result<Self> {
+        let user = member.user;
+        Ok(DcMember {
+            id: user.id.parse()?,
+            name: user.username,
+            discriminator: user.discriminator.parse()?,
+            global_name: user.global_name,
+            nick: member.nick,
+        })
+    }
+}
+
+/// 一个服务器的成员
+#[derive(Default)]
+struct GuildMembers {
+    members: HashMap<u64, DcMember>,
+    /// (名称, discriminator) -> 成员id
+    names: HashMap<(String, u16), u64>,
+}
+
+#[derive(Default)]
+pub struct MemberCache {
+    guilds: HashMap<u64, GuildMembers>,
+}
+
+impl MemberCache {
+    /// 是否已拉取服务器的成员
+    pub fn is_loaded(&self, guild_id: u64) -> bool {
+        self.guilds.contains_key(&guild_id)
+    }
+
+    pub fn get(&self, guild_id: u64, user_id: u64) -> Option<&DcMember> {
+        self.guilds.get(&guild_id)?.members.get(&user_id)
+    }
+
+    /// # 按名称查询
+    /// - 旧账号按 `名称#discriminator` 精确查询
+    /// - 没有discriminator(或为0)时按新用户名查询(不区分大小写), 查不到时按全局显示名或服务器昵称查询, 重名时不匹配
+    pub fn find_by_name(&self, guild_id: u64, name: &str, discriminator: Option<u16>) -> Option<&DcMember> {
+        let guild = self.guilds.get(&guild_id)?;
+        if let Some(discriminator @ 1..) = discriminator {
+            let id = guild.names.get(&(name.to_string(), discriminator))?;
+            return guild.members.get(id);
+        }
+        if let Some(id) = guild.names.get(&(name.to_lowercase(), 0)) {
+            return guild.members.get(id);
+        }
+        let mut named = guild
+            .members
+            .values()
+            .filter(|member| member.global_name.as_deref() == Some(name) || member.nick.as_deref() == Some(name));
+        match (named.next(), named.next()) {
+            (Some(member), None) => Some(member),
+            _ => None,
+        }
+    }
+
+    /// 替换服务器的全部成员
+    pub fn fill(&mut self, guild_id: u64, members: Vec<DcMember>) {
+        let mut guild = GuildMembers::default();
+        for member in members {
+            guild.names.insert((member.name.clone(), member.discriminator), member.id);
+            guild.members.insert(member.id, member);
+        }
+        self.guilds.insert(guild_id, guild);
+    }
+
+    /// 成员加入或改名
+    pub fn update(&mut self, guild_id: u64, member: DcMember) {
+        let guild = self.guilds.entry(guild_id).or_default();
+        if let Some(old) = guild.members.get(&member.id) {
+            guild.names.remove(&(old.name.clone(), old.discriminator));
+        }
+        guild.names.insert((member.name.clone(), member.discriminator), member.id);
+        guild.members.insert(member.id, member);
+    }
+
+    /// # 由网关事件更新成员
+    /// 事件中没有全局显示名, 沿用缓存中的全局显示名
+    /// ### Returns
+    /// 缓存中也没有全局显示名时返回 `true`, 需要请求成员
+    pub fn update_from_event(&mut self, guild_id: u64, mut member: DcMember) -> bool {
+        member.global_name = self.get(guild_id, member.id).and_then(|cached| cached.global_name.clone());
+        let missing = member.global_name.is_none();
+        self.update(guild_id, member);
+        missing
+    }
+
+    /// 成员离开
+    pub fn remove(&mut self, guild_id: u64, user_id: u64) {
+        let Some(guild) = self.guilds.get_mut(&guild_id) else {
+            return;
+        };
+        if let Some(old) = guild.members.remove(&user_id) {
+            guild.names.remove(&(old.name, old.discriminator));
+        }
+    }
+}
+
+lazy_static! {
+    pub static ref DC_MEMBERS: Mutex<MemberCache> = Mutex::new(MemberCache::default());
+}
+
+/// 请求一页成员
+async fn fetch_members(http: &Http, guild_id: u64, after: Option<u64>) -> anyhow::Result<Vec<DcMember>> {
+    let route = RouteInfo::GetGuildMembers {
+        after,
+        guild_id,
+        limit: Some(PAGE_SIZE),
+    };
+    let page: Vec<RawMember> = http.fire(Request::new(RequestBuilder::new(route))).await?;
+    page.into_iter().map(DcMember::try_from).collect()
+}
+
+/// 请求一个成员
+async fn fetch_member(http: &Http, guild_id: u64, user_id: u64) -> anyhow::Result<DcMember> {
+    let route = RouteInfo::GetMember { guild_id, user_id };
+    let member: RawMember = http.fire(Request::new(RequestBuilder::new(route))).await?;
+    member.try_into()
+}
+
+/// 分页拉取服务器的全部成员
+pub async fn load_guild(http: &Http, guild_id: u64) -> anyhow::Result<()> {
+    let mut members = vec![];
+    let mut after = None;
+    loop {
+        let page = fetch_members(http, guild_id, after).await?;
+        let count = page.len() as u64;
+        after = page.last().map(|member| member.id);
+        members.extend(page);
+        if count < PAGE_SIZE {
+            break;
+        }
+    }
+    info!("[DC] 服务器 {guild_id} 已缓存 {} 个成员", members.len());
+    DC_MEMBERS.lock().await.fill(guild_id, members);
+    Ok(())
+}
+
+/// 服务器成员还没有缓存时拉取
+async fn ensure_loaded(http: &Http, guild_id: u64) {
+    if DC_MEMBERS.lock().await.is_loaded(guild_id) {
+        return;
+    }
+    if let Err(err) = load_guild(http, guild_id).await {
+        warn!(?err, "[DC] 服务器 {guild_id} 的成员拉取失败");
+    }
+}
+
+/// 通过名称查询成员, 见 [`MemberCache::find_by_name`]
+pub async fn find_by_name(http: &Http, guild_id: u64, name: &str, discriminator: Option<u16>) -> Option<DcMember> {
+    ensure_loaded(http, guild_id).await;
+    DC_MEMBERS.lock().await.find_by_name(guild_id, name, discriminator).cloned()
+}
+
+/// 查询成员, 缓存中没有时请求接口
+pub async fn get_member(http: &Http, guild_id: u64, user_id: u64) -> Option<DcMember> {
+    if let Some(member) = DC_MEMBERS.lock().await.get(guild_id, user_id) {
+        return Some(member.clone());
+    }
+    let member = match fetch_member(http, guild_id, user_id).await {
+        Ok(member) => member,
+        Err(err) => {
+            warn!(?err, "[DC] 获取成员 {user_id} 失败");
+            return None;
+        }
+    };
+    DC_MEMBERS.lock().await.update(guild_id, member.clone());
+    Some(member)
+}
+
+/// 成员加入或更新, 见 [`MemberCache::update_from_event`]; 没有缓存全局显示名时请求成员
+pub async fn refresh(http: &Http, guild_id: u64, member: DcMember) {
+    let user_id = member.id;
+    if !DC_MEMBERS.lock().await.update_from_event(guild_id, member) {
+        return;
+    }
+    match fetch_member(http, guild_id, user_id).await {
+        Ok(fetched) => DC_MEMBERS.lock().await.update(guild_id, fetched),
+        Err(err) => warn!(?err, "[DC] 获取成员 {user_id} 失败"),
+    }
+}
+
+/// 消息或回应的发送者, 补充缓存中的全局显示名和服务器昵称
+pub async fn of_user(guild_id: Option<u64>, user: &User) -> DcMember {
+    let mut member = DcMember::new(user, None);
+    let Some(guild_id) = guild_id else {
+        return member;
+    };
+    if let Some(cached) = DC_MEMBERS.lock().await.get(guild_id, user.id.0) {
+        member.global_name = cached.global_name.clone();
+        member.nick = cached.nick.clone();
+    }
+    member
+}
+
+#[cfg(test)]
+mod test {
+    use super::{DcMember, MemberCache, RawMember};
+
+    #[test]
+    fn test_update() {
+        let member = |id: u64, name: &str| DcMember {
+            id,
+            name: name.to_string(),
+            discriminator: 1,
+            global_name: None,
+            nick: None,
+        };
+        let mut cache = MemberCache::default();
+        cache.fill(1, vec![member(10, "a"), member(11, "b")]);
+        assert_eq!(cache.find_by_name(1, "b", Some(1)).map(|member| member.id), Some(11));
+        assert!(cache.find_by_name(1, "b", Some(2)).is_none());
+        assert!(cache.find_by_name(2, "b", Some(1)).is_none());
+
+        // 改名后旧名称查不到
+        cache.update(1, member(11, "c"));
+        assert!(cache.find_by_name(1, "b", Some(1)).is_none());
+        assert_eq!(cache.get(1, 11).map(|member| member.name.as_str()), Some("c"));
+
+        cache.remove(1, 11);
+        assert!(cache.find_by_name(1, "c", Some(1)).is_none());
+        assert!(cache.get(1, 10).is_some());
+
+        // 网关事件沿用缓存的全局显示名, 没有时需要请求成员
+        assert!(cache.update_from_event(1, member(12, "d")));
+        cache.update(
+            1,
+            DcMember {
+                global_name: Some("D".to_string()),
+                ..member(12, "d")
+            },
+        );
+        assert!(!cache.update_from_event(1, member(12, "e")));
+        let cached = cache.get(1, 12).unwrap();
+        assert_eq!((cached.name.as_str(), cached.global_name.as_deref()), ("e", Some("D")));
+    }
+
+    #[test]
+    fn test_find_new_username() {
+        let member = |id: u64, name: &str, nick: Option<&str>| DcMember {
+            id,
+            name: name.to_string(),
+            discriminator: 0,
+            global_name: None,
+            nick: nick.map(str::to_string),
+        };
+        let mut cache = MemberCache::default();
+        cache.fill(
+            1,
+            vec![
+                member(10, "alice", Some("小A")),
+                member(11, "bob", Some("同名")),
+                member(12, "carol", Some("同名")),
+            ],
+        );
+        assert_eq!(cache.find_by_name(1, "alice", None).map(|member| member.id), Some(10));
+        assert_eq!(cache.find_by_name(1, "Alice", Some(0)).map(|member| member.id), Some(10));
+        assert_eq!(cache.find_by_name(1, "小A", None).map(|member| member.id), Some(10));
+        assert!(cache.find_by_name(1, "同名", None).is_none());
+        assert!(cache.find_by_name(1, "alice", Some(1234)).is_none());
+
+        // 全局显示名
+        cache.update(
+            1,
+            DcMember {
+                global_name: Some("Alice Liddell".to_string()),
+                ..member(10, "alice", Some("小A"))
+            },
+        );
+        assert_eq!(cache.find_by_name(1, "Alice Liddell", None).map(|member| member.id), Some(10));
+        assert!(cache.find_by_name(1, "alice liddell", None).is_none());
+    }
+
+    #[test]
+    fn test_raw_member() {
+        let json = r#"{
+            "nick": null,
+            "user": { "id": "80351110224678912", "username": "nelly", "discriminator": "0", "global_name": "Nelly", "avatar": null }
+        }"#;
+        let member = DcMember::try_from(serde_json::from_str::<RawMember>(json).unwrap()).unwrap();
+        assert_eq!(member.id, 80351110224678912);
+        assert_eq!(member.discriminator, 0);
+        assert_eq!(member.global_name.as_deref(), Some("Nelly"));
+        assert_eq!(member.display_text(), "Nelly");
+    }
+}
@@ -1,98 +1,176 @@
 use std::sync::Arc;
 
+use async_trait::async_trait;
 use serenity::http::Http;
-use serenity::model::guild::Member;
 use serenity::prelude::*;
+use tokio::sync::OnceCell;
 use tracing::{instrument, warn};
 
-use crate::bridge::pojo::BridgeMessagePO;
+use crate::bridge::pojo::{BridgeMessagePO, BridgeUserSaveForm};
 use crate::bridge::user::BridgeUser;
+use crate::bridge::{BridgeAdapter, BridgeClient, BridgeClientPlatform, BridgeMessage, BridgeReaction, OriginMessageId};
 
 // use crate::bridge_message_history::{BridgeMessageHistory, Platform};
-use crate::{bridge, bridge_dc, Config};
+use crate::config::SharedConfig;
+use crate::{bridge, bridge_dc};
 
 pub mod bridge_client;
 pub mod handler;
+pub mod member_cache;
+pub mod slash_command;
+pub mod webhook;
 
 pub use handler::*;
+pub use member_cache::DcMember;
 
-pub async fn start(config: Arc<Config>, bridge: Arc<bridge::BridgeClient>) {
+/// Discord 平台适配器
+pub struct DiscordAdapter {
+    config: Arc<SharedConfig>,
+    bridge: Arc<BridgeClient>,
+    http: OnceCell<Arc<Http>>,
+}
+
+impl DiscordAdapter {
+    pub fn new(config: Arc<SharedConfig>, bridge: Arc<BridgeClient>) -> Self {
+        DiscordAdapter {
+            config,
+            bridge,
+            http: OnceCell::new(),
+        }
+    }
+
+    /**
+     * 申请桥用户
+     * 用户改名或迁移到新用户名后更新显示文本
+     */
+    pub async fn apply_dc_user(&self, member: &DcMember) -> anyhow::Result<BridgeUser> {
+        let form = BridgeUserSaveForm {
+            origin_id: member.id.to_string(),
+            platform: self.platform().to_string(),
+            display_text: member.display_text(),
+        };
+        let user = bridge::manager::BRIDGE_USER_MANAGER.lock().await.likeAndUpdate(form).await;
+        user.map_err(|err| anyhow::anyhow!("申请桥用户失败: {err}"))
+    }
+}
+
+#[async_trait]
+impl BridgeAdapter for DiscordAdapter {
+    fn platform(&self) -> BridgeClientPlatform {
+        BridgeClientPlatform::Discord
+    }
+
+    fn bridge(&self) -> &Arc<BridgeClient> {
+        &self.bridge
+    }
+
+    async fn start(self: Arc<Self>) {
+        start(self).await
+    }
+
+    async fn send_message(&self, message: &BridgeMessage) -> anyhow::Result<Option<OriginMessageId>> {
+        let Some(http) = self.http.get() else {
+            return Err(anyhow::anyhow!("[DC] 客户端未连接"));
+        };
+        bridge_client::send_message(self, http, message).await
+    }
+
+    async fn edit_message(&self, message: &BridgeMessage, origin_id: &OriginMessageId) -> anyhow::Result<()> {
+        let Some(http) = self.http.get() else {
+            return Err(anyhow::anyhow!("[DC] 客户端未连接"));
+        };
+        bridge_client::edit_message(self, http, message, origin_id).await
+    }
+
+    async fn recall_message(&self, message: &BridgeMessage, origin_id: &OriginMessageId) -> anyhow::Result<()> {
+        let Some(http) = self.http.get() else {
+            return Err(anyhow::anyhow!("[DC] 客户端未连接"));
+        };
+        bridge_client::recall_message(http, message, origin_id).await
+    }
+
+    async fn react_message(
+        &self,
+        message: &BridgeMessage,
+        origin_id: &OriginMessageId,
+        reaction: &BridgeReaction,
+        added: bool,
+        reactions: &[BridgeReaction],
+    ) -> anyhow::Result<()> {
+        let Some(http) = self.http.get() else {
+            return Err(anyhow::anyhow!("[DC] 客户端未连接"));
+        };
+        bridge_client::react_message(http, message, origin_id, reaction, added, reactions).await
+    }
+}
+
+async fn start(adapter: Arc<DiscordAdapter>) {
     tracing::info!("[DC] 初始化DC桥");
+    let config = adapter.config.current();
     let token = &config.discord_config.botToken;
-    let intents =
-        GatewayIntents::GUILD_MESSAGES | GatewayIntents::GUILD_MEMBERS | GatewayIntents::DIRECT_MESSAGES | GatewayIntents::MESSAGE_CONTENT;
+    let intents = GatewayIntents::GUILDS
+        | GatewayIntents::GUILD_MESSAGES
+        | GatewayIntents::GUILD_MEMBERS
+        | GatewayIntents::GUILD_WEBHOOKS
+        | GatewayIntents::GUILD_MESSAGE_REACTIONS
+        | GatewayIntents::DIRECT_MESSAGES
+        | GatewayIntents::MESSAGE_CONTENT;
 
     let mut client = Client::builder(&token, intents)
         .event_handler(bridge_dc::Handler {
-            config: config.clone(),
-            bridge: bridge.clone(),
+            config: adapter.config.clone(),
+            adapter: adapter.clone(),
         })
         .await
         .expect("Err creating client");
-    // 创建机器人的webhook, 能够发送交互组件消息
-    // let webhook = http.create_webhook(channel_id, &map, None).await?;
-    // let map = serde_json::json!({"name": "test"});
-    // let webhook = client
-    //     .cache_and_http
-    //     .http
-    //     .create_webhook(781347109676384297, &map, None)
-    //     .await
-    //     .unwrap();
-    // let webhook = client
-    //     .cache_and_http
-    //     .http
-    //     .get_webhook(1084186702567981077)
-    //     .await
-    //     .unwrap();
-    // println!("webhook_id: {}", webhook.id);
-    // webhook
-    //     .execute(&client.cache_and_http.http, false, |w| {
-    //         w.username("Webhook test").components(|c| {
-    //             c.create_action_row(|row| {
-    //                 row.create_button(|b| b.style(ButtonStyle::Link).url("https://discord.com/channels/724829522230378536/781347109676384297/1082716108953497681").label("跳转回复"))
-    //             })
-    //         })
-    //     })
-    //     .await
-    //     .expect("Could not execute webhook.");
     let cache = client.cache_and_http.clone();
+    let _ = adapter.http.set(cache.http.clone());
+    webhook::provision_all(&cache.http, &config).await;
 
     tokio::select! {
         _ = client.start() => {
             tracing::warn!("[DC] Discord客户端退出");
         },
-        _ = bridge_client::listen(bridge.clone(), cache.http.clone()) => {
+        _ = bridge::adapter::sync_message(adapter.as_ref()) => {
             tracing::warn!("[DC] Discord桥关闭");
         },
     }
 }
 
 /**
- * 申请桥用户
+ * dc用户的显示文本
+ * 迁移到新用户名的账号 discriminator 为0, 有全局显示名时显示全局显示名, 否则显示用户名; 旧账号显示 `名称#discriminator`
+ * 全局显示名没有空格时, `@[DC] 全局显示名` 能通过 [`member_cache::MemberCache::find_by_name`] 查回该成员
  */
-pub async fn apply_bridge_user(id: u64, name: &str, discriminator: u16) -> BridgeUser {
-    let bridge_user = bridge::manager::BRIDGE_USER_MANAGER
-        .lock()
-        .await
-        .likeAndSave(bridge::pojo::BridgeUserSaveForm {
-            origin_id: id.to_string(),
-            platform: "DC".to_string(),
-            display_text: format!("{}#{}", name, discriminator),
-        })
-        .await;
-    bridge_user.unwrap()
+pub fn to_display_text(name: &str, discriminator: u16, global_name: Option<&str>) -> String {
+    match discriminator {
+        0 => global_name.unwrap_or(name).to_string(),
+        _ => format!("{name}#{discriminator:04}"),
+    }
+}
+
+/**
+ * 迁移旧的显示文本, 不需要迁移时为空
+ * 以前保存为 `名称#discriminator`, 迁移到新用户名的账号是 `用户名#0`, 不足4位的discriminator没有补0
+ */
+pub fn migrate_display_text(display_text: &str) -> Option<String> {
+    let (name, discriminator) = display_text.rsplit_once('#')?;
+    let discriminator = discriminator.parse::<u16>().ok()?;
+    let migrated = to_display_text(name, discriminator, None);
+    (migrated != display_text).then_some(migrated)
 }
 
 /**
  * 通过名称和discriminator查询成员
+ * 成员来自缓存, 见 [`member_cache`]
  */
 #[instrument(level = "debug", skip(http), ret)]
-pub async fn find_member_by_name(http: &Http, guild_id: u64, nickname: &str, discriminator: &str) -> Option<Member> {
-    let members = http.get_guild_members(guild_id, None, None).await.unwrap();
-    let member = members
-        .into_iter()
-        .find(|member| member.user.name == nickname && member.user.discriminator.to_string() == discriminator);
-    member
+pub async fn find_member_by_name(http: &Http, guild_id: u64, name: &str, discriminator: Option<&str>) -> Option<DcMember> {
+    let discriminator = match discriminator {
+        Some(discriminator) => Some(discriminator.parse::<u16>().ok()?),
+        None => None,
+    };
+    member_cache::find_by_name(http, guild_id, name, discriminator).await
 }
 
 /**
@@ -126,7 +204,7 @@ pub async fn to_reply_content(reply_message: BridgeMessagePO) -> Vec<String> {
                 }
                 let bridge_user = bridge_user.unwrap();
                 // 查看桥关联的本平台用户id
-                if let Some(ref_user) = bridge_user.find_by_platform("DC").await {
+                if let Some(ref_user) = bridge_user.find_by_platform(&BridgeClientPlatform::Discord.to_string()).await {
                     content.push_str(format!("@{}", ref_user.to_string()).as_str());
                     continue;
                 }
@@ -147,36 +225,109 @@ pub async fn to_reply_content(reply_message: BridgeMessagePO) -> Vec<String> {
 
 /**
  * 解析文本规则取出提及@[DC]用户的文本
+ * - 旧账号: `@[DC] 名称#1234`, 名称可以包含空格但首尾不是空白; 以前保存的 `@[DC] 用户名#0` 也能识别
+ * - 新用户名、全局显示名或服务器昵称: `@[DC] 名称`, 名称到空白或文本结尾为止
  */
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Eq)]
 pub enum MentionText {
     Text(String),
-    MentionText { name: String, discriminator: String },
+    MentionText { name: String, discriminator: Option<String> },
 }
 pub fn parse_text_mention_rule(text: String) -> Vec<MentionText> {
-    let mut text = text;
+    const PREFIX: &str = "@[DC] ";
     let mut chain: Vec<MentionText> = vec![];
-    let split_const = "#|x-x|#".to_string();
-    let reg_at_user = regex::Regex::new(r"@\[DC\] ([^\n^#^@]+)?#(\d\d\d\d)").unwrap();
-    // let caps = reg_at_user.captures(text);
-    while let Some(caps) = reg_at_user.captures(text.as_str()) {
-        println!("{:?}", caps);
-        let from = caps.get(0).unwrap().as_str();
-        let name = caps.get(1).unwrap().as_str().to_string();
-        let discriminator = caps.get(2).unwrap().as_str().to_string();
-
-        let result = text.replace(from, &split_const);
-        let splits: Vec<&str> = result.split(&split_const).collect();
-        let prefix = splits.get(0).unwrap();
-        chain.push(MentionText::Text(prefix.to_string()));
-        chain.push(MentionText::MentionText { name, discriminator });
-        if let Some(fix) = splits.get(1) {
-            text = fix.to_string();
+    let mut last = 0;
+    let mut from = 0;
+    while let Some(index) = text[from..].find(PREFIX) {
+        let start = from + index;
+        from = start + PREFIX.len();
+        let Some((name, discriminator, len)) = parse_mention(&text[from..]) else {
+            continue;
+        };
+        if start > last {
+            chain.push(MentionText::Text(text[last..start].to_string()));
         }
+        chain.push(MentionText::MentionText {
+            name: name.to_string(),
+            discriminator: discriminator.map(str::to_string),
+        });
+        from += len;
+        last = from;
     }
-    if text.len() > 0 {
-        chain.push(MentionText::Text(text.to_string()));
+    if last < text.len() {
+        chain.push(MentionText::Text(text[last..].to_string()));
     }
-    println!("parse_text_mention_rule: {:?}", chain);
+    tracing::debug!("parse_text_mention_rule: {:?}", chain);
     chain
 }
+
+/**
+ * 解析 `@[DC] ` 之后的名称, 返回 (名称, discriminator, 提及的长度)
+ * discriminator 只能是4位数字或 `0`, 否则 `#` 只是普通文本
+ */
+fn parse_mention(rest: &str) -> Option<(&str, Option<&str>, usize)> {
+    let line = rest.split('\n').next().unwrap_or_default();
+    if let Some(hash) = line.find(['#', '@']).filter(|&hash| line[hash..].starts_with('#')) {
+        let name = &line[..hash];
+        let digits = &line[hash + 1..];
+        let digits = &digits[..digits.find(|c: char| !c.is_ascii_digit()).unwrap_or(digits.len())];
+        if !name.is_empty() && name.trim() == name && (digits.len() == 4 || digits == "0") {
+            return Some((name, Some(digits), hash + 1 + digits.len()));
+        }
+    }
+    let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+    (end > 0).then(|| (&rest[..end], None, end))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_text_mention_rule() {
+        let mention = |name: &str, discriminator: Option<&str>| MentionText::MentionText {
+            name: name.to_string(),
+            discriminator: discriminator.map(str::to_string),
+        };
+        assert_eq!(
+            parse_text_mention_rule("你好 @[DC] old name#0420 和 @[DC] new_user.1 !".to_string()),
+            vec![
+                MentionText::Text("你好 ".to_string()),
+                mention("old name", Some("0420")),
+                MentionText::Text(" 和 ".to_string()),
+                mention("new_user.1", None),
+                MentionText::Text(" !".to_string()),
+            ]
+        );
+        assert_eq!(
+            parse_text_mention_rule("@[DC] alice#0".to_string()),
+            vec![mention("alice", Some("0"))]
+        );
+        // 服务器昵称、全局显示名
+        assert_eq!(
+            parse_text_mention_rule("@[DC] 小A 和 @[DC] Alice_Liddell\n早".to_string()),
+            vec![
+                mention("小A", None),
+                MentionText::Text(" 和 ".to_string()),
+                mention("Alice_Liddell", None),
+                MentionText::Text("\n早".to_string()),
+            ]
+        );
+        // 不是discriminator的 `#` 不属于提及
+        assert_eq!(
+            parse_text_mention_rule("@[DC] bob 看 issue #12".to_string()),
+            vec![mention("bob", None), MentionText::Text(" 看 issue #12".to_string())]
+        );
+        assert_eq!(
+            parse_text_mention_rule("@[DC] bob#12345 @[DC] ".to_string()),
+            vec![mention("bob#12345", None), MentionText::Text(" @[DC] ".to_string())]
+        );
+        assert_eq!(to_display_text("alice", 0, None), "alice");
+        assert_eq!(to_display_text("alice", 0, Some("爱丽丝")), "爱丽丝");
+        assert_eq!(to_display_text("Bob", 42, None), "Bob#0042");
+        assert_eq!(migrate_display_text("alice#0").as_deref(), Some("alice"));
+        assert_eq!(migrate_display_text("Bob#42").as_deref(), Some("Bob#0042"));
+        assert_eq!(migrate_display_text("Bob#0042"), None);
+        assert_eq!(migrate_display_text("alice"), None);
+    }
+}
@@ -0,0 +1,217 @@
+//! dc斜杠指令
+//!
+//! 把桥的指令([`BridgeCommand`])注册为dc的应用指令，指令名和说明按客户端语言显示中文；
+//! 收到的指令先延迟回应，交给 [`CommandCentext::process_command`] 处理后编辑回应，结果只回复给执行指令的用户(ephemeral)，不会发到桥上
+
+use serde_json::{json, Value};
+use serenity::http::Http;
+use serenity::model::application::interaction::application_command::ApplicationCommandInteraction;
+use serenity::model::application::interaction::InteractionResponseType;
+use serenity::prelude::*;
+use tracing::{error, info, warn};
+
+use crate::bridge::{BridgeAdapter, BridgeMessage, MessageContent};
+use crate::bridge_cmd::{self, BridgeCommand, CommandCentext, CMD_BIND, CMD_CONFIRM_BIND, CMD_UNBIND};
+
+use super::{member_cache, DiscordAdapter};
+
+const BIND: &str = "bind";
+const CONFIRM_BIND: &str = "confirm-bind";
+const UNBIND: &str = "unbind";
+const HELP: &str = "help";
+
+/// 字符串类型的指令参数
+const STRING_OPTION: u8 = 3;
+
+/// 中文的名称或说明
+fn zh(text: &str) -> Value {
+    json!({ "zh-CN": text, "zh-TW": text })
+}
+
+/// 应用指令的定义
+fn definitions() -> Value {
+    json!([
+        {
+            "name": BIND,
+            "name_localizations": zh("关联"),
+            "description": "Request account binding, or answer a request with its code",
+            "description_localizations": zh("申请关联，获取验证码；或者用验证码回应申请"),
+            "options": [{
+                "type": STRING_OPTION,
+                "name": "token",
+                "name_localizations": zh("口令"),
+                "description": "Code from the other platform; leave empty to request",
+                "description_localizations": zh("另一个平台的验证码；不填时申请关联"),
+                "required": false,
+            }],
+        },
+        {
+            "name": CONFIRM_BIND,
+            "name_localizations": zh("确认关联"),
+            "description": "Confirm the binding answered on the other platform",
+            "description_localizations": zh("确定保存关联"),
+        },
+        {
+            "name": UNBIND,
+            "name_localizations": zh("解除关联"),
+            "description": "Remove the binding with a platform",
+            "description_localizations": zh("解除指定平台的关联"),
+            "options": [{
+                "type": STRING_OPTION,
+                "name": "platform",
+                "name_localizations": zh("平台"),
+                "description": "Platform to unbind",
+                "description_localizations": zh("要解除关联的平台"),
+                "required": true,
+                "choices": [
+                    { "name": "QQ", "value": "QQ" },
+                    { "name": "Telegram", "value": "TG" },
+                ],
+            }],
+        },
+        {
+            "name": HELP,
+            "name_localizations": zh("帮助"),
+            "description": "Show bridge commands",
+            "description_localizations": zh("查看桥的指令"),
+            "options": [{
+                "type": STRING_OPTION,
+                "name": "command",
+                "name_localizations": zh("指令"),
+                "description": "Command to explain",
+                "description_localizations": zh("查看该指令的用法"),
+                "required": false,
+                "choices": [
+                    { "name": CMD_BIND, "value": CMD_BIND },
+                    { "name": CMD_CONFIRM_BIND, "value": CMD_CONFIRM_BIND },
+                    { "name": CMD_UNBIND, "value": CMD_UNBIND },
+                ],
+            }],
+        },
+    ])
+}
+
+/// 注册应用指令, 覆盖以前注册的指令
+pub async fn register(http: &Http) {
+    match http.create_global_application_commands(&definitions()).await {
+        Ok(commands) => info!("[DC] 已注册 {} 个斜杠指令", commands.len()),
+        Err(err) => error!(?err, "[DC] 斜杠指令注册失败"),
+    }
+}
+
+/// # 斜杠指令转为桥指令
+/// ### Arguments
+/// - `name` 指令名
+/// - `option` 按名称取字符串参数
+fn to_bridge_command(name: &str, option: impl Fn(&str) -> Option<String>) -> Option<BridgeCommand> {
+    Some(match name {
+        BIND => BridgeCommand::Bind { token: option("token") },
+        CONFIRM_BIND => BridgeCommand::ConfirmBind,
+        UNBIND => BridgeCommand::Unbind {
+            platform: option("platform")?,
+        },
+        HELP => BridgeCommand::Tips {
+            command: option("command"),
+        },
+        _ => return None,
+    })
+}
+
+/// # 处理斜杠指令, 结果只回复给执行指令的用户
+/// 先回应"正在思考", 处理完成后编辑该回应, 指令处理较慢时也不会超过dc要求的3秒回应时限
+pub async fn handle(adapter: &DiscordAdapter, ctx: &Context, command: ApplicationCommandInteraction) {
+    let option = |name: &str| {
+        command
+            .data
+            .options
+            .iter()
+            .find(|option| option.name == name)
+            .and_then(|option| option.value.as_ref()?.as_str().map(str::to_string))
+    };
+    let Some(token) = to_bridge_command(&command.data.name, option) else {
+        warn!("[DC] 未知的斜杠指令: {}", command.data.name);
+        return;
+    };
+    info!("[DC] 斜杠指令 {:?}", token);
+    let deferred = command
+        .create_interaction_response(&ctx.http, |response| {
+            response
+                .kind(InteractionResponseType::DeferredChannelMessageWithSource)
+                .interaction_response_data(|data| data.ephemeral(true))
+        })
+        .await;
+    if let Err(err) = deferred {
+        error!(?err, "[DC] 斜杠指令回复失败");
+        return;
+    }
+    let text = match process(adapter, &command, token).await {
+        Ok(text) => text,
+        Err(err) => format!("{err:#}"),
+    };
+    let resp = command
+        .edit_original_interaction_response(&ctx.http, |response| response.content(text))
+        .await;
+    if let Err(err) = resp {
+        error!(?err, "[DC] 斜杠指令回复失败");
+    }
+}
+
+/// 执行桥指令, 返回回复的文本
+async fn process(adapter: &DiscordAdapter, command: &ApplicationCommandInteraction, token: BridgeCommand) -> anyhow::Result<String> {
+    let user = member_cache::of_user(command.guild_id.map(|guild_id| guild_id.0), &command.user).await;
+    let bridge_user = adapter.apply_dc_user(&user).await?;
+    // 私聊或没有配置桥的频道中也能使用, 指令处理只用到发送者
+    let bridge_config = adapter
+        .config
+        .current()
+        .find_by_discord_channel(command.channel_id.0)
+        .unwrap_or_default();
+    let cmd = CommandCentext {
+        token,
+        ctx: bridge_cmd::command_parser(),
+        client: adapter.bridge().name.clone(),
+        src_msg: BridgeMessage {
+            id: uuid::Uuid::new_v4().to_string(),
+            sender_id: bridge_user.id,
+            avatar_url: None,
+            bridge_config,
+            message_chain: vec![],
+        },
+    };
+    let feedback = cmd.process_command().await.map_err(anyhow::Error::msg)?;
+    let text = feedback
+        .iter()
+        .filter_map(|chain| match chain {
+            MessageContent::Plain { text } => Some(text.as_str()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("");
+    Ok(text)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_to_bridge_command() {
+        let token = |name: &str| (name == "token").then(|| "1a2b3c".to_string());
+        assert!(matches!(
+            to_bridge_command(BIND, token),
+            Some(BridgeCommand::Bind { token: Some(token) }) if token == "1a2b3c"
+        ));
+        assert!(matches!(
+            to_bridge_command(BIND, |_| None),
+            Some(BridgeCommand::Bind { token: None })
+        ));
+        // 缺少必填参数
+        assert!(to_bridge_command(UNBIND, |_| None).is_none());
+        assert!(to_bridge_command("unknown", |_| None).is_none());
+        // 每个定义的指令都能转为桥指令
+        let platform = |_: &str| Some("QQ".to_string());
+        for definition in definitions().as_array().unwrap() {
+            assert!(to_bridge_command(definition["name"].as_str().unwrap(), platform).is_some());
+        }
+    }
+}
@@ -0,0 +1,192 @@
+//! 频道webhook的自动配置
+//!
+//! dc端点只配置 `channelId` 时，启动时为频道查找机器人创建的webhook，没有则创建，凭据保存在 `./data/dc_webhook.json`；
+//! webhook被删除后，下次发送消息时重新创建。端点配置了 `id` 和 `token` 时仍使用配置的webhook
+//!
+//! 获取到的webhook按频道缓存在内存中, 频道的webhook有变化(`webhook_update` 事件)或发送失败时清除
+
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use serenity::http::{Http, StatusCode};
+use serenity::model::webhook::Webhook;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use tokio::sync::Mutex;
+use tracing::{error, info, warn};
+
+use crate::bridge::storage::json_file;
+use crate::config::{Config, DiscordBridgeConfig, EndpointTarget};
+
+/// 自动创建的webhook名
+const WEBHOOK_NAME: &str = "message_bridge";
+
+/// 自动配置的webhook凭据
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct WebhookCredential {
+    pub channel_id: u64,
+    pub id: u64,
+    pub token: String,
+}
+
+pub struct WebhookStore {
+    path: PathBuf,
+    webhooks: Vec<WebhookCredential>,
+}
+
+impl WebhookStore {
+    pub fn new() -> WebhookStore {
+        Self::open_at(PathBuf::from("./data/dc_webhook.json"))
+    }
+
+    pub fn open_at(path: PathBuf) -> WebhookStore {
+        WebhookStore {
+            webhooks: json_file::load(&path).unwrap_or_default(),
+            path,
+        }
+    }
+
+    /// 查询频道的webhook
+    pub fn get(&self, channel_id: u64) -> Option<&WebhookCredential> {
+        self.webhooks.iter().find(|webhook| webhook.channel_id == channel_id)
+    }
+
+    /// 是否自动配置的webhook
+    pub fn contains(&self, webhook_id: u64) -> bool {
+        self.webhooks.iter().any(|webhook| webhook.id == webhook_id)
+    }
+
+    /// 保存频道的webhook, 替换频道原来的webhook
+    pub async fn save(&mut self, credential: WebhookCredential) {
+        self.webhooks.retain(|webhook| webhook.channel_id != credential.channel_id);
+        self.webhooks.push(credential);
+        self.serialize().await;
+    }
+
+    async fn serialize(&self) {
+        if let Err(err) = json_file::save_blocking(self.path.clone(), self.webhooks.clone()).await {
+            error!(?err, "webhook保存失败: {}", self.path.display());
+        }
+    }
+}
+
+lazy_static! {
+    pub static ref DC_WEBHOOKS: Mutex<WebhookStore> = Mutex::new(WebhookStore::new());
+    /// 频道id -> 已获取的webhook
+    static ref WEBHOOK_CACHE: Mutex<HashMap<u64, Webhook>> = Mutex::new(HashMap::new());
+}
+
+/// 是否桥的webhook (配置的或自动配置的), 收到其消息时不再同步, 以免消息循环
+pub async fn is_bridge_webhook(config: &Config, webhook_id: u64) -> bool {
+    config.is_bridge_webhook(webhook_id) || DC_WEBHOOKS.lock().await.contains(webhook_id)
+}
+
+/// # 获取端点的webhook
+/// 优先使用缓存; 没有缓存时使用配置的webhook, 自动配置的webhook被删除时重新创建
+pub async fn get_webhook(http: &Http, discord: &DiscordBridgeConfig) -> anyhow::Result<Webhook> {
+    if let Some(webhook) = WEBHOOK_CACHE.lock().await.get(&discord.channelId) {
+        return Ok(webhook.clone());
+    }
+    let webhook = fetch_webhook(http, discord).await?;
+    WEBHOOK_CACHE.lock().await.insert(discord.channelId, webhook.clone());
+    Ok(webhook)
+}
+
+/// 清除频道缓存的webhook, 下次发送时重新获取
+pub async fn forget(channel_id: u64) {
+    WEBHOOK_CACHE.lock().await.remove(&channel_id);
+}
+
+async fn fetch_webhook(http: &Http, discord: &DiscordBridgeConfig) -> anyhow::Result<Webhook> {
+    if discord.id != 0 {
+        return Ok(Webhook::from_id_with_token(http, discord.id, &discord.token).await?);
+    }
+    let saved = DC_WEBHOOKS.lock().await.get(discord.channelId).cloned();
+    if let Some(saved) = saved {
+        match Webhook::from_id_with_token(http, saved.id, &saved.token).await {
+            Ok(webhook) => return Ok(webhook),
+            Err(serenity::Error::Http(err)) if err.status_code() == Some(StatusCode::NOT_FOUND) => {
+                warn!("[DC] 频道 {} 的webhook已被删除, 重新创建", discord.channelId);
+            }
+            Err(err) => return Err(err.into()),
+        }
+    }
+    provision(http, discord.channelId).await
+}
+
+/// 查找机器人在频道中创建的webhook, 没有则创建
+async fn provision(http: &Http, channel_id: u64) -> anyhow::Result<Webhook> {
+    let bot_id = http.get_current_user().await?.id;
+    let found = http
+        .get_channel_webhooks(channel_id)
+        .await?
+        .into_iter()
+        .find(|webhook| webhook.token.is_some() && webhook.user.as_ref().map_or(false, |user| user.id == bot_id));
+    let webhook = match found {
+        Some(webhook) => webhook,
+        None => {
+            let map = serde_json::json!({ "name": WEBHOOK_NAME });
+            http.create_webhook(channel_id, &map, Some("消息桥自动创建")).await?
+        }
+    };
+    let Some(token) = webhook.token.clone() else {
+        anyhow::bail!("webhook没有token: {}", webhook.id);
+    };
+    info!("[DC] 频道 {channel_id} 使用webhook {}", webhook.id);
+    let credential = WebhookCredential {
+        channel_id,
+        id: webhook.id.0,
+        token,
+    };
+    DC_WEBHOOKS.lock().await.save(credential).await;
+    Ok(webhook)
+}
+
+/// 启动时为只配置了频道的端点准备webhook
+pub async fn provision_all(http: &Http, config: &Config) {
+    let endpoints = config
+        .bridges
+        .iter()
+        .filter(|bridge| bridge.enable)
+        .flat_map(|bridge| bridge.endpoints.iter());
+    for endpoint in endpoints {
+        let EndpointTarget::Discord { discord } = &endpoint.target else {
+            continue;
+        };
+        if discord.id != 0 {
+            continue;
+        }
+        let channel_id = discord.channelId;
+        if let Err(err) = get_webhook(http, discord).await {
+            error!("[DC] 频道 {channel_id} 的webhook配置失败, 请检查机器人的管理webhook权限: {err:?}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{WebhookCredential, WebhookStore};
+    use crate::bridge::test_util::temp_json;
+
+    #[test]
+    fn test_save() {
+        let path = temp_json("dc_webhook");
+        let credential = |channel_id: u64, id: u64| WebhookCredential {
+            channel_id,
+            id,
+            token: format!("token{id}"),
+        };
+        tokio_test::block_on(async {
+            let mut store = WebhookStore::open_at(path.clone());
+            store.save(credential(1, 10)).await;
+            store.save(credential(2, 20)).await;
+            // webhook被删除后重新创建
+            store.save(credential(1, 11)).await;
+            assert_eq!(store.get(1).unwrap().id, 11);
+            assert!(!store.contains(10));
+        });
+
+        let store = WebhookStore::open_at(path.clone());
+        assert!(store.contains(11) && store.contains(20));
+        let _ = std::fs::remove_file(path);
+    }
+}
@@ -6,24 +6,23 @@ use proc_qq::re_exports::async_trait::async_trait;
 use proc_qq::re_exports::ricq_core;
 use proc_qq::re_exports::ricq_core::msg::elem;
 use proc_qq::{
-    FriendMessageEvent, GroupMessageEvent, GroupTempMessageEvent, LoginEventProcess,
-    MessageChainPointTrait, MessageEvent, MessageEventProcess,
+    FriendMessageEvent, GroupMessageEvent, GroupMessageRecallEvent, GroupMessageRecallEventProcess, GroupTempMessageEvent,
+    LoginEventProcess, MessageChainPointTrait, MessageEvent, MessageEventProcess,
 };
 use tracing::{debug, error, info};
 
-use crate::bridge::{BridgeClient, Image, MessageContent};
-use crate::config::BridgeConfig;
-use crate::{bridge, elo, utils, Config};
+use crate::bridge::{BridgeAdapter, GroupMessageId, Image, MessageContent, OriginMessageId};
+use crate::config::{BridgeConfig, SharedConfig};
+use crate::{bridge, elo, utils};
 
-use super::group_message_id::GroupMessageId;
-use super::{apply_bridge_user, RqClient};
+use super::QQAdapter;
 
 const OKK: anyhow::Result<bool> = Ok(true);
 
 async fn recv_group_msg(
     event: &GroupMessageEvent,
     config: &BridgeConfig,
-    bridge: Arc<BridgeClient>,
+    adapter: &QQAdapter,
 ) -> anyhow::Result<bool> {
     let mut _pass = true;
     let msg = &event.inner;
@@ -35,9 +34,11 @@ async fn recv_group_msg(
         msg.group_name, msg.elements
     );
     // 为发送者申请桥用户
-    let bridge_user = apply_bridge_user(sender_id, sender_nickname.as_str()).await;
+    let bridge_user = adapter
+        .apply_bridge_user(&sender_id.to_string(), &format!("{}({})", sender_nickname, sender_id))
+        .await;
     // 并接该群消息的id
-    let qq_message_id = GroupMessageId::new(group_id, msg.seqs.get(0).unwrap().clone());
+    let qq_message_id = GroupMessageId::with_rand(group_id, msg.seqs.get(0).unwrap().clone(), msg.rands.first().copied());
     // 组装向桥发送的消息体表单
     let mut bridge_message = bridge::pojo::BridgeSendMessageForm {
         sender_id: bridge_user.id,
@@ -45,8 +46,9 @@ async fn recv_group_msg(
         bridge_config: config.clone(),
         message_chain: Vec::new(),
         origin_message: bridge::pojo::BridgeMessageRefPO {
-            origin_id: qq_message_id.to_string(),
-            platform: "QQ".to_string(),
+            origin_id: OriginMessageId::QQ(qq_message_id),
+            platform: adapter.platform().to_string(),
+            endpoint: None,
         },
     };
 
@@ -55,11 +57,10 @@ async fn recv_group_msg(
         match chain {
             elem::RQElem::At(at) => {
                 debug!("RQElem::At: {:?}", at);
-                let bridge_user = apply_bridge_user(
-                    at.target as u64,
-                    elo!(at.display.strip_prefix("@") ;; continue),
-                )
-                .await;
+                let name = elo!(at.display.strip_prefix("@") ;; continue);
+                let bridge_user = adapter
+                    .apply_bridge_user(&at.target.to_string(), &format!("{}({})", name, at.target))
+                    .await;
                 bridge_message
                     .message_chain
                     .push(MessageContent::At { id: bridge_user.id });
@@ -93,31 +94,14 @@ async fn recv_group_msg(
                     debug!("疑似回复消息 id: {:?}", source_msg);
                     let seqs = source_msg.orig_seqs.first().unwrap().clone();
                     let group_message_id = GroupMessageId::new(source_msg.to_uin() as u64, seqs);
-                    let result = bridge::manager::BRIDGE_MESSAGE_MANAGER
-                        .lock()
-                        .await
-                        .find_by_ref_and_platform(group_message_id.to_string().as_str(), "QQ")
-                        .await;
-                    if let Err(err) = result {
-                        bridge_message
-                            .message_chain
-                            .push(MessageContent::Err { message: err });
-                        continue;
-                    }
-                    let result = result.unwrap();
-                    if let Some(reply) = result {
+                    let reply = adapter.to_bridge_reply(&OriginMessageId::QQ(group_message_id)).await;
+                    if let MessageContent::Reply { .. } = reply {
                         // 这条是一个笨逻辑, qq的回复会自动at, 这里把他去掉
                         bridge_message.message_chain.pop();
                         bridge_message.message_chain.pop();
-                        // 填入回复的消息
-                        bridge_message.message_chain.push(MessageContent::Reply {
-                            id: Some(reply.id.clone()),
-                        });
-                        continue;
                     }
-                    bridge_message.message_chain.push(MessageContent::Err {
-                        message: "回复一条QQ消息, 但是同步回复消息失败".to_string(),
-                    });
+                    // 填入回复的消息
+                    bridge_message.message_chain.push(reply);
                 } else {
                     debug!("未解读 elem: {:?}", o);
                 }
@@ -130,7 +114,7 @@ async fn recv_group_msg(
             }
         }
     }
-    bridge.send_message(bridge_message).await;
+    adapter.send_to_bridge(bridge_message).await;
     OKK
 }
 
@@ -149,18 +133,12 @@ async fn recv_friend_msg(event: &FriendMessageEvent) -> anyhow::Result<bool> {
 }
 
 pub struct DefaultHandler {
-    pub config: Arc<Config>,
-    pub bridge: Arc<BridgeClient>,
-    pub origin_client: Option<Arc<RqClient>>,
+    pub config: Arc<SharedConfig>,
+    pub adapter: Arc<QQAdapter>,
 }
 impl DefaultHandler {
-    fn find_cfg_by_group(&self, group_id: u64) -> Option<&BridgeConfig> {
-        let bridge_config = self
-            .config
-            .bridges
-            .iter()
-            .find(|b| group_id == b.qqGroup && b.enable);
-        Some(bridge_config?)
+    fn find_cfg_by_group(&self, group_id: u64) -> Option<BridgeConfig> {
+        self.config.current().find_by_qq_group(group_id)
     }
 }
 #[async_trait]
@@ -177,7 +155,7 @@ impl MessageEventProcess for DefaultHandler {
                     info!("群({gid})未启用消息同步");
                     return OKK;
                 };
-                recv_group_msg(group_msg_event, bridge_cfg, self.bridge.clone()).await
+                recv_group_msg(group_msg_event, &bridge_cfg, &self.adapter).await
             }
         };
         match res {
@@ -198,3 +176,17 @@ impl LoginEventProcess for DefaultHandler {
         OKK
     }
 }
+#[async_trait]
+impl GroupMessageRecallEventProcess for DefaultHandler {
+    async fn handle(&self, event: &GroupMessageRecallEvent) -> anyhow::Result<bool> {
+        let recall = &event.inner;
+        let gid = recall.group_code as u64;
+        let Some(bridge_cfg) = self.find_cfg_by_group(gid) else {
+            return OKK;
+        };
+        debug!("群({gid})撤回消息: {}", recall.msg_seq);
+        let qq_message_id = GroupMessageId::new(gid, recall.msg_seq);
+        self.adapter.send_recall_to_bridge(&OriginMessageId::QQ(qq_message_id), &bridge_cfg).await;
+        OKK
+    }
+}
@@ -1,26 +1,33 @@
+use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::Duration;
 
+use async_trait::async_trait;
 use proc_qq::re_exports::ricq::msg::MessageChain;
 use proc_qq::re_exports::ricq_core::msg::elem;
 use proc_qq::FileSessionStore;
 use proc_qq::{Authentication, ClientBuilder, DeviceSource, ModuleEventHandler, ModuleEventProcess, ShowQR};
-use tracing::{debug, error};
+use tokio::sync::{Mutex, OnceCell};
+use tracing::debug;
 
+use crate::bridge;
+use crate::bridge::{
+    avatar, BridgeAdapter, BridgeClient, BridgeClientPlatform, BridgeMessage, BridgeReaction, GroupMessageId, OriginMessageId,
+};
 use crate::bridge_qq::handler::DefaultHandler;
-use crate::{bridge, Config};
+use crate::config::{BridgeConfig, SharedConfig};
 use bridge::pojo::BridgeMessagePO;
 
-mod group_message_id;
 mod handler;
 
-use group_message_id::GroupMessageId;
-
 type RqClient = proc_qq::re_exports::ricq::Client;
 
+/// QQ 没有表情回应, 每隔一段时间汇总一次收到回应的消息
+const REACTION_SUMMARY_INTERVAL: Duration = Duration::from_secs(60);
+
 pub async fn upload_group_image(group_id: u64, url: &str, rq_client: Arc<RqClient>) -> anyhow::Result<elem::GroupImage> {
-    let client = reqwest::Client::new();
-    let stream = client.get(url).send().await?;
-    let img_bytes = stream.bytes().await.unwrap();
+    // 缓存的头像读取本地文件
+    let img_bytes = avatar::to_image(url).load_data().await?;
     let group_image = rq_client.upload_group_image(group_id as i64, img_bytes.as_ref()).await?;
     Ok(group_image)
 }
@@ -37,7 +44,7 @@ async fn proc_at(target: &str, send_content: &mut MessageChain) {
     }
     let bridge_user = bridge_user.unwrap();
     // 查看桥关联的本平台用户id
-    if let Some(ref_user) = bridge_user.find_by_platform("QQ").await {
+    if let Some(ref_user) = bridge_user.find_by_platform(&BridgeClientPlatform::QQ.to_string()).await {
         if let Ok(origin_id) = ref_user.origin_id.parse::<i64>() {
             send_content.push(elem::At::new(origin_id));
             return;
@@ -50,111 +57,187 @@ async fn proc_at(target: &str, send_content: &mut MessageChain) {
 /**
  * 同步消息方法
  */
-pub async fn sync_message(bridge: Arc<bridge::BridgeClient>, rq_client: Arc<RqClient>) {
-    let mut subs = bridge.sender.subscribe();
+async fn send_message(adapter: &QQAdapter, rq_client: Arc<RqClient>, message: &BridgeMessage) -> anyhow::Result<Option<OriginMessageId>> {
     let bot_id = rq_client.uin().await;
-    loop {
-        let message = match subs.recv().await {
-            Ok(m) => m,
-            Err(err) => {
-                error!(?err, "[{bot_id}] 消息同步失败");
-                continue;
-            }
-        };
-
-        let mut send_content = MessageChain::default();
+    let mut send_content = MessageChain::default();
 
-        // 配置发送者头像
-        if let Some(avatar_url) = &message.avatar_url {
-            debug!("用户头像: {:?}", message.avatar_url);
-            let image = upload_group_image(message.bridge_config.qqGroup, avatar_url, rq_client.clone()).await;
-            if let Result::Ok(image) = image {
-                send_content.push(image);
-            }
+    // 配置发送者头像
+    if let Some(avatar_url) = &message.avatar_url {
+        debug!("用户头像: {:?}", message.avatar_url);
+        let image = upload_group_image(message.bridge_config.qqGroup, avatar_url, rq_client.clone()).await;
+        if let Result::Ok(image) = image {
+            send_content.push(image);
         }
-        let bridge_user = bridge::manager::BRIDGE_USER_MANAGER.lock().await.get(&message.sender_id).await;
-        // 配置发送者用户名
-        send_content.push(elem::Text::new(format!("{}\n", bridge_user.unwrap().to_string())));
+    }
+    let bridge_user = bridge::manager::BRIDGE_USER_MANAGER.lock().await.get(&message.sender_id).await;
+    // 配置发送者用户名
+    send_content.push(elem::Text::new(format!("{}\n", bridge_user.unwrap().to_string())));
 
-        for chain in &message.message_chain {
-            match chain {
-                bridge::MessageContent::Reply { id } => {
-                    if let Some(id) = id {
-                        let reply_message = bridge::manager::BRIDGE_MESSAGE_MANAGER.lock().await.get(id).await;
-                        if let Some(reply_message) = reply_message {
-                            to_reply_content(&mut send_content, reply_message, bot_id).await
-                        } else {
-                            send_content.push(elem::Text::new("> {回复消息}\n".to_string()));
-                        }
+    for chain in &message.message_chain {
+        match chain {
+            bridge::MessageContent::Reply { id } => {
+                if let Some(id) = id {
+                    let reply_message = bridge::manager::BRIDGE_MESSAGE_MANAGER.lock().await.get(id).await;
+                    if let Some(reply_message) = reply_message {
+                        to_reply_content(adapter, &message.bridge_config, &mut send_content, reply_message, bot_id).await
+                    } else {
+                        send_content.push(elem::Text::new("> {回复消息}\n".to_string()));
                     }
                 }
-                // 桥文本 转 qq文本
-                bridge::MessageContent::Plain { text } => {
-                    let mention_text_list = parse_text_mention_rule(text.to_string());
-                    for mention_text in mention_text_list {
-                        match mention_text {
-                            MentionText::Text(text) => send_content.push(elem::Text::new(text)),
-                            MentionText::MentionText { id, .. } => send_content.push(elem::At::new(id)),
-                        }
+            }
+            // 桥文本 转 qq文本
+            bridge::MessageContent::Plain { text } => {
+                let mention_text_list = parse_text_mention_rule(text.to_string());
+                for mention_text in mention_text_list {
+                    match mention_text {
+                        MentionText::Text(text) => send_content.push(elem::Text::new(text)),
+                        MentionText::MentionText { id, .. } => send_content.push(elem::At::new(id)),
                     }
                 }
-                // @桥用户 转 @qq用户 或 @文本
-                bridge::MessageContent::At { id } => proc_at(id, &mut send_content).await,
-                // 桥图片 转 qq图片
-                bridge::MessageContent::Image(image) => {
-                    debug!("桥消息-图片: {:?}", image);
-                    match image.clone().load_data().await {
-                        Ok(data) => match rq_client
-                            .upload_group_image(message.bridge_config.qqGroup as i64, data.as_slice())
-                            .await
-                        {
-                            Ok(image) => {
-                                send_content.push(image);
-                            }
-                            Err(_) => {}
-                        },
+            }
+            // @桥用户 转 @qq用户 或 @文本
+            bridge::MessageContent::At { id } => proc_at(id, &mut send_content).await,
+            // 桥图片 转 qq图片
+            bridge::MessageContent::Image(image) => {
+                debug!("桥消息-图片: {:?}", image);
+                match image.clone().load_data().await {
+                    Ok(data) => match rq_client
+                        .upload_group_image(message.bridge_config.qqGroup as i64, data.as_slice())
+                        .await
+                    {
+                        Ok(image) => {
+                            send_content.push(image);
+                        }
                         Err(_) => {}
-                    }
+                    },
+                    Err(_) => {}
                 }
-                _ => send_content.push(elem::Text::new("{未处理的桥信息}".to_string())),
             }
+            _ => send_content.push(elem::Text::new("{未处理的桥信息}".to_string())),
         }
-        debug!("[QQ] 同步消息");
-        debug!("{:?}", send_content);
-        debug!("{:?}", message.bridge_config.qqGroup as i64);
+    }
+    debug!("[QQ] 同步消息");
+    debug!("{:?}", send_content);
+    debug!("{:?}", message.bridge_config.qqGroup as i64);
 
-        // seqs: [6539], rands: [1442369605], time: 1678267174
-        // rq_client.send_message(routing_head, message_chain, ptt);
+    // seqs: [6539], rands: [1442369605], time: 1678267174
+    // rq_client.send_message(routing_head, message_chain, ptt);
 
-        let result = rq_client
-            .send_group_message(message.bridge_config.qqGroup as i64, send_content)
-            .await
-            .ok();
-        if let Some(receipt) = result {
-            // 发送成功后, 将平台消息和桥消息进行关联, 为以后进行回复功能
-            let seqs = receipt.seqs.first().unwrap().clone();
-            let group_message_id = GroupMessageId {
-                group_id: message.bridge_config.qqGroup,
-                seqs,
-            };
-            bridge::manager::BRIDGE_MESSAGE_MANAGER
-                .lock()
-                .await
-                .ref_bridge_message(bridge::pojo::BridgeMessageRefMessageForm {
-                    bridge_message_id: message.id,
-                    platform: "QQ".to_string(),
-                    origin_id: group_message_id.to_string(),
-                })
-                .await;
+    let receipt = rq_client
+        .send_group_message(message.bridge_config.qqGroup as i64, send_content)
+        .await?;
+    let Some(seqs) = receipt.seqs.first() else {
+        return Ok(None);
+    };
+    let group_message_id = GroupMessageId::with_rand(message.bridge_config.qqGroup, *seqs, receipt.rands.first().copied());
+    Ok(Some(OriginMessageId::QQ(group_message_id)))
+}
+
+/// QQ 平台适配器
+pub struct QQAdapter {
+    config: Arc<SharedConfig>,
+    bridge: Arc<BridgeClient>,
+    rq_client: OnceCell<Arc<RqClient>>,
+    /// 等待汇总回应的桥消息, 按桥消息id和qq群记录
+    pending_reactions: Mutex<HashMap<(String, u64), BridgeMessage>>,
+}
+
+impl QQAdapter {
+    pub fn new(config: Arc<SharedConfig>, bridge: Arc<BridgeClient>) -> Self {
+        QQAdapter {
+            config,
+            bridge,
+            rq_client: OnceCell::new(),
+            pending_reactions: Mutex::new(HashMap::new()),
         }
     }
 }
 
+#[async_trait]
+impl BridgeAdapter for QQAdapter {
+    fn platform(&self) -> BridgeClientPlatform {
+        BridgeClientPlatform::QQ
+    }
+
+    fn bridge(&self) -> &Arc<BridgeClient> {
+        &self.bridge
+    }
+
+    async fn start(self: Arc<Self>) {
+        start(self).await
+    }
+
+    async fn send_message(&self, message: &BridgeMessage) -> anyhow::Result<Option<OriginMessageId>> {
+        let Some(rq_client) = self.rq_client.get() else {
+            return Err(anyhow::anyhow!("[QQ] 客户端未登录"));
+        };
+        send_message(self, rq_client.clone(), message).await
+    }
+
+    /// QQ 不能编辑消息, 补发一条"(已编辑)"的消息
+    async fn edit_message(&self, message: &BridgeMessage, _origin_id: &OriginMessageId) -> anyhow::Result<()> {
+        let Some(rq_client) = self.rq_client.get() else {
+            return Err(anyhow::anyhow!("[QQ] 客户端未登录"));
+        };
+        let mut message_chain = vec![bridge::MessageContent::Plain {
+            text: "(已编辑) ".to_string(),
+        }];
+        let text = message
+            .message_chain
+            .iter()
+            .filter(|chain| !matches!(chain, bridge::MessageContent::Reply { .. } | bridge::MessageContent::Image(..)));
+        message_chain.extend(text.cloned());
+        let follow_up = BridgeMessage {
+            message_chain,
+            ..message.clone()
+        };
+        // 补发的消息也关联到桥消息, 回复它时能找到原消息
+        if let Some(origin_id) = send_message(self, rq_client.clone(), &follow_up).await? {
+            self.record_origin(message, origin_id).await;
+        }
+        Ok(())
+    }
+
+    async fn recall_message(&self, _message: &BridgeMessage, origin_id: &OriginMessageId) -> anyhow::Result<()> {
+        let Some(rq_client) = self.rq_client.get() else {
+            return Err(anyhow::anyhow!("[QQ] 客户端未登录"));
+        };
+        let OriginMessageId::QQ(group_message_id) = origin_id else {
+            return Err(anyhow::anyhow!("不是QQ消息id: {origin_id}"));
+        };
+        let Some(rand) = group_message_id.rand else {
+            tracing::warn!("[QQ] 消息没有记录rand, 无法撤回: {origin_id}");
+            return Ok(());
+        };
+        rq_client
+            .recall_group_message(group_message_id.group_id as i64, vec![group_message_id.seqs], vec![rand])
+            .await?;
+        Ok(())
+    }
+
+    /// QQ 不能回应消息, 记下消息等待定时汇总, 见 [`summary_reactions`]
+    async fn react_message(
+        &self,
+        message: &BridgeMessage,
+        _origin_id: &OriginMessageId,
+        _reaction: &BridgeReaction,
+        _added: bool,
+        _reactions: &[BridgeReaction],
+    ) -> anyhow::Result<()> {
+        self.pending_reactions
+            .lock()
+            .await
+            .insert((message.id.clone(), message.bridge_config.qqGroup), message.clone());
+        Ok(())
+    }
+}
+
 /**
  * 消息桥构建入口
  */
-pub async fn start(config: Arc<Config>, bridge: Arc<bridge::BridgeClient>) {
+async fn start(adapter: Arc<QQAdapter>) {
     tracing::info!("[QQ] 初始化QQ桥");
+    let config = adapter.config.current();
     // 确认配置无误
     let auth = match config.qq_config.get_auth() {
         Ok(a) => a,
@@ -172,21 +255,27 @@ pub async fn start(config: Arc<Config>, bridge: Arc<bridge::BridgeClient>) {
     };
 
     let handler = DefaultHandler {
-        config: config.clone(),
-        bridge: bridge.clone(),
-        origin_client: None,
+        config: adapter.config.clone(),
+        adapter: adapter.clone(),
     };
     let handler = Box::new(handler);
     let on_message = ModuleEventHandler {
         name: "OnMessage".to_owned(),
         process: ModuleEventProcess::Message(handler),
     };
+    let on_recall = ModuleEventHandler {
+        name: "OnGroupMessageRecall".to_owned(),
+        process: ModuleEventProcess::GroupMessageRecall(Box::new(DefaultHandler {
+            config: adapter.config.clone(),
+            adapter: adapter.clone(),
+        })),
+    };
 
     // let modules = module!("qq_bridge", "qq桥模块", handler);
     let module = proc_qq::Module {
         id: "qq_bridge".to_string(),
         name: "qq桥模块".to_string(),
-        handles: vec![on_message],
+        handles: vec![on_message, on_recall],
     };
 
     let mut show_qr = ShowQR::OpenBySystem;
@@ -207,47 +296,90 @@ pub async fn start(config: Arc<Config>, bridge: Arc<bridge::BridgeClient>) {
         .await
         .unwrap();
     let arc = Arc::new(client);
+    let _ = adapter.rq_client.set(arc.rq_client.clone());
     tokio::select! {
         Err(e) = proc_qq::run_client(arc.clone()) => {
             tracing::error!(err = ?e, "[QQ] QQ客户端退出");
         },
-        _ = sync_message(bridge.clone(), arc.rq_client.clone()) => {
+        _ = bridge::adapter::sync_message(adapter.as_ref()) => {
             tracing::warn!("[QQ] QQ桥关闭");
         },
+        _ = summary_reactions(adapter.as_ref()) => {},
     }
 }
 
 /**
- * 申请桥用户
+ * 定时汇总消息收到的回应, 回复原消息: "👍×3 ❤×1"
  */
-async fn apply_bridge_user(id: u64, name: &str) -> bridge::user::BridgeUser {
-    let bridge_user = bridge::manager::BRIDGE_USER_MANAGER
-        .lock()
-        .await
-        .likeAndSave(bridge::pojo::BridgeUserSaveForm {
-            origin_id: id.to_string(),
-            platform: "QQ".to_string(),
-            display_text: format!("{}({})", name, id),
-        })
-        .await;
-    bridge_user.unwrap()
+async fn summary_reactions(adapter: &QQAdapter) {
+    let mut interval = tokio::time::interval(REACTION_SUMMARY_INTERVAL);
+    loop {
+        interval.tick().await;
+        let pending = std::mem::take(&mut *adapter.pending_reactions.lock().await);
+        let Some(rq_client) = adapter.rq_client.get() else {
+            continue;
+        };
+        for ((id, _), message) in pending {
+            let Some(po) = bridge::manager::BRIDGE_MESSAGE_MANAGER.lock().await.get(&id).await else {
+                continue;
+            };
+            let summary = to_reaction_summary(&po.reactions);
+            if summary.is_empty() {
+                continue;
+            }
+            let bot_id = rq_client.uin().await;
+            let mut send_content = MessageChain::default();
+            to_reply_content(adapter, &message.bridge_config, &mut send_content, po, bot_id).await;
+            send_content.push(elem::Text::new(summary));
+            if let Err(err) = rq_client
+                .send_group_message(message.bridge_config.qqGroup as i64, send_content)
+                .await
+            {
+                tracing::error!(?err, "[QQ] 发送回应汇总失败: {id}");
+            }
+        }
+    }
+}
+
+/**
+ * 按表情统计回应数量, 保持表情首次出现的顺序
+ */
+fn to_reaction_summary(reactions: &[BridgeReaction]) -> String {
+    let mut counts: Vec<(String, usize)> = vec![];
+    for reaction in reactions {
+        let emoji = reaction.display();
+        match counts.iter_mut().find(|(e, _)| *e == emoji) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((emoji, 1)),
+        }
+    }
+    counts
+        .into_iter()
+        .map(|(emoji, count)| format!("{emoji}×{count}"))
+        .collect::<Vec<String>>()
+        .join(" ")
 }
 
 /**
  * 处理回复
  */
-async fn to_reply_content(message_chain: &mut MessageChain, reply_message: BridgeMessagePO, uni: i64) {
-    let refs = reply_message.refs.iter().find(|refs| refs.platform.eq("QQ"));
+async fn to_reply_content(
+    adapter: &QQAdapter,
+    bridge_config: &BridgeConfig,
+    message_chain: &mut MessageChain,
+    reply_message: BridgeMessagePO,
+    uni: i64,
+) {
+    let origin_id = adapter.find_origin_id(&reply_message, bridge_config);
     let bridge_user = bridge::manager::BRIDGE_USER_MANAGER
         .lock()
         .await
         .get(&reply_message.sender_id)
         .await
         .unwrap();
-    if let Some(refs) = refs {
-        let group_message_id = GroupMessageId::from_bridge_message_id(&refs.origin_id);
+    if let Some(OriginMessageId::QQ(group_message_id)) = origin_id {
         let mut reply_content = MessageChain::default();
-        let sender: i64 = if bridge_user.platform == "QQ" {
+        let sender: i64 = if bridge_user.platform == adapter.platform().to_string() {
             bridge_user.origin_id.parse::<i64>().unwrap()
         } else {
             uni
@@ -332,3 +464,20 @@ pub fn parse_text_mention_rule(text: String) -> Vec<MentionText> {
     println!("parse_text_mention_rule: {:?}", chain);
     chain
 }
+
+#[test]
+fn test_reaction_summary() {
+    let reaction = |emoji: &str, sender_id: &str| BridgeReaction {
+        emoji: emoji.to_string(),
+        sender_id: sender_id.to_string(),
+        platform: "DC".to_string(),
+    };
+    let reactions = vec![
+        reaction("👍", "1"),
+        reaction("<:pepe:123>", "1"),
+        reaction("👍", "2"),
+        reaction("👍", "3"),
+    ];
+    assert_eq!(to_reaction_summary(&reactions), "👍×3 :pepe:×1");
+    assert_eq!(to_reaction_summary(&[]), "");
+}
@@ -0,0 +1,190 @@
+//! TG相册
+//!
+//! 相册中的每张图片都是一条单独的消息，带有相同的 `grouped_id`，分别到达；
+//! 等相册最后一条消息到达一段时间后，合并为一条桥消息发送
+
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+use crate::bridge;
+use crate::bridge::pojo::BridgeMessageRefMessageForm;
+use crate::bridge::{BridgeAdapter, MessageContent, OriginMessageId};
+use crate::config::BridgeConfig;
+
+use super::TelegramAdapter;
+
+/// 最后一条消息到达后等待的时间
+const ALBUM_WAIT: Duration = Duration::from_millis(1500);
+
+/// 相册中的一条消息
+struct AlbumItem {
+    origin_id: OriginMessageId,
+    message_chain: Vec<MessageContent>,
+}
+
+struct PendingAlbum {
+    /// 发送者的桥用户id和头像
+    sender: (String, Option<String>),
+    bridge_config: BridgeConfig,
+    items: Vec<AlbumItem>,
+    updated: Instant,
+}
+
+lazy_static! {
+    /// (会话id, grouped_id) -> 等待合并的相册
+    static ref ALBUMS: Mutex<HashMap<(i64, i64), PendingAlbum>> = Mutex::new(HashMap::new());
+}
+
+/// 收到相册中的一条消息
+pub async fn push(
+    adapter: Arc<TelegramAdapter>,
+    bridge_config: BridgeConfig,
+    sender: (String, Option<String>),
+    origin_id: OriginMessageId,
+    grouped_id: i64,
+    message_chain: Vec<MessageContent>,
+) {
+    let OriginMessageId::Telegram { chat_id, .. } = origin_id else {
+        return;
+    };
+    let key = (chat_id, grouped_id);
+    let mut albums = ALBUMS.lock().await;
+    let item = AlbumItem { origin_id, message_chain };
+    if let Some(album) = albums.get_mut(&key) {
+        album.items.push(item);
+        album.updated = Instant::now();
+        return;
+    }
+    albums.insert(
+        key,
+        PendingAlbum {
+            sender,
+            bridge_config,
+            items: vec![item],
+            updated: Instant::now(),
+        },
+    );
+    tokio::spawn(flush(adapter, key));
+}
+
+/// 等待相册的消息全部到达后发送到桥
+async fn flush(adapter: Arc<TelegramAdapter>, key: (i64, i64)) {
+    let album = loop {
+        let mut albums = ALBUMS.lock().await;
+        let Some(album) = albums.get(&key) else {
+            return;
+        };
+        let wait = ALBUM_WAIT.saturating_sub(album.updated.elapsed());
+        if wait.is_zero() {
+            break albums.remove(&key).unwrap();
+        }
+        drop(albums);
+        tokio::time::sleep(wait).await;
+    };
+    let (origin_id, others, message_chain) = merge(album.items);
+    let bridge_config = album.bridge_config;
+    let endpoint = bridge_config.endpoint(adapter.platform()).map(|endpoint| endpoint.name.clone());
+    if message_chain.is_empty() {
+        return;
+    }
+    let form = super::to_bridge_form(&adapter, bridge_config, album.sender, origin_id.clone(), message_chain);
+    adapter.send_to_bridge(form).await;
+    if others.is_empty() {
+        return;
+    }
+    // 相册的其它消息也关联到这条桥消息, 回复或删除其中任一条都能找到
+    let Ok(Some(message)) = adapter.find_by_origin(&origin_id).await else {
+        return;
+    };
+    let mut manager = bridge::manager::BRIDGE_MESSAGE_MANAGER.lock().await;
+    for origin_id in others {
+        manager
+            .ref_bridge_message(BridgeMessageRefMessageForm {
+                bridge_message_id: message.id.clone(),
+                platform: adapter.platform().to_string(),
+                origin_id,
+                endpoint: endpoint.clone(),
+            })
+            .await;
+    }
+}
+
+/// # 合并相册的消息
+/// 回复放在最前, 然后是全部图片和说明文字; 带说明文字的消息作为桥消息关联的主消息, 都没有时取第一条
+/// ### Return
+/// (主消息id, 其它消息id, 合并后的消息链)
+fn merge(mut items: Vec<AlbumItem>) -> (OriginMessageId, Vec<OriginMessageId>, Vec<MessageContent>) {
+    let message_id = |item: &AlbumItem| match item.origin_id {
+        OriginMessageId::Telegram { message_id, .. } => message_id,
+        _ => 0,
+    };
+    items.sort_by_key(message_id);
+    let is_text = |chain: &MessageContent| !matches!(chain, MessageContent::Reply { .. } | MessageContent::Image(..));
+    let main = items.iter().position(|item| item.message_chain.iter().any(is_text)).unwrap_or(0);
+    let main_id = items[main].origin_id.clone();
+    let mut reply = None;
+    let mut images = vec![];
+    let mut texts = vec![];
+    let mut others = vec![];
+    for (index, item) in items.into_iter().enumerate() {
+        if index != main {
+            others.push(item.origin_id);
+        }
+        for chain in item.message_chain {
+            match chain {
+                MessageContent::Reply { .. } => {
+                    reply.get_or_insert(chain);
+                }
+                MessageContent::Image(..) => images.push(chain),
+                _ => texts.push(chain),
+            }
+        }
+    }
+    (main_id, others, reply.into_iter().chain(images).chain(texts).collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bridge::Image;
+
+    fn item(message_id: i32, message_chain: Vec<MessageContent>) -> AlbumItem {
+        AlbumItem {
+            origin_id: OriginMessageId::Telegram { chat_id: 1, message_id },
+            message_chain,
+        }
+    }
+
+    #[test]
+    fn test_merge() {
+        let image = || MessageContent::Image(Image::Buff(vec![]));
+        let items = vec![
+            item(13, vec![image()]),
+            item(
+                12,
+                vec![
+                    image(),
+                    MessageContent::Plain {
+                        text: "说明".to_string()
+                    },
+                ],
+            ),
+            item(11, vec![MessageContent::Reply { id: None }, image()]),
+        ];
+        let (main, others, message_chain) = merge(items);
+        assert_eq!(
+            main,
+            OriginMessageId::Telegram {
+                chat_id: 1,
+                message_id: 12
+            }
+        );
+        assert_eq!(others.len(), 2);
+        assert_eq!(message_chain.len(), 5);
+        assert!(matches!(message_chain[0], MessageContent::Reply { .. }));
+        assert!(matches!(message_chain[4], MessageContent::Plain { .. }));
+    }
+}
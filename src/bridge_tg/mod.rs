@@ -3,24 +3,170 @@ use std::path::Path;
 use std::sync::Arc;
 
 use anyhow::Result;
+use async_trait::async_trait;
 use lazy_static::lazy_static;
 use proc_qq::re_exports::image;
-use teleser::re_exports::async_trait::async_trait;
-use teleser::re_exports::grammers_client::types::{Chat, Media, Message};
-use teleser::re_exports::grammers_client::{Client, InitParams, InputMessage};
+use teleser::re_exports::grammers_client::types::{Chat, Media, Message, MessageDeletion, Uploaded, User};
+use teleser::re_exports::grammers_client::{Client, InitParams, InputMedia, InputMessage, InvocationError};
 use teleser::re_exports::grammers_session::PackedChat;
+use teleser::re_exports::grammers_tl_types as tl;
 use teleser::re_exports::grammers_tl_types::enums::MessageEntity;
-use teleser::{Auth, ClientBuilder, FileSessionStore, NewMessageProcess, Process, StaticBotToken};
+use teleser::{
+    Auth, ClientBuilder, FileSessionStore, MessageDeletedProcess, MessageEditedProcess, NewMessageProcess, Process, RawProcess,
+    StaticBotToken,
+};
 use tokio::io::AsyncWriteExt;
-use tokio::sync::Mutex;
+use tokio::sync::{Mutex, OnceCell};
 use tracing::{debug, error, warn};
 
 use crate::bridge;
+use crate::bridge::pojo::BridgeUserSaveForm;
+use crate::bridge::user::BridgeUser;
 use crate::bridge::MessageContent::Plain;
-use crate::bridge::{BridgeClient, Image, MessageContent};
-use crate::config::{BridgeConfig, Config};
+use crate::bridge::{
+    avatar, BridgeAdapter, BridgeClient, BridgeClientPlatform, BridgeMessage, BridgeReaction, Image, MessageContent, OriginMessageId,
+};
+use crate::config::{BridgeConfig, SharedConfig};
 
-pub async fn start(config: Arc<Config>, bridge: Arc<bridge::BridgeClient>) {
+use user_cache::{TgUser, TG_USER_CACHE};
+
+mod album;
+mod user_cache;
+
+/// TG拒绝回应的错误, 不重试
+const REACTION_REJECTED: [&str; 2] = ["REACTIONS_TOO_MANY", "REACTION_INVALID"];
+
+/// Telegram 平台适配器
+pub struct TelegramAdapter {
+    config: Arc<SharedConfig>,
+    bridge: Arc<BridgeClient>,
+    client: OnceCell<Arc<teleser::Client>>,
+}
+
+impl TelegramAdapter {
+    pub fn new(config: Arc<SharedConfig>, bridge: Arc<BridgeClient>) -> Self {
+        TelegramAdapter {
+            config,
+            bridge,
+            client: OnceCell::new(),
+        }
+    }
+
+    /**
+     * 申请桥用户
+     * 用户改名后更新显示文本 `姓名(id)`
+     */
+    pub async fn apply_tg_user(&self, id: i64, display_text: &str) -> anyhow::Result<BridgeUser> {
+        let form = BridgeUserSaveForm {
+            origin_id: id.to_string(),
+            platform: self.platform().to_string(),
+            display_text: display_text.to_string(),
+        };
+        let user = bridge::manager::BRIDGE_USER_MANAGER.lock().await.likeAndUpdate(form).await;
+        user.map_err(|err| anyhow::anyhow!("申请桥用户失败: {err}"))
+    }
+}
+
+#[async_trait]
+impl BridgeAdapter for TelegramAdapter {
+    fn platform(&self) -> BridgeClientPlatform {
+        BridgeClientPlatform::Telegram
+    }
+
+    fn bridge(&self) -> &Arc<BridgeClient> {
+        &self.bridge
+    }
+
+    async fn start(self: Arc<Self>) {
+        start(self).await
+    }
+
+    async fn send_message(&self, message: &BridgeMessage) -> Result<Option<OriginMessageId>> {
+        let Some(client) = self.client.get() else {
+            return Err(anyhow::anyhow!("[TG] 客户端未登录"));
+        };
+        send_message(self, client, message).await
+    }
+
+    async fn edit_message(&self, message: &BridgeMessage, origin_id: &OriginMessageId) -> Result<()> {
+        let Some(client) = self.client.get() else {
+            return Err(anyhow::anyhow!("[TG] 客户端未登录"));
+        };
+        edit_message(self, client, message, origin_id).await
+    }
+
+    async fn recall_message(&self, _message: &BridgeMessage, origin_id: &OriginMessageId) -> Result<()> {
+        let Some(client) = self.client.get() else {
+            return Err(anyhow::anyhow!("[TG] 客户端未登录"));
+        };
+        let OriginMessageId::Telegram { chat_id, message_id } = *origin_id else {
+            return Err(anyhow::anyhow!("不是TG消息id: {origin_id}"));
+        };
+        let chat = get_chat(chat_id).await?;
+        let inner_client = get_inner_client(client).await?;
+        inner_client.delete_messages(chat, &[message_id]).await?;
+        Ok(())
+    }
+
+    /// # bot的回应即其它平台的回应
+    /// 非会员的bot每条消息只能回应一个表情, 每次同步都设置为其它平台最近的一个 unicode 回应, 没有时清除
+    async fn react_message(
+        &self,
+        _message: &BridgeMessage,
+        origin_id: &OriginMessageId,
+        _reaction: &BridgeReaction,
+        _added: bool,
+        reactions: &[BridgeReaction],
+    ) -> Result<()> {
+        let Some(client) = self.client.get() else {
+            return Err(anyhow::anyhow!("[TG] 客户端未登录"));
+        };
+        let OriginMessageId::Telegram { chat_id, message_id } = *origin_id else {
+            return Err(anyhow::anyhow!("不是TG消息id: {origin_id}"));
+        };
+        let platform = self.platform().to_string();
+        // TG 只能回应 unicode emoji
+        let reaction = reactions
+            .iter()
+            .rev()
+            .find(|reaction| reaction.platform != platform && reaction.is_unicode())
+            .map(|reaction| {
+                tl::enums::Reaction::Emoji(tl::types::ReactionEmoji {
+                    emoticon: reaction.emoji.clone(),
+                })
+            })
+            .into_iter()
+            .collect();
+        let chat = get_chat(chat_id).await?;
+        let inner_client = get_inner_client(client).await?;
+        let result = inner_client
+            .invoke(&tl::functions::messages::SendReaction {
+                big: false,
+                add_to_recent: false,
+                peer: chat.to_input_peer(),
+                msg_id: message_id,
+                reaction: Some(reaction),
+            })
+            .await;
+        match result {
+            Ok(_) => Ok(()),
+            // 群组不允许该表情等, 重试也不会成功
+            Err(InvocationError::Rpc(err)) if REACTION_REJECTED.contains(&err.name.as_str()) => {
+                warn!("[TG] 回应被拒绝, 不再同步: {} {origin_id}", err.name);
+                Ok(())
+            }
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+/// TG 的消息id只在会话内唯一, 由会话id和消息id组成关联id
+fn to_origin_id(chat_id: i64, message_id: i32) -> OriginMessageId {
+    OriginMessageId::Telegram { chat_id, message_id }
+}
+
+async fn start(adapter: Arc<TelegramAdapter>) {
+    let config = adapter.config.current();
     // 还原pack
     let folder = format!(
         "tg.pack.{}",
@@ -47,14 +193,37 @@ pub async fn start(config: Arc<Config>, bridge: Arc<bridge::BridgeClient>) {
     let module = teleser::Module {
         id: "tg_new_message".to_owned(),
         name: "tg_new_message".to_owned(),
-        handlers: vec![teleser::Handler {
-            id: "tg_new_message".to_owned(),
-            process: Process::NewMessageProcess(Box::new(TgNewMessage {
-                config: config.clone(),
-                bridge: bridge.clone(),
-                pack_folder: folder.clone(),
-            })),
-        }],
+        handlers: vec![
+            teleser::Handler {
+                id: "tg_new_message".to_owned(),
+                process: Process::NewMessageProcess(Box::new(TgNewMessage {
+                    config: adapter.config.clone(),
+                    adapter: adapter.clone(),
+                    pack_folder: folder.clone(),
+                })),
+            },
+            teleser::Handler {
+                id: "tg_message_edited".to_owned(),
+                process: Process::MessageEditedProcess(Box::new(TgMessageEdited {
+                    config: adapter.config.clone(),
+                    adapter: adapter.clone(),
+                })),
+            },
+            teleser::Handler {
+                id: "tg_message_deleted".to_owned(),
+                process: Process::MessageDeletedProcess(Box::new(TgMessageDeleted {
+                    config: adapter.config.clone(),
+                    adapter: adapter.clone(),
+                })),
+            },
+            teleser::Handler {
+                id: "tg_message_reaction".to_owned(),
+                process: Process::RawProcess(Box::new(TgMessageReaction {
+                    config: adapter.config.clone(),
+                    adapter: adapter.clone(),
+                })),
+            },
+        ],
     };
     let client = ClientBuilder::new()
         .with_api_id(config.telegram_config.apiId.clone())
@@ -77,31 +246,24 @@ pub async fn start(config: Arc<Config>, bridge: Arc<bridge::BridgeClient>) {
         .build()
         .unwrap();
     let arc = Arc::new(client);
+    let _ = adapter.client.set(arc.clone());
     tokio::select! {
         _ = teleser::run_client_and_reconnect(arc.clone()) => {
             tracing::warn!("[TG] TG客户端退出");
         },
-        _ = sync_message(bridge.clone(), arc) => {
+        _ = bridge::adapter::sync_message(adapter.as_ref()) => {
             tracing::warn!("[TG] TG桥关闭");
         },
     }
 }
 
 pub struct TgNewMessage {
-    pub config: Arc<Config>,
-    pub bridge: Arc<BridgeClient>,
+    pub config: Arc<SharedConfig>,
+    pub adapter: Arc<TelegramAdapter>,
     pub pack_folder: String,
 }
 
 impl TgNewMessage {
-    fn find_cfg_by_group(&self, group_id: i64) -> Option<&BridgeConfig> {
-        let bridge_config = self
-            .config
-            .bridges
-            .iter()
-            .find(|b| group_id == b.tgGroup && b.enable);
-        Some(bridge_config?)
-    }
     async fn pack_chat(&self, event: &Message) {
         let chat = event.chat();
         let mut lock = PACK_MAP.lock().await;
@@ -121,209 +283,632 @@ impl TgNewMessage {
 impl NewMessageProcess for TgNewMessage {
     async fn handle(&self, client: &mut Client, event: &Message) -> Result<bool> {
         self.pack_chat(event).await;
-        if !event.outgoing() {
-            if let Chat::Group(group) = event.chat() {
-                if let Some(config) = self.find_cfg_by_group(group.id()) {
-                    if let Some(Chat::User(user)) = event.sender() {
-                        // 为发送者申请桥用户
-                        let bridge_user = apply_bridge_user(user.id(), user.full_name()).await;
-                        // 组装向桥发送的消息体表单
-                        let mut bridge_message = bridge::pojo::BridgeSendMessageForm {
-                            sender_id: bridge_user.id,
-                            avatar_url: None,
-                            bridge_config: config.clone(),
-                            message_chain: Vec::new(),
-                            origin_message: bridge::pojo::BridgeMessageRefPO {
-                                origin_id: "".to_string(),
-                                platform: "TG".to_string(),
-                            },
-                        };
-                        // 下载图片
-                        let media = event.media();
-                        if let Some(Media::Photo(_)) = &media {
-                            // download media 存在一定时间以后不能使用的BUG, 已经使用临时仓库解决
-                            // see: https://github.com/Lonami/grammers/issues/166
-                            match download_media(client, &media.unwrap()).await {
-                                Ok(data) => bridge_message
-                                    .message_chain
-                                    .push(MessageContent::Image(Image::Buff(data))),
-                                Err(err) => {
-                                    error!("下载TG图片失败 : {:?}", err)
-                                }
-                            }
-                        }
-                        if !event.text().is_empty() {
-                            if let Some(entities) = event.fmt_entities() {
-                                let text = event.text();
-                                let mut offset: usize = 0;
-                                for x in entities {
-                                    match x {
-                                        MessageEntity::Mention(m) => {
-                                            // todo 用数据库和更新用户名和id的对应关系, 因为机器人api不允许使用用户名转id的功能
-                                            bridge_message.message_chain.push(Plain {
-                                                text: text[offset..(m.offset) as usize].to_string(),
-                                            });
-                                            offset = (m.offset + m.length) as usize;
-                                            // todo @
-                                            // bridge_message.message_chain.push(At {
-                                            // });
-                                            bridge_message.message_chain.push(Plain {
-                                                text: text[(m.offset as usize)
-                                                    ..((m.offset + m.length) as usize)]
-                                                    .to_string(),
-                                            });
-                                        }
-                                        MessageEntity::MentionName(m) => {
-                                            if offset < m.offset as usize {
-                                                bridge_message.message_chain.push(Plain {
-                                                    text: text[offset..(m.offset as usize)]
-                                                        .to_string(),
-                                                });
-                                                offset = (m.offset + m.length) as usize;
-                                                // todo @
-                                                // bridge_message.message_chain.push(At {
-                                                // });
-                                                bridge_message.message_chain.push(Plain {
-                                                    text: format!(
-                                                        "@{}",
-                                                        text[(m.offset as usize)
-                                                            ..((m.offset + m.length) as usize)]
-                                                            .to_string()
-                                                    ),
-                                                });
-                                            }
-                                        }
-                                        _ => {}
-                                    }
-                                }
-                            } else {
-                                bridge_message.message_chain.push(Plain {
-                                    text: event.text().to_owned(),
-                                });
-                            }
-                        }
-                        if !bridge_message.message_chain.is_empty() {
-                            self.bridge.send_message(bridge_message).await;
-                        }
-                    }
+        if event.outgoing() {
+            return Ok(false);
+        }
+        // 群、超级群和频道, 私聊不同步
+        let chat = event.chat();
+        if let Chat::User(_) = chat {
+            return Ok(false);
+        }
+        let Some(config) = self.config.current().find_by_tg_topic(chat.id(), topic_of(event)) else {
+            return Ok(false);
+        };
+        // 为发送者申请桥用户
+        let (bridge_user, avatar_url) = match event.sender() {
+            Some(Chat::User(user)) => {
+                remember_user(&user).await;
+                let bridge_user = self
+                    .adapter
+                    .apply_tg_user(user.id(), &format!("{}({})", user.full_name(), user.id()))
+                    .await;
+                (bridge_user, avatar_of(client, &user).await)
+            }
+            // 频道消息和匿名管理员的消息以会话本身为发送者
+            sender => {
+                let sender = sender.unwrap_or(chat.clone());
+                let bridge_user = self
+                    .adapter
+                    .apply_tg_user(sender.id(), &format!("{}({})", sender.name(), sender.id()))
+                    .await;
+                (bridge_user, None)
+            }
+        };
+        let bridge_user = match bridge_user {
+            Ok(bridge_user) => bridge_user,
+            Err(err) => {
+                error!(?err, "[TG] 消息同步失败");
+                return Ok(false);
+            }
+        };
+        let origin_id = to_origin_id(chat.id(), event.id());
+        let mut message_chain = vec![];
+        // 回复
+        if let Some(reply_id) = reply_of(event) {
+            let reply = self.adapter.to_bridge_reply(&to_origin_id(chat.id(), reply_id)).await;
+            message_chain.push(reply);
+        }
+        // 下载图片
+        let media = event.media();
+        if let Some(Media::Photo(_)) = &media {
+            // download media 存在一定时间以后不能使用的BUG, 已经使用临时仓库解决
+            // see: https://github.com/Lonami/grammers/issues/166
+            match download_media(client, &media.unwrap()).await {
+                Ok(data) => message_chain.push(MessageContent::Image(Image::Buff(data))),
+                Err(err) => {
+                    error!("下载TG图片失败 : {:?}", err)
                 }
             }
         }
+        message_chain.append(&mut to_bridge_chain(&self.adapter, event).await);
+        // 相册的每张图片是一条消息, 合并后再发送
+        if let Some(grouped_id) = event.grouped_id() {
+            let sender = (bridge_user.id, avatar_url);
+            album::push(self.adapter.clone(), config, sender, origin_id, grouped_id, message_chain).await;
+        } else if !message_chain.is_empty() {
+            let form = to_bridge_form(&self.adapter, config, (bridge_user.id, avatar_url), origin_id, message_chain);
+            self.adapter.send_to_bridge(form).await;
+        }
         Ok(false)
     }
 }
 
-lazy_static! {
-    static ref PACK_MAP: Mutex<HashMap::<i64, PackedChat>> = Mutex::new(HashMap::new());
+pub struct TgMessageEdited {
+    pub config: Arc<SharedConfig>,
+    pub adapter: Arc<TelegramAdapter>,
 }
 
-pub async fn sync_message(bridge: Arc<bridge::BridgeClient>, teleser_client: Arc<teleser::Client>) {
-    let mut subs = bridge.sender.subscribe();
-    loop {
-        let message = match subs.recv().await {
-            Ok(m) => m,
-            Err(err) => {
-                error!(?err, "[tg] 消息同步失败");
-                continue;
-            }
+#[async_trait]
+impl MessageEditedProcess for TgMessageEdited {
+    async fn handle(&self, _: &mut Client, event: &Message) -> Result<bool> {
+        if event.outgoing() {
+            return Ok(false);
+        }
+        let chat = event.chat();
+        if let Chat::User(_) = chat {
+            return Ok(false);
+        }
+        let Some(config) = self.config.current().find_by_tg_topic(chat.id(), topic_of(event)) else {
+            return Ok(false);
         };
-        // 配置发送者头像
-        if let Some(avatar_url) = &message.avatar_url {
-            debug!("用户头像: {:?}", avatar_url);
+        if let Some(Chat::User(user)) = event.sender() {
+            remember_user(&user).await;
         }
-        let bridge_user = bridge::manager::BRIDGE_USER_MANAGER
-            .lock()
-            .await
-            .get(&message.sender_id)
-            .await
-            .unwrap();
-        // telegram 每条消息只能带一个附件或一个图片
-        // 同时可以发一组图片消息，但是只有第一个图片消息可以带文字，文字会显示到一组消息的最下方
-        // todo 发送图片消息和 @
-        let mut builder = vec![];
-        let mut images = vec![];
-        for x in &message.message_chain {
-            match x {
-                MessageContent::Reply { .. } => {}
-                MessageContent::Plain { text } => builder.push(text.as_str()),
-                MessageContent::At { .. } => {
-                    // todo
+        let origin_id = to_origin_id(chat.id(), event.id());
+        let message_chain = to_bridge_chain(&self.adapter, event).await;
+        self.adapter.send_edit_to_bridge(&origin_id, &config, message_chain).await;
+        Ok(false)
+    }
+}
+
+pub struct TgMessageDeleted {
+    pub config: Arc<SharedConfig>,
+    pub adapter: Arc<TelegramAdapter>,
+}
+
+#[async_trait]
+impl MessageDeletedProcess for TgMessageDeleted {
+    async fn handle(&self, _: &mut Client, event: &MessageDeletion) -> Result<bool> {
+        let config = self.config.current();
+        // 超级群和频道的删除事件带会话id; 普通群的不带, 在配置的tg群中逐个查找
+        let chat_ids = match event.channel_id() {
+            Some(chat_id) => vec![chat_id],
+            None => config.tg_groups(),
+        };
+        for message_id in event.messages() {
+            let mut found = vec![];
+            for chat_id in &chat_ids {
+                let origin_id = to_origin_id(*chat_id, *message_id);
+                match self.adapter.find_by_origin(&origin_id).await {
+                    Ok(Some(_)) => found.push((*chat_id, origin_id)),
+                    Ok(None) => {}
+                    Err(err) => warn!("[TG] 同步撤回失败: {err}"),
                 }
-                MessageContent::AtAll => {}
-                MessageContent::Image(image) => images.push(image),
-                MessageContent::Err { .. } => {}
-                MessageContent::Othen => {}
             }
+            // 多个群都有该id的消息时无法确定被删除的是哪一条
+            if found.len() > 1 {
+                debug!("[TG] 删除的消息 {message_id} 在多个群中都有关联, 不同步撤回");
+                continue;
+            }
+            let Some((chat_id, origin_id)) = found.pop() else {
+                continue;
+            };
+            let Some(config) = config.find_by_tg_group(chat_id) else {
+                continue;
+            };
+            self.adapter.send_recall_to_bridge(&origin_id, &config).await;
+        }
+        Ok(false)
+    }
+}
+
+pub struct TgMessageReaction {
+    pub config: Arc<SharedConfig>,
+    pub adapter: Arc<TelegramAdapter>,
+}
+
+#[async_trait]
+impl RawProcess for TgMessageReaction {
+    async fn handle(&self, _: &mut Client, event: &tl::enums::Update) -> Result<bool> {
+        let tl::enums::Update::BotMessageReaction(update) = event else {
+            return Ok(false);
+        };
+        let chat_id = match &update.peer {
+            tl::enums::Peer::Chat(chat) => chat.chat_id,
+            tl::enums::Peer::Channel(channel) => channel.channel_id,
+            tl::enums::Peer::User(_) => return Ok(false),
+        };
+        let tl::enums::Peer::User(actor) = &update.actor else {
+            return Ok(false);
+        };
+        let Some(config) = self.config.current().find_by_tg_group(chat_id) else {
+            return Ok(false);
+        };
+        let emojis = |reactions: &Vec<tl::enums::Reaction>| -> Vec<String> {
+            reactions
+                .iter()
+                .filter_map(|reaction| match reaction {
+                    tl::enums::Reaction::Emoji(emoji) => Some(emoji.emoticon.clone()),
+                    _ => None,
+                })
+                .collect()
+        };
+        let old_emojis = emojis(&update.old_reactions);
+        let new_emojis = emojis(&update.new_reactions);
+        let Some(bridge_user) = reaction_user(&self.adapter, actor.user_id).await else {
+            debug!("[TG] 没有见过回应的用户 {}, 不同步回应", actor.user_id);
+            return Ok(false);
+        };
+        let origin_id = to_origin_id(chat_id, update.msg_id);
+        for emoji in old_emojis.iter().filter(|emoji| !new_emojis.contains(emoji)) {
+            self.adapter
+                .send_reaction_to_bridge(&origin_id, &config, emoji.clone(), bridge_user.id.clone(), false)
+                .await;
         }
-        // 获取PACK
-        let map_lock = PACK_MAP.lock().await;
-        let chat = match map_lock.get(&message.bridge_config.tgGroup) {
-            Some(chat) => Some(chat.clone()),
-            None => {
-                warn!("PACK 未找到 : {}", message.bridge_config.tgGroup);
+        for emoji in new_emojis.iter().filter(|emoji| !old_emojis.contains(emoji)) {
+            self.adapter
+                .send_reaction_to_bridge(&origin_id, &config, emoji.clone(), bridge_user.id.clone(), true)
+                .await;
+        }
+        Ok(false)
+    }
+}
+
+/// # 回应的桥用户
+/// 回应事件不带用户的信息, 用缓存中的姓名申请桥用户; 缓存中没有姓名时只查询已有的桥用户, 不以id为显示文本创建
+async fn reaction_user(adapter: &TelegramAdapter, user_id: i64) -> Option<BridgeUser> {
+    let display_text = TG_USER_CACHE.lock().await.get(user_id).and_then(TgUser::display_text);
+    match display_text {
+        Some(display_text) => match adapter.apply_tg_user(user_id, &display_text).await {
+            Ok(bridge_user) => Some(bridge_user),
+            Err(err) => {
+                error!(?err, "[TG] 回应同步失败");
                 None
             }
+        },
+        None => {
+            let platform = adapter.platform().to_string();
+            bridge::manager::BRIDGE_USER_MANAGER
+                .lock()
+                .await
+                .like(&user_id.to_string(), &platform)
+                .await
+        }
+    }
+}
+
+/// # 消息所在的论坛话题
+/// 话题中的消息回复话题的第一条消息; 回复话题中的其它消息时, 话题id在 `reply_to_top_id`。
+/// 普通群和论坛的 General 话题中的消息没有话题
+fn topic_of(event: &Message) -> Option<i32> {
+    if !event.is_topic_message() {
+        return None;
+    }
+    event.reply_to_top_id().or(event.reply_to_message_id())
+}
+
+/// 消息回复的消息, 不包括话题的第一条消息
+fn reply_of(event: &Message) -> Option<i32> {
+    if event.is_topic_message() && event.reply_to_top_id().is_none() {
+        return None;
+    }
+    event.reply_to_message_id()
+}
+
+/// # 组装向桥发送的消息体表单
+/// `sender` 为发送者的桥用户id和头像
+fn to_bridge_form(
+    adapter: &TelegramAdapter,
+    bridge_config: BridgeConfig,
+    (sender_id, avatar_url): (String, Option<String>),
+    origin_id: OriginMessageId,
+    message_chain: Vec<MessageContent>,
+) -> bridge::pojo::BridgeSendMessageForm {
+    bridge::pojo::BridgeSendMessageForm {
+        sender_id,
+        avatar_url,
+        bridge_config,
+        message_chain,
+        origin_message: bridge::pojo::BridgeMessageRefPO {
+            origin_id,
+            platform: adapter.platform().to_string(),
+            endpoint: None,
+        },
+    }
+}
+
+/// # 发送者的头像
+/// 按用户和头像id缓存, 更换头像后重新下载
+async fn avatar_of(client: &teleser::InnerClient, user: &User) -> Option<String> {
+    let photo = user.photo()?;
+    let user_id = user.id().to_string();
+    if let Some(avatar_url) = avatar::find(BridgeClientPlatform::Telegram, &user_id, photo.photo_id) {
+        return Some(avatar_url);
+    }
+    let data = match download_profile_photo(client, user, photo.photo_id).await {
+        Ok(data) => data,
+        Err(err) => {
+            warn!("[TG] 下载头像失败: {err:?}");
+            return None;
+        }
+    };
+    match avatar::save(BridgeClientPlatform::Telegram, &user_id, photo.photo_id, &data).await {
+        Ok(avatar_url) => Some(avatar_url),
+        Err(err) => {
+            warn!("[TG] 保存头像失败: {err:?}");
+            None
+        }
+    }
+}
+
+/// 每次下载的大小, 需要是 4KB 的倍数且整除 1MB
+const DOWNLOAD_CHUNK: i32 = 512 * 1024;
+
+/// 下载用户的头像 (小图)
+async fn download_profile_photo(client: &teleser::InnerClient, user: &User, photo_id: i64) -> Result<Vec<u8>> {
+    let location = tl::enums::InputFileLocation::InputPeerPhotoFileLocation(tl::types::InputPeerPhotoFileLocation {
+        big: false,
+        peer: user.pack().to_input_peer(),
+        photo_id,
+    });
+    let mut data = Vec::new();
+    loop {
+        let file = client
+            .invoke(&tl::functions::upload::GetFile {
+                precise: false,
+                cdn_supported: false,
+                location: location.clone(),
+                offset: data.len() as i64,
+                limit: DOWNLOAD_CHUNK,
+            })
+            .await?;
+        let tl::enums::upload::File::File(file) = file else {
+            return Err(anyhow::anyhow!("头像需要从CDN下载"));
+        };
+        let done = file.bytes.len() < DOWNLOAD_CHUNK as usize;
+        data.extend(file.bytes);
+        if done {
+            return Ok(data);
+        }
+    }
+}
+
+/// 记录发送者的用户名和 access_hash, 用于转换 @
+async fn remember_user(user: &User) {
+    let seen = TgUser {
+        id: user.id(),
+        username: user.username().map(str::to_string),
+        access_hash: user.pack().access_hash,
+        full_name: Some(user.full_name()),
+    };
+    TG_USER_CACHE.lock().await.update(seen).await;
+}
+
+/// TG 实体的 offset 和 length 以 UTF-16 计算
+fn utf16_slice(text: &[u16], start: usize, end: usize) -> String {
+    let end = end.min(text.len());
+    String::from_utf16_lossy(&text[start.min(end)..end])
+}
+
+/**
+ * TG的消息文本处理成桥的消息链
+ * @用户名 只能转换缓存中见过的用户, 其余保留原文
+ */
+async fn to_bridge_chain(adapter: &TelegramAdapter, event: &Message) -> Vec<MessageContent> {
+    let mut message_chain = Vec::new();
+    let text: Vec<u16> = event.text().encode_utf16().collect();
+    let mut offset: usize = 0;
+    for entity in event.fmt_entities().into_iter().flatten() {
+        let (start, length, user_id) = match entity {
+            MessageEntity::Mention(m) => {
+                let username = utf16_slice(&text, m.offset as usize, (m.offset + m.length) as usize);
+                let user_id = TG_USER_CACHE.lock().await.find_by_username(&username).map(|user| user.id);
+                (m.offset as usize, m.length as usize, user_id)
+            }
+            MessageEntity::MentionName(m) => (m.offset as usize, m.length as usize, Some(m.user_id)),
+            _ => continue,
+        };
+        let Some(user_id) = user_id else {
+            continue;
         };
-        drop(map_lock);
-        //
-        if let Some(chat) = chat {
-            let lock = teleser_client.inner_client.lock().await;
-            let inner_client = lock.clone();
-            drop(lock);
-            if let Some(inner_client) = inner_client {
-                // send message
-                if !images.is_empty() {
-                    for x in images {
-                        match x.clone().load_data().await {
-                            Ok(data) => {
-                                match image::guess_format(&data) {
-                                    Ok(format) => {
-                                        let len = data.len();
-                                        let mut reader = std::io::Cursor::new(data);
-                                        let upload = inner_client
-                                            .upload_stream(
-                                                &mut reader,
-                                                len,
-                                                format!("file.{}", format.extensions_str()[0]),
-                                            )
-                                            .await;
-                                        match upload {
-                                            Ok(img) => {
-                                                let _ = inner_client
-                                                    .send_message(
-                                                        chat.clone(),
-                                                        InputMessage::text(format!(
-                                                            "{}:",
-                                                            bridge_user.to_string(),
-                                                        ))
-                                                        .photo(img),
-                                                    )
-                                                    .await;
-                                            }
-                                            Err(_) => {}
-                                        }
-                                    }
-                                    Err(_) => {}
-                                };
-                            }
-                            Err(_) => {}
-                        }
+        if start < offset {
+            continue;
+        }
+        let name = utf16_slice(&text, start, start + length);
+        let Some(bridge_user) = mention_user(adapter, user_id, &name).await else {
+            continue;
+        };
+        if offset < start {
+            message_chain.push(Plain {
+                text: utf16_slice(&text, offset, start),
+            });
+        }
+        message_chain.push(MessageContent::At { id: bridge_user.id });
+        offset = start + length;
+    }
+    if offset < text.len() {
+        message_chain.push(Plain {
+            text: utf16_slice(&text, offset, text.len()),
+        });
+    }
+    message_chain
+}
+
+/**
+ * 提及的桥用户
+ * 缓存中有姓名时与发送者一样以 `姓名(id)` 申请; 没有时只在桥用户不存在时以提及的文本创建, 用户发言后更新为姓名
+ */
+async fn mention_user(adapter: &TelegramAdapter, user_id: i64, name: &str) -> Option<BridgeUser> {
+    let display_text = TG_USER_CACHE.lock().await.get(user_id).and_then(TgUser::display_text);
+    let Some(display_text) = display_text else {
+        let display_text = format!("{}({})", name.trim_start_matches('@'), user_id);
+        return Some(adapter.apply_bridge_user(&user_id.to_string(), &display_text).await);
+    };
+    match adapter.apply_tg_user(user_id, &display_text).await {
+        Ok(bridge_user) => Some(bridge_user),
+        Err(err) => {
+            warn!(?err, "[TG] 提及的用户 {user_id} 同步失败");
+            None
+        }
+    }
+}
+
+/**
+ * 桥的消息文本和@追加到TG消息文本
+ * 关联了TG账号的用户: 缓存中有 access_hash 时使用 MentionName, 有用户名时使用 @用户名; 否则用标准格式
+ */
+async fn push_tg_text(adapter: &TelegramAdapter, message_chain: &[MessageContent], text: &mut String, entities: &mut Vec<MessageEntity>) {
+    for x in message_chain {
+        match x {
+            MessageContent::Plain { text: plain } => text.push_str(plain),
+            MessageContent::At { id } => {
+                let Some(bridge_user) = bridge::manager::BRIDGE_USER_MANAGER.lock().await.get(id).await else {
+                    text.push_str(&format!("@[UN] {id}"));
+                    continue;
+                };
+                let tg_user = match find_tg_user(adapter, &bridge_user).await {
+                    Some(tg_user) => tg_user,
+                    None => {
+                        text.push_str(&format!("@{}", bridge_user.to_string()));
+                        continue;
                     }
+                };
+                let mention = match &tg_user.username {
+                    Some(username) => format!("@{username}"),
+                    None => format!("@{}", bridge_user.display_text),
+                };
+                let offset = text.encode_utf16().count() as i32;
+                let length = mention.encode_utf16().count() as i32;
+                text.push_str(&mention);
+                match (tg_user.access_hash, &tg_user.username) {
+                    (Some(access_hash), _) => entities.push(MessageEntity::InputMessageEntityMentionName(
+                        tl::types::InputMessageEntityMentionName {
+                            offset,
+                            length,
+                            user_id: tl::enums::InputUser::User(tl::types::InputUser {
+                                user_id: tg_user.id,
+                                access_hash,
+                            }),
+                        },
+                    )),
+                    (None, Some(_)) => entities.push(MessageEntity::Mention(tl::types::MessageEntityMention { offset, length })),
+                    (None, None) => {}
                 }
-                if !builder.is_empty() {
-                    let send = builder.join("");
-                    if !send.is_empty() {
-                        let _ = inner_client
-                            .send_message(
-                                chat.clone(),
-                                format!("{} : {}", bridge_user.to_string(), send),
-                            )
-                            .await;
-                    }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// 查询桥用户关联的TG用户
+async fn find_tg_user(adapter: &TelegramAdapter, bridge_user: &BridgeUser) -> Option<TgUser> {
+    let ref_user = adapter.find_ref_user(bridge_user).await?;
+    let user_id = ref_user.origin_id.parse::<i64>().ok()?;
+    TG_USER_CACHE.lock().await.get(user_id).cloned()
+}
+
+lazy_static! {
+    static ref PACK_MAP: Mutex<HashMap::<i64, PackedChat>> = Mutex::new(HashMap::new());
+}
+
+async fn send_message(
+    adapter: &TelegramAdapter,
+    teleser_client: &teleser::Client,
+    message: &BridgeMessage,
+) -> Result<Option<OriginMessageId>> {
+    // bot 发送的消息不能使用其他头像
+    if let Some(avatar_url) = &message.avatar_url {
+        debug!("用户头像: {:?}", avatar_url);
+    }
+    let bridge_user = bridge::manager::BRIDGE_USER_MANAGER
+        .lock()
+        .await
+        .get(&message.sender_id)
+        .await
+        .unwrap();
+    // telegram 每条消息只能带一个附件或一个图片
+    // 多张图片作为一组图片消息(相册)发送，文字作为相册的说明，显示到一组消息的最下方
+    let mut images = vec![];
+    // 回复的消息在本群时使用TG的回复, 只有第一条发送的消息带回复;
+    // 发送到论坛话题时, 其余消息回复话题的第一条消息
+    let topic = message.bridge_config.tgTopic;
+    let mut reply_to = None;
+    for x in &message.message_chain {
+        match x {
+            MessageContent::Reply { id: Some(id) } => {
+                let Some(reply_message) = bridge::manager::BRIDGE_MESSAGE_MANAGER.lock().await.get(id).await else {
+                    continue;
+                };
+                if let Some(OriginMessageId::Telegram { message_id, .. }) = adapter.find_origin_id(&reply_message, &message.bridge_config) {
+                    reply_to = Some(message_id);
                 }
             }
+            MessageContent::Reply { id: None } => {}
+            MessageContent::Image(image) => images.push(image),
+            _ => {}
         }
     }
+    let mut text = format!("{} : ", bridge_user.to_string());
+    let prefix_len = text.len();
+    let mut entities = vec![];
+    push_tg_text(adapter, &message.message_chain, &mut text, &mut entities).await;
+    let mut has_text = text.len() > prefix_len;
+    let chat = get_chat(message.bridge_config.tgGroup).await?;
+    let inner_client = get_inner_client(teleser_client).await?;
+    // send message
+    let mut sent = None;
+    if !images.is_empty() {
+        // 文字超过说明的长度限制时单独发送
+        let (caption, caption_entities) = if has_text && text.encode_utf16().count() <= CAPTION_LIMIT {
+            has_text = false;
+            (std::mem::take(&mut text), std::mem::take(&mut entities))
+        } else {
+            (format!("{}:", bridge_user.to_string()), vec![])
+        };
+        sent = send_images(&inner_client, &chat, images, caption, caption_entities, reply_to.take(), topic).await?;
+    }
+    if has_text {
+        let message = inner_client
+            .send_message(
+                chat.clone(),
+                InputMessage::text(text).fmt_entities(entities).reply_to(reply_to.take().or(topic)),
+            )
+            .await?;
+        sent = Some(message.id());
+    }
+    // 关联最后一条发送的消息
+    Ok(sent.map(|id| to_origin_id(message.bridge_config.tgGroup, id)))
+}
+
+/// 图片说明的长度限制 (UTF-16)
+const CAPTION_LIMIT: usize = 1024;
+/// 一组图片消息最多的图片数
+const ALBUM_LIMIT: usize = 10;
+
+/// # 发送图片
+/// 多张图片按组发送相册, 只有第一张图片带说明和回复; 发送到话题时每组都回复话题的第一条消息
+/// ### Return
+/// 带说明的消息id
+async fn send_images(
+    inner_client: &teleser::InnerClient,
+    chat: &PackedChat,
+    images: Vec<&Image>,
+    caption: String,
+    entities: Vec<MessageEntity>,
+    reply_to: Option<i32>,
+    topic: Option<i32>,
+) -> Result<Option<i32>> {
+    let mut uploaded = vec![];
+    for x in images {
+        let data = x.clone().load_data().await?;
+        let format = image::guess_format(&data)?;
+        let len = data.len();
+        let mut reader = std::io::Cursor::new(data);
+        let img = inner_client
+            .upload_stream(&mut reader, len, format!("file.{}", format.extensions_str()[0]))
+            .await?;
+        uploaded.push(img);
+    }
+    let mut caption = Some((caption, entities));
+    let mut reply_to = reply_to;
+    let mut sent = None;
+    let mut uploaded = uploaded.into_iter();
+    loop {
+        let mut group: Vec<Uploaded> = uploaded.by_ref().take(ALBUM_LIMIT).collect();
+        let reply_to = reply_to.take().or(topic);
+        let id = match group.len() {
+            0 => break,
+            1 => {
+                let (text, entities) = caption.take().unwrap_or_default();
+                let message = InputMessage::text(text)
+                    .fmt_entities(entities)
+                    .photo(group.remove(0))
+                    .reply_to(reply_to);
+                Some(inner_client.send_message(chat.clone(), message).await?.id())
+            }
+            _ => {
+                // 相册的回复取第一张图片的回复
+                let media = group
+                    .into_iter()
+                    .enumerate()
+                    .map(|(index, img)| {
+                        let media = match caption.take() {
+                            Some((text, entities)) => InputMedia::caption(text).fmt_entities(entities),
+                            None => InputMedia::caption(""),
+                        };
+                        media.photo(img).reply_to(if index == 0 { reply_to } else { None })
+                    })
+                    .collect();
+                let messages = inner_client.send_album(chat.clone(), media).await?;
+                messages.into_iter().flatten().next().map(|message| message.id())
+            }
+        };
+        sent = sent.or(id);
+    }
+    Ok(sent)
+}
+
+async fn edit_message(
+    adapter: &TelegramAdapter,
+    teleser_client: &teleser::Client,
+    message: &BridgeMessage,
+    origin_id: &OriginMessageId,
+) -> Result<()> {
+    let OriginMessageId::Telegram { chat_id, message_id } = *origin_id else {
+        return Err(anyhow::anyhow!("不是TG消息id: {origin_id}"));
+    };
+    let bridge_user = bridge::manager::BRIDGE_USER_MANAGER
+        .lock()
+        .await
+        .get(&message.sender_id)
+        .await
+        .unwrap();
+    let mut text = format!("{} : ", bridge_user.to_string());
+    let mut entities = vec![];
+    push_tg_text(adapter, &message.message_chain, &mut text, &mut entities).await;
+    let chat = get_chat(chat_id).await?;
+    let inner_client = get_inner_client(teleser_client).await?;
+    inner_client
+        .edit_message(chat, message_id, InputMessage::text(text).fmt_entities(entities))
+        .await?;
+    Ok(())
+}
+
+/// 获取PACK
+async fn get_chat(chat_id: i64) -> Result<PackedChat> {
+    let map_lock = PACK_MAP.lock().await;
+    let Some(chat) = map_lock.get(&chat_id).cloned() else {
+        return Err(anyhow::anyhow!("PACK 未找到 : {}", chat_id));
+    };
+    Ok(chat)
+}
+
+async fn get_inner_client(teleser_client: &teleser::Client) -> Result<teleser::InnerClient> {
+    let lock = teleser_client.inner_client.lock().await;
+    let Some(inner_client) = lock.clone() else {
+        return Err(anyhow::anyhow!("[TG] 客户端未登录"));
+    };
+    Ok(inner_client)
 }
 
 async fn download_media(c: &mut teleser::InnerClient, media: &Media) -> Result<Vec<u8>> {
@@ -334,19 +919,3 @@ async fn download_media(c: &mut teleser::InnerClient, media: &Media) -> Result<V
     }
     Ok(data)
 }
-
-/**
- * 申请桥用户
- */
-async fn apply_bridge_user(id: i64, name: String) -> bridge::user::BridgeUser {
-    let bridge_user = bridge::manager::BRIDGE_USER_MANAGER
-        .lock()
-        .await
-        .likeAndSave(bridge::pojo::BridgeUserSaveForm {
-            origin_id: id.to_string(),
-            platform: "TG".to_string(),
-            display_text: format!("{}({})", name, id),
-        })
-        .await;
-    bridge_user.unwrap()
-}
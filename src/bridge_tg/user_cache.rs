@@ -0,0 +1,157 @@
+//! TG用户缓存
+//!
+//! 机器人不能通过用户名查询用户, 只能记录收到的消息中出现过的用户：
+//! 用户名用于将 `@用户名` 转为桥用户, access_hash 用于向TG发送 @用户 (MentionName)
+
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use tokio::sync::Mutex;
+use tracing::error;
+
+use crate::bridge::storage::json_file;
+
+/// 见过的TG用户
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TgUser {
+    pub id: i64,
+    /// 用户名, 不带 `@`
+    pub username: Option<String>,
+    pub access_hash: Option<i64>,
+    /// 姓名, 以前记录的用户没有
+    #[serde(default)]
+    pub full_name: Option<String>,
+}
+
+impl TgUser {
+    /// 桥用户的显示文本 `姓名(id)`, 没有记录姓名时为空
+    pub fn display_text(&self) -> Option<String> {
+        Some(format!("{}({})", self.full_name.as_ref()?, self.id))
+    }
+}
+
+pub struct TgUserCache {
+    path: PathBuf,
+    users: Vec<TgUser>,
+}
+
+impl TgUserCache {
+    pub fn new() -> TgUserCache {
+        Self::open_at(PathBuf::from("./data/tg_user.json"))
+    }
+
+    pub fn open_at(path: PathBuf) -> TgUserCache {
+        TgUserCache {
+            users: json_file::load(&path).unwrap_or_default(),
+            path,
+        }
+    }
+
+    pub fn get(&self, id: i64) -> Option<&TgUser> {
+        self.users.iter().find(|user| user.id == id)
+    }
+
+    /// 按用户名查询, 不区分大小写, 可以带 `@`
+    pub fn find_by_username(&self, username: &str) -> Option<&TgUser> {
+        let username = username.trim_start_matches('@');
+        self.users
+            .iter()
+            .find(|user| user.username.as_ref().map_or(false, |name| name.eq_ignore_ascii_case(username)))
+    }
+
+    /// # 记录消息中见到的用户
+    /// 用户名以最新见到的为准, 同名的旧记录会清除用户名(用户名可以被其他用户使用);
+    /// 没有 access_hash 或姓名时保留旧值; 有变化时才保存
+    pub async fn update(&mut self, user: TgUser) {
+        let mut changed = false;
+        if let Some(username) = &user.username {
+            for other in self.users.iter_mut().filter(|other| other.id != user.id) {
+                if other.username.as_ref().map_or(false, |name| name.eq_ignore_ascii_case(username)) {
+                    other.username = None;
+                    changed = true;
+                }
+            }
+        }
+        match self.users.iter_mut().find(|old| old.id == user.id) {
+            Some(old) => {
+                let access_hash = user.access_hash.or(old.access_hash);
+                let full_name = user.full_name.or(old.full_name.take());
+                if old.username != user.username || old.access_hash != access_hash || old.full_name != full_name {
+                    changed = true;
+                }
+                old.username = user.username;
+                old.access_hash = access_hash;
+                old.full_name = full_name;
+            }
+            None => {
+                self.users.push(user);
+                changed = true;
+            }
+        }
+        if changed {
+            self.serialize().await;
+        }
+    }
+
+    async fn serialize(&self) {
+        if let Err(err) = json_file::save_blocking(self.path.clone(), self.users.clone()).await {
+            error!(?err, "TG用户缓存保存失败: {}", self.path.display());
+        }
+    }
+}
+
+lazy_static! {
+    pub static ref TG_USER_CACHE: Mutex<TgUserCache> = Mutex::new(TgUserCache::new());
+}
+
+#[cfg(test)]
+mod test {
+    use super::{TgUser, TgUserCache};
+    use crate::bridge::test_util::temp_json;
+
+    fn user(id: i64, username: Option<&str>, access_hash: Option<i64>) -> TgUser {
+        TgUser {
+            id,
+            username: username.map(str::to_string),
+            access_hash,
+            full_name: None,
+        }
+    }
+
+    #[test]
+    fn test_update() {
+        let path = temp_json("tg_user");
+        tokio_test::block_on(async {
+            let mut cache = TgUserCache::open_at(path.clone());
+            cache.update(user(1, Some("Alice"), Some(11))).await;
+            cache.update(user(2, Some("bob"), None)).await;
+            assert_eq!(cache.find_by_username("@alice").unwrap().id, 1);
+
+            // 没有 access_hash 时保留旧值
+            cache.update(user(1, Some("alice_new"), None)).await;
+            assert_eq!(cache.get(1), Some(&user(1, Some("alice_new"), Some(11))));
+            assert!(cache.find_by_username("alice").is_none());
+
+            // 用户名被其他用户使用
+            cache.update(user(3, Some("BOB"), None)).await;
+            assert_eq!(cache.find_by_username("bob").unwrap().id, 3);
+            assert_eq!(cache.get(2).unwrap().username, None);
+
+            // 没有姓名时保留旧值
+            cache
+                .update(TgUser {
+                    full_name: Some("Bob B".to_string()),
+                    ..user(3, Some("BOB"), None)
+                })
+                .await;
+            cache.update(user(3, Some("BOB"), None)).await;
+            assert_eq!(cache.get(3).unwrap().display_text().as_deref(), Some("Bob B(3)"));
+            assert_eq!(cache.get(2).unwrap().display_text(), None);
+        });
+
+        // 重新打开仍能读到
+        let cache = TgUserCache::open_at(path.clone());
+        assert_eq!(cache.find_by_username("alice_new").unwrap().access_hash, Some(11));
+        let _ = std::fs::remove_file(path);
+    }
+}
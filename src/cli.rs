@@ -0,0 +1,195 @@
+//! 命令行
+//!
+//! 不带子命令时启动桥；子命令用于桥停止时的维护操作
+
+use chrono::TimeZone;
+use clap::{Parser, Subcommand};
+
+use crate::archive;
+use crate::bridge::manager::DEAD_LETTER_MANAGER;
+use crate::bridge::user::BridgeUser;
+use crate::bridge::{storage, BridgeClientPlatform, MessageQueue};
+use crate::bridge_dc;
+use crate::config::{self, Config};
+
+#[derive(Parser, Debug)]
+#[command(version, about = "消息桥")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// 死信：多次重试仍投递失败的桥事件
+    #[command(subcommand)]
+    DeadLetter(DeadLetterCommand),
+    /// 存储：桥用户和桥消息
+    #[command(subcommand)]
+    Storage(StorageCommand),
+    /// 导出桥用户、桥消息、登录会话等全部桥数据到一个文件，用于迁移或备份
+    Export {
+        /// 导出文件路径，默认为 bridge-export-<日期>.json
+        #[arg(long)]
+        output: Option<String>,
+    },
+    /// 从导出文件恢复桥数据，已存在的数据会被覆盖 (请在桥停止时执行)
+    Import {
+        /// 导出文件路径
+        path: String,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum DeadLetterCommand {
+    /// 列出死信
+    List,
+    /// 将死信放回投递队列，下次启动桥时重新投递 (请在桥停止时执行)
+    Replay {
+        /// 只重投递到该桥客户端的死信，如 bridge_qq_client
+        #[arg(long)]
+        client: Option<String>,
+        /// 只重投递关联该桥消息的死信
+        #[arg(long)]
+        id: Option<String>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum StorageCommand {
+    /// 将json文件中的桥用户和桥消息导入sqlite (请在桥停止时执行)
+    Migrate,
+    /// 将旧版绑定映射 (data/BindMap.json) 导入桥用户的关联，并列出冲突的映射 (请在桥停止时执行)
+    MigrateBindMap,
+    /// 迁移dc用户的显示文本：新用户名去掉 `#0`，旧账号的discriminator补足4位 (请在桥停止时执行)
+    MigrateDcNames,
+}
+
+/// 执行子命令
+pub async fn run(command: Command) -> anyhow::Result<()> {
+    match command {
+        Command::DeadLetter(command) => dead_letter(command).await,
+        Command::Storage(StorageCommand::Migrate) => {
+            let report = storage::migrate_json_to_sqlite()?;
+            for user in &report.duplicates {
+                println!(
+                    "重复: 桥用户 {} 与其他桥用户的平台和源id相同 ({} {}), 未导入",
+                    user.id, user.platform, user.origin_id
+                );
+            }
+            println!(
+                "已导入 {} 个桥用户, {} 条桥消息, 重复未导入 {} 个桥用户; 请在配置中设置 \"storage\": \"sqlite\"",
+                report.users,
+                report.messages,
+                report.duplicates.len()
+            );
+            Ok(())
+        }
+        Command::Storage(StorageCommand::MigrateBindMap) => migrate_bind_map(),
+        Command::Storage(StorageCommand::MigrateDcNames) => migrate_dc_names(),
+        Command::Export { output } => export(output),
+        Command::Import { path } => import(&path),
+    }
+}
+
+/// 按配置的存储方式读写桥用户和桥消息
+fn init_storage() -> anyhow::Result<()> {
+    storage::init(Config::load(config::CONFIG_PATH)?.storage)
+}
+
+fn export(output: Option<String>) -> anyhow::Result<()> {
+    init_storage()?;
+    let archive = archive::export()?;
+    let output = output.unwrap_or_else(|| format!("bridge-export-{}.json", chrono::Local::now().format("%Y%m%d%H%M%S")));
+    std::fs::write(&output, serde_json::to_vec(&archive)?)?;
+    println!(
+        "已导出 {} 个桥用户, {} 条桥消息, {} 个文件到 {output}",
+        archive.users.len(),
+        archive.messages.len(),
+        archive.files.len()
+    );
+    Ok(())
+}
+
+fn import(path: &str) -> anyhow::Result<()> {
+    init_storage()?;
+    let archive = archive::parse(&std::fs::read_to_string(path)?)?;
+    archive::restore(&archive)?;
+    println!(
+        "已导入 {} 个桥用户, {} 条桥消息, {} 个文件",
+        archive.users.len(),
+        archive.messages.len(),
+        archive.files.len()
+    );
+    Ok(())
+}
+
+fn migrate_bind_map() -> anyhow::Result<()> {
+    init_storage()?;
+    let report = storage::bind_map::migrate(storage::open_user_storage().as_mut())?;
+    for conflict in &report.conflicts {
+        println!("冲突: {conflict}");
+    }
+    println!(
+        "新建关联 {} 对, 已关联跳过 {} 对, 新建桥用户 {} 个, 冲突 {} 对",
+        report.bound,
+        report.skipped,
+        report.created,
+        report.conflicts.len()
+    );
+    Ok(())
+}
+
+fn migrate_dc_names() -> anyhow::Result<()> {
+    init_storage()?;
+    let (users, _) = storage::export()?;
+    let platform = BridgeClientPlatform::Discord.to_string();
+    let users: Vec<BridgeUser> = users
+        .into_iter()
+        .filter(|user| user.platform == platform)
+        .filter_map(|mut user| {
+            user.display_text = bridge_dc::migrate_display_text(&user.display_text)?;
+            Some(user)
+        })
+        .collect();
+    storage::open_user_storage().save(&users)?;
+    println!("已更新 {} 个dc用户的显示文本", users.len());
+    Ok(())
+}
+
+async fn dead_letter(command: DeadLetterCommand) -> anyhow::Result<()> {
+    let mut manager = DEAD_LETTER_MANAGER.lock().await;
+    match command {
+        DeadLetterCommand::List => {
+            for letter in manager.list() {
+                let time = chrono::Local.timestamp_opt(letter.time, 0).single();
+                let time = time.map(|time| time.format("%Y-%m-%d %H:%M:%S").to_string()).unwrap_or_default();
+                println!(
+                    "{} [{}] {} 尝试{}次 {}: {}",
+                    time,
+                    letter.platform,
+                    letter.event.id(),
+                    letter.attempts,
+                    letter.client,
+                    letter.error
+                );
+            }
+            println!("共 {} 条死信", manager.list().len());
+        }
+        DeadLetterCommand::Replay { client, id } => {
+            let letters = manager.filter(client.as_deref(), id.as_deref());
+            let mut replayed = 0;
+            for letter in &letters {
+                // 放回投递队列后才移除死信, 放回失败的死信保留
+                if let Err(err) = MessageQueue::open(&letter.client).push(letter.event.clone()).await {
+                    println!("死信 {} 放回投递队列失败: {err:#}", letter.event.id());
+                    continue;
+                }
+                manager.remove(&letter.id).await;
+                replayed += 1;
+            }
+            println!("已将 {replayed}/{} 条死信放回投递队列", letters.len());
+        }
+    }
+    Ok(())
+}
@@ -1,10 +1,27 @@
 #![allow(non_snake_case)]
 
+use crate::bridge::storage::StorageKind;
+use crate::bridge::BridgeClientPlatform;
+use anyhow::{anyhow, bail, Context};
 use proc_qq::re_exports::ricq::version;
 use proc_qq::Authentication;
+use regex::Regex;
 use serde::Deserialize;
 use serde::Serialize;
+use std::collections::HashSet;
+use std::fmt::{Display, Formatter, Result as FmtResult};
 use std::fs;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+use tracing::{error, info, warn};
+
+/// 配置文件路径
+pub const CONFIG_PATH: &str = "./config.json";
+/// 检查配置文件是否修改的间隔
+const WATCH_INTERVAL: Duration = Duration::from_secs(5);
+/// 桥消息至少保留的天数和条数, 保证最近的消息仍能被回复、编辑和撤回
+const MIN_RETENTION_DAYS: u64 = 1;
+const MIN_RETENTION_COUNT: usize = 100;
 
 #[derive(Deserialize, Serialize, Debug, Eq, PartialEq)]
 pub struct Config {
@@ -17,16 +34,193 @@ pub struct Config {
     pub discord_config: DiscordConfig,
     #[serde(rename = "telegramConfig")]
     pub telegram_config: TelegramConfig,
+    /// 桥用户和桥消息的存储方式, 默认json
+    #[serde(default)]
+    pub storage: StorageKind,
+    /// 桥消息的保留策略
+    #[serde(default)]
+    pub retention: RetentionConfig,
+    /// 缓存头像(如TG头像)的访问地址, dc webhook 只能使用url头像, 见 [`crate::bridge::avatar`]
+    #[serde(rename = "avatarBaseUrl", default)]
+    pub avatar_base_url: Option<String>,
     pub bridges: Vec<BridgeConfig>,
 }
 
 impl Config {
     pub fn new() -> Self {
-        let file = fs::read_to_string("./config.json").unwrap();
-        // println!("{file}");
-        let config: Config = serde_json::from_str(file.as_str()).unwrap();
+        Self::load(CONFIG_PATH).unwrap()
+    }
 
-        config
+    /// # 读取并校验配置
+    /// 旧版的单端点配置会转为端点, 见 [`BridgeConfig::normalize`]
+    pub fn load(path: &str) -> anyhow::Result<Self> {
+        let file = fs::read_to_string(path).with_context(|| format!("读取配置文件失败: {path}"))?;
+        let mut config: Config = serde_json::from_str(file.as_str()).with_context(|| format!("解析配置文件失败: {path}"))?;
+        for bridge in config.bridges.iter_mut() {
+            bridge.normalize();
+        }
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// 校验配置
+    pub fn validate(&self) -> anyhow::Result<()> {
+        self.qq_config.get_auth()?;
+        self.qq_config.get_version()?;
+        self.retention.validate()?;
+        let mut names = HashSet::new();
+        for (index, bridge) in self.bridges.iter().enumerate() {
+            bridge.validate().with_context(|| format!("第{}个桥配置有误", index + 1))?;
+            if !names.insert(bridge.name.as_str()) {
+                bail!("桥名重复: {}", bridge.name);
+            }
+        }
+        Ok(())
+    }
+
+    /// 平台登录、存储方式等配置, 修改后需要重启
+    fn is_restart_required(&self, other: &Config) -> bool {
+        self.print_qr != other.print_qr
+            || self.storage != other.storage
+            || self.qq_config != other.qq_config
+            || self.discord_config != other.discord_config
+            || self.telegram_config != other.telegram_config
+    }
+
+    /// # 查找dc频道所在的桥
+    /// 返回以该频道为端点的桥配置, 见 [`BridgeConfig::to_target`]
+    pub fn find_by_discord_channel(&self, channel_id: u64) -> Option<BridgeConfig> {
+        self.find_endpoint(|target| matches!(target, EndpointTarget::Discord { discord } if discord.channelId == channel_id))
+    }
+
+    /// 查找qq群所在的桥
+    pub fn find_by_qq_group(&self, group_id: u64) -> Option<BridgeConfig> {
+        self.find_endpoint(|target| matches!(target, EndpointTarget::QQ { qqGroup } if *qqGroup == group_id))
+    }
+
+    /// 查找tg群(或频道)所在的桥, 不区分话题
+    pub fn find_by_tg_group(&self, group_id: i64) -> Option<BridgeConfig> {
+        self.find_endpoint(|target| matches!(target, EndpointTarget::Telegram { tgGroup, .. } if *tgGroup == group_id))
+    }
+
+    /// 启用的桥配置的全部tg群(或频道)
+    pub fn tg_groups(&self) -> Vec<i64> {
+        let mut groups: Vec<i64> = self
+            .bridges
+            .iter()
+            .filter(|bridge| bridge.enable)
+            .flat_map(|bridge| bridge.endpoints.iter())
+            .filter_map(|endpoint| match endpoint.target {
+                EndpointTarget::Telegram { tgGroup, .. } => Some(tgGroup),
+                _ => None,
+            })
+            .collect();
+        groups.sort();
+        groups.dedup();
+        groups
+    }
+
+    /// # 查找tg话题所在的桥
+    /// 优先使用配置了该话题的端点, 其次是不区分话题的端点
+    /// ### Arguments
+    /// - `group_id` 群(或频道)id
+    /// - `topic` 论坛话题id, 不是话题消息时为空
+    pub fn find_by_tg_topic(&self, group_id: i64, topic: Option<i32>) -> Option<BridgeConfig> {
+        let find = |expect: Option<i32>| {
+            self.find_endpoint(
+                |target| matches!(target, EndpointTarget::Telegram { tgGroup, tgTopic } if *tgGroup == group_id && *tgTopic == expect),
+            )
+        };
+        topic.and_then(|_| find(topic)).or_else(|| find(None))
+    }
+
+    /// 是否桥配置的dc webhook
+    pub fn is_bridge_webhook(&self, webhook_id: u64) -> bool {
+        self.bridges
+            .iter()
+            .flat_map(|bridge| bridge.endpoints.iter())
+            .any(|endpoint| matches!(&endpoint.target, EndpointTarget::Discord { discord } if discord.id == webhook_id))
+    }
+
+    fn find_endpoint(&self, matches: impl Fn(&EndpointTarget) -> bool) -> Option<BridgeConfig> {
+        self.bridges.iter().filter(|bridge| bridge.enable).find_map(|bridge| {
+            let endpoint = bridge.endpoints.iter().find(|endpoint| matches(&endpoint.target))?;
+            Some(bridge.to_target(endpoint))
+        })
+    }
+}
+
+/// # 桥消息的保留策略
+/// 超过保留天数或条数的桥消息由后台定时清理, 不配置时永久保留
+#[derive(Clone, Default, Deserialize, Serialize, Debug, Eq, PartialEq)]
+pub struct RetentionConfig {
+    /// 保留天数
+    #[serde(default)]
+    pub maxDays: Option<u64>,
+    /// 每个桥保留的消息条数
+    #[serde(default)]
+    pub maxCount: Option<usize>,
+}
+
+impl RetentionConfig {
+    fn validate(&self) -> anyhow::Result<()> {
+        if self.maxDays.is_some_and(|days| days < MIN_RETENTION_DAYS) {
+            bail!("retention.maxDays 不能小于 {MIN_RETENTION_DAYS}");
+        }
+        if self.maxCount.is_some_and(|count| count < MIN_RETENTION_COUNT) {
+            bail!("retention.maxCount 不能小于 {MIN_RETENTION_COUNT}");
+        }
+        Ok(())
+    }
+}
+
+/// # 可热重载的配置
+/// 各平台的处理器每次使用时读取当前配置, 配置文件修改后由 [`watch`] 替换
+pub struct SharedConfig {
+    config: RwLock<Arc<Config>>,
+}
+
+impl SharedConfig {
+    pub fn new(config: Config) -> Self {
+        SharedConfig {
+            config: RwLock::new(Arc::new(config)),
+        }
+    }
+
+    /// 当前配置
+    pub fn current(&self) -> Arc<Config> {
+        self.config.read().unwrap().clone()
+    }
+
+    /// # 重新加载配置
+    /// 配置有误时返回错误, 继续使用原来的配置
+    pub fn reload(&self, path: &str) -> anyhow::Result<()> {
+        let config = Config::load(path)?;
+        if config.is_restart_required(&self.current()) {
+            warn!("平台登录、存储方式等配置已修改, 重启后生效");
+        }
+        *self.config.write().unwrap() = Arc::new(config);
+        Ok(())
+    }
+}
+
+/// # 监听配置文件
+/// 定时检查配置文件的修改时间, 修改后重新加载
+pub async fn watch(config: Arc<SharedConfig>) {
+    let modified_time = || fs::metadata(CONFIG_PATH).and_then(|meta| meta.modified()).ok();
+    let mut modified: Option<SystemTime> = modified_time();
+    let mut interval = tokio::time::interval(WATCH_INTERVAL);
+    loop {
+        interval.tick().await;
+        let time = modified_time();
+        if time.is_none() || time == modified {
+            continue;
+        }
+        modified = time;
+        match config.reload(CONFIG_PATH) {
+            Ok(()) => info!("配置已重新加载"),
+            Err(err) => error!("配置重新加载失败, 继续使用原来的配置: {err:?}"),
+        }
     }
 }
 
@@ -92,22 +286,300 @@ pub struct DiscordConfig {
 
 #[derive(Deserialize, Serialize, Debug, Eq, PartialEq)]
 pub struct TelegramConfig {
+    /// 是否启动tg桥, 默认不启动
+    #[serde(default)]
+    pub enable: bool,
     pub apiId: i32,
     pub apiHash: String,
     pub botToken: String,
 }
 
-#[derive(Clone, Deserialize, Serialize, Debug, Eq, PartialEq)]
+/// # 桥配置
+/// 桥由若干端点(dc频道、qq群、tg群)和端点之间的连接组成, 消息沿连接同步
+///
+/// `discord`、`qqGroup`、`tgGroup` 是旧版的单端点配置, 加载时转为端点 (见 [`BridgeConfig::normalize`]);
+/// 同步中的桥消息里它们表示消息在各平台所在/要投递的端点 (见 [`BridgeConfig::to_target`])
+#[derive(Clone, Default, Deserialize, Serialize, Debug, Eq, PartialEq)]
 pub struct BridgeConfig {
+    /// 桥名, 记录在桥消息上; 不配置时以第一个端点命名, 见 [`BridgeConfig::normalize`]
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
     pub discord: DiscordBridgeConfig,
+    #[serde(default)]
     pub qqGroup: u64,
+    #[serde(default)]
     pub tgGroup: i64,
+    /// tg论坛话题, 只出现在端点和同步中的桥消息里
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tgTopic: Option<i32>,
     pub enable: bool,
+    /// 端点, 每个平台可以有任意多个
+    #[serde(default)]
+    pub endpoints: Vec<BridgeEndpoint>,
+    /// 端点之间的连接, 不配置时所有端点互相同步
+    #[serde(default)]
+    pub links: Vec<BridgeLink>,
+    /// 消息过滤规则, 按顺序执行
+    #[serde(default)]
+    pub filters: Vec<FilterRule>,
 }
 
+impl BridgeConfig {
+    /// 校验端点名不重复、连接引用的端点存在; 过滤规则的正则在解析配置时编译, 见 [`FilterText`]
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let mut names = HashSet::new();
+        for endpoint in &self.endpoints {
+            if !names.insert(endpoint.name.as_str()) {
+                bail!("端点名重复: {}", endpoint.name);
+            }
+        }
+        for link in &self.links {
+            for name in [&link.from, &link.to] {
+                if !names.contains(name.as_str()) {
+                    bail!("连接的端点不存在: {name}");
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// 将旧版的单端点配置转为端点, 端点名为平台名 (DC、QQ、TG); 没有桥名时以第一个端点命名 (如 `QQ:123`)
+    pub fn normalize(&mut self) {
+        let mut legacy = vec![];
+        if self.discord.channelId != 0 {
+            legacy.push(EndpointTarget::Discord {
+                discord: self.discord.clone(),
+            });
+        }
+        if self.qqGroup != 0 {
+            legacy.push(EndpointTarget::QQ { qqGroup: self.qqGroup });
+        }
+        if self.tgGroup != 0 {
+            legacy.push(EndpointTarget::Telegram {
+                tgGroup: self.tgGroup,
+                tgTopic: None,
+            });
+        }
+        for target in legacy {
+            if self.endpoints.iter().all(|endpoint| endpoint.target != target) {
+                self.endpoints.push(BridgeEndpoint {
+                    name: target.platform().to_string(),
+                    target,
+                });
+            }
+        }
+        if self.name.is_empty() {
+            self.name = self.endpoints.first().map(|endpoint| endpoint.target.to_string()).unwrap_or_default();
+        }
+    }
+
+    /// 以该端点作为消息在其平台所在/要投递的端点
+    pub fn to_target(&self, endpoint: &BridgeEndpoint) -> BridgeConfig {
+        let mut config = self.clone();
+        match &endpoint.target {
+            EndpointTarget::Discord { discord } => config.discord = discord.clone(),
+            EndpointTarget::QQ { qqGroup } => config.qqGroup = *qqGroup,
+            EndpointTarget::Telegram { tgGroup, tgTopic } => {
+                config.tgGroup = *tgGroup;
+                config.tgTopic = *tgTopic;
+            }
+        }
+        config
+    }
+
+    /// 消息在该平台所在的端点, 见 [`BridgeConfig::to_target`]
+    pub fn endpoint(&self, platform: BridgeClientPlatform) -> Option<&BridgeEndpoint> {
+        self.endpoints.iter().find(|endpoint| match &endpoint.target {
+            EndpointTarget::Discord { discord } => platform == BridgeClientPlatform::Discord && *discord == self.discord,
+            EndpointTarget::QQ { qqGroup } => platform == BridgeClientPlatform::QQ && *qqGroup == self.qqGroup,
+            EndpointTarget::Telegram { tgGroup, tgTopic } => {
+                platform == BridgeClientPlatform::Telegram && *tgGroup == self.tgGroup && *tgTopic == self.tgTopic
+            }
+        })
+    }
+
+    /// # 消息从该端点出发要同步到的端点
+    /// ### Argument
+    /// `from` 消息来源端点, 为空时(如指令消息)同步到所有端点
+    pub fn route(&self, from: Option<&BridgeEndpoint>) -> Vec<&BridgeEndpoint> {
+        let Some(from) = from else {
+            return self.endpoints.iter().collect();
+        };
+        self.endpoints
+            .iter()
+            .filter(|to| to.name != from.name)
+            .filter(|to| {
+                self.links.is_empty()
+                    || self.links.iter().any(|link| {
+                        (link.from == from.name && link.to == to.name)
+                            || (link.direction == LinkDirection::Both && link.to == from.name && link.from == to.name)
+                    })
+            })
+            .collect()
+    }
+}
+
+/// 桥的端点
+#[derive(Clone, Deserialize, Serialize, Debug, Eq, PartialEq)]
+pub struct BridgeEndpoint {
+    /// 端点名, 连接通过端点名引用端点
+    pub name: String,
+    #[serde(flatten)]
+    pub target: EndpointTarget,
+}
+
+/// 端点所在的平台会话, 配置项与旧版的单端点配置相同
+#[derive(Clone, Deserialize, Serialize, Debug, Eq, PartialEq)]
+#[serde(untagged)]
+pub enum EndpointTarget {
+    Discord { discord: DiscordBridgeConfig },
+    QQ { qqGroup: u64 },
+    /// tg群、超级群或频道; 配置 `tgTopic` 时只同步论坛中的该话题
+    Telegram {
+        tgGroup: i64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        tgTopic: Option<i32>,
+    },
+}
+
+impl Display for EndpointTarget {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            EndpointTarget::Discord { discord } => write!(f, "{}:{}", self.platform(), discord.channelId),
+            EndpointTarget::QQ { qqGroup } => write!(f, "{}:{}", self.platform(), qqGroup),
+            EndpointTarget::Telegram { tgGroup, tgTopic: None } => write!(f, "{}:{}", self.platform(), tgGroup),
+            EndpointTarget::Telegram {
+                tgGroup,
+                tgTopic: Some(topic),
+            } => write!(f, "{}:{}/{}", self.platform(), tgGroup, topic),
+        }
+    }
+}
+
+impl EndpointTarget {
+    pub fn platform(&self) -> BridgeClientPlatform {
+        match self {
+            EndpointTarget::Discord { .. } => BridgeClientPlatform::Discord,
+            EndpointTarget::QQ { .. } => BridgeClientPlatform::QQ,
+            EndpointTarget::Telegram { .. } => BridgeClientPlatform::Telegram,
+        }
+    }
+}
+
+/// 端点之间的连接
+#[derive(Clone, Deserialize, Serialize, Debug, Eq, PartialEq)]
+pub struct BridgeLink {
+    pub from: String,
+    pub to: String,
+    /// 同步方向, 默认双向
+    #[serde(default)]
+    pub direction: LinkDirection,
+}
+
+#[derive(Clone, Copy, Default, Deserialize, Serialize, Debug, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LinkDirection {
+    /// 双向同步
+    #[default]
+    Both,
+    /// 只从 from 同步到 to
+    Oneway,
+}
+
+/// # 消息过滤规则
+/// 匹配条件都满足时执行动作, 没有配置的条件不限制
+#[derive(Clone, Deserialize, Serialize, Debug, Eq, PartialEq)]
+pub struct FilterRule {
+    /// 规则作用的方向
+    #[serde(default)]
+    pub direction: FilterDirection,
+    /// 消息来源的平台(DC、QQ、TG、CMD)或端点名
+    #[serde(default)]
+    pub from: Vec<String>,
+    /// 消息投递的平台或端点名, 只对 outbound 规则有效
+    #[serde(default)]
+    pub to: Vec<String>,
+    /// 匹配文本的正则
+    #[serde(default)]
+    pub text: Option<FilterText>,
+    /// 发送者白名单(平台用户id或桥用户id), 名单外的发送者匹配
+    #[serde(default)]
+    pub allowSenders: Vec<String>,
+    /// 发送者黑名单
+    #[serde(default)]
+    pub denySenders: Vec<String>,
+    /// 只匹配只有图片的消息
+    #[serde(default)]
+    pub imageOnly: bool,
+    pub action: FilterAction,
+}
+
+/// # 过滤规则的正则
+/// 解析配置时编译一次, 正则无效时配置加载失败; 序列化为正则文本
+#[derive(Clone, Deserialize, Serialize, Debug)]
+#[serde(try_from = "String", into = "String")]
+pub struct FilterText(Regex);
+
+impl TryFrom<String> for FilterText {
+    type Error = anyhow::Error;
+
+    fn try_from(text: String) -> anyhow::Result<Self> {
+        let regex = Regex::new(&text).map_err(|err| anyhow!("过滤规则的正则无效: {text}\n{err}"))?;
+        Ok(FilterText(regex))
+    }
+}
+
+impl From<FilterText> for String {
+    fn from(text: FilterText) -> Self {
+        text.0.as_str().to_string()
+    }
+}
+
+impl std::ops::Deref for FilterText {
+    type Target = Regex;
+
+    fn deref(&self) -> &Regex {
+        &self.0
+    }
+}
+
+impl PartialEq for FilterText {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.as_str() == other.0.as_str()
+    }
+}
+
+impl Eq for FilterText {}
+
+#[derive(Clone, Copy, Default, Deserialize, Serialize, Debug, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum FilterDirection {
+    /// 消息进入桥时, 对所有端点生效
+    #[default]
+    Inbound,
+    /// 消息投递到端点时
+    Outbound,
+}
+
+/// 过滤动作, strip 和 replace 作用于 `text` 匹配的文本, 没有配置 `text` 时作用于全部文本
 #[derive(Clone, Deserialize, Serialize, Debug, Eq, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum FilterAction {
+    /// 不同步该消息
+    Drop,
+    /// 删除文本
+    Strip,
+    /// 替换文本
+    Replace { with: String },
+}
+
+#[derive(Clone, Default, Deserialize, Serialize, Debug, Eq, PartialEq)]
 pub struct DiscordBridgeConfig {
+    /// webhook id, 为0时自动查找或创建频道的webhook, 见 [`crate::bridge_dc::webhook`]
+    #[serde(default)]
     pub id: u64,
+    #[serde(default)]
     pub token: String,
     pub channelId: u64,
 }
@@ -130,4 +602,84 @@ mod test {
         println!("config:");
         println!("{:?}", config);
     }
+
+    #[test]
+    fn route() {
+        let mut bridge: BridgeConfig = serde_json::from_str(
+            r#"{
+                "enable": true,
+                "qqGroup": 1,
+                "endpoints": [
+                    {"name": "announce", "discord": {"id": 2, "token": "t", "channelId": 3}},
+                    {"name": "qqB", "qqGroup": 4},
+                    {"name": "tg", "tgGroup": -5}
+                ],
+                "links": [
+                    {"from": "announce", "to": "QQ", "direction": "oneway"},
+                    {"from": "QQ", "to": "tg"},
+                    {"from": "qqB", "to": "tg"}
+                ]
+            }"#,
+        )
+        .unwrap();
+        bridge.normalize();
+        assert_eq!(bridge.endpoints.len(), 4);
+        let names = |from: &str| -> Vec<String> {
+            let from = bridge.endpoints.iter().find(|endpoint| endpoint.name == from);
+            bridge.route(from).into_iter().map(|endpoint| endpoint.name.clone()).collect()
+        };
+        assert_eq!(names("announce"), vec!["QQ"]);
+        assert_eq!(names("QQ"), vec!["tg"]);
+        assert_eq!(names("tg"), vec!["qqB", "QQ"]);
+        assert_eq!(names("cmd").len(), 4);
+
+        let target = bridge.to_target(&bridge.endpoints[1]);
+        assert_eq!(target.qqGroup, 4);
+        assert_eq!(target.endpoint(BridgeClientPlatform::QQ).unwrap().name, "qqB");
+        assert!(target.endpoint(BridgeClientPlatform::Telegram).is_none());
+    }
+
+    #[test]
+    fn find_by_tg_topic() {
+        let config: Config = serde_json::from_str(
+            r#"{
+                "qqConfig": {"version": "ipad", "auth": "qr"},
+                "discordConfig": {"botId": 1, "botToken": "t"},
+                "telegramConfig": {"apiId": 1, "apiHash": "h", "botToken": "t"},
+                "bridges": [
+                    {"name": "topic", "enable": true, "endpoints": [{"name": "tg", "tgGroup": 100, "tgTopic": 5}]},
+                    {"name": "group", "enable": true, "endpoints": [{"name": "tg", "tgGroup": 100}]},
+                    {"name": "off", "enable": false, "endpoints": [{"name": "tg", "tgGroup": 300}]}
+                ]
+            }"#,
+        )
+        .unwrap();
+        let bridge = config.find_by_tg_topic(100, Some(5)).unwrap();
+        assert_eq!((bridge.name.as_str(), bridge.tgTopic), ("topic", Some(5)));
+        assert!(bridge.endpoint(BridgeClientPlatform::Telegram).is_some());
+        assert_eq!(config.find_by_tg_topic(100, Some(6)).unwrap().name, "group");
+        assert_eq!(config.find_by_tg_topic(100, None).unwrap().tgTopic, None);
+        assert!(config.find_by_tg_topic(200, Some(5)).is_none());
+        assert_eq!(config.tg_groups(), vec![100]);
+    }
+
+    #[test]
+    fn validate() {
+        let bridge = |json: &str| -> BridgeConfig {
+            let mut bridge: BridgeConfig = serde_json::from_str(json).unwrap();
+            bridge.normalize();
+            bridge
+        };
+        let valid = bridge(
+            r#"{"enable": true, "qqGroup": 1, "endpoints": [{"name": "tg", "tgGroup": -2}], "links": [{"from": "QQ", "to": "tg"}]}"#,
+        );
+        assert!(valid.validate().is_ok());
+        let duplicate = bridge(r#"{"enable": true, "endpoints": [{"name": "a", "qqGroup": 1}, {"name": "a", "qqGroup": 2}]}"#);
+        assert!(duplicate.validate().is_err());
+        let unknown = bridge(r#"{"enable": true, "qqGroup": 1, "links": [{"from": "QQ", "to": "DC"}]}"#);
+        assert!(unknown.validate().is_err());
+        // 无效的正则在解析时报错
+        let regex = r#"{"enable": true, "qqGroup": 1, "filters": [{"text": "(", "action": {"type": "drop"}}]}"#;
+        assert!(serde_json::from_str::<BridgeConfig>(regex).is_err());
+    }
 }
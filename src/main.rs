@@ -1,17 +1,21 @@
 #![feature(fs_try_exists)]
 
+use bridge::{BridgeAdapter, BridgeClientPlatform};
+use clap::Parser;
 use config::*;
 use std::sync::Arc;
 use tokio::sync::Mutex;
-use tracing::{info, Level};
+use tracing::{error, info, Level};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
+mod archive;
 mod bridge;
 mod bridge_cmd;
 mod bridge_dc;
 mod bridge_log;
 mod bridge_qq;
 mod bridge_tg;
+mod cli;
 mod config;
 mod logger;
 mod utils;
@@ -20,23 +24,42 @@ pub type HttpResult<T> = std::result::Result<T, Box<dyn std::error::Error>>;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let cli = cli::Cli::parse();
+    // 子命令读取数据文件时的警告和错误也需要输出
     let _log_guard = logger::init_logger();
-    let config = Arc::new(Config::new());
-    info!("config: {:#?}", config);
+    if let Some(command) = cli.command {
+        return Ok(cli::run(command).await?);
+    }
+    let config = Arc::new(SharedConfig::new(Config::new()));
+    info!("config: {:#?}", config.current());
     info!("config loaded");
+    // 先打开存储, 数据库无法打开或升级时不启动桥
+    if let Err(err) = bridge::storage::init(config.current().storage) {
+        error!("打开存储失败: {err:?}");
+        return Err(err.into());
+    }
+    tokio::spawn(config::watch(config.clone()));
+    tokio::spawn(bridge::manager::prune_messages(config.clone()));
     let bridge_service = bridge::BridgeService::new();
     let bridge_service = Arc::new(Mutex::new(bridge_service));
-    let bridge_dc_client = bridge::BridgeService::create_client("bridge_dc_client", bridge_service.clone()).await;
-    let bridge_qq_client = bridge::BridgeService::create_client("bridge_qq_client", bridge_service.clone()).await;
-    let _bridge_tg_client = bridge::BridgeService::create_client("bridge_tg_client", bridge_service.clone()).await;
-    let bridge_cmd_client = bridge::BridgeService::create_client("bridge_cmd_client", bridge_service.clone()).await;
+    let bridge_dc_client =
+        bridge::BridgeService::create_client("bridge_dc_client", BridgeClientPlatform::Discord, bridge_service.clone()).await;
+    let bridge_qq_client = bridge::BridgeService::create_client("bridge_qq_client", BridgeClientPlatform::QQ, bridge_service.clone()).await;
+    let bridge_cmd_client =
+        bridge::BridgeService::create_client("bridge_cmd_client", BridgeClientPlatform::Cmd, bridge_service.clone()).await;
 
-    tokio::select! {
-        _ = bridge_dc::start(config.clone(), bridge_dc_client) => {},
-        _ = bridge_qq::start(config.clone(), bridge_qq_client) => {},
-        // _ = bridge_tg::start(config.clone(), bridge_tg_client) => {},
-        _ = bridge_cmd::start(config.clone(), bridge_cmd_client) => {},
+    let mut adapters: Vec<Arc<dyn BridgeAdapter>> = vec![
+        Arc::new(bridge_dc::DiscordAdapter::new(config.clone(), bridge_dc_client)),
+        Arc::new(bridge_qq::QQAdapter::new(config.clone(), bridge_qq_client)),
+        Arc::new(bridge_cmd::CmdAdapter::new(bridge_cmd_client)),
+    ];
+    // 没有启动的桥不创建客户端, 以免消息堆积在投递队列中
+    if config.current().telegram_config.enable {
+        let bridge_tg_client =
+            bridge::BridgeService::create_client("bridge_tg_client", BridgeClientPlatform::Telegram, bridge_service.clone()).await;
+        adapters.push(Arc::new(bridge_tg::TelegramAdapter::new(config.clone(), bridge_tg_client)));
     }
+    bridge::adapter::run_adapters(adapters).await;
 
     Ok(())
 }
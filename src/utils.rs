@@ -1,3 +1,5 @@
+use lazy_static::lazy_static;
+use regex::Regex;
 use serde::Deserialize;
 use serde::Serialize;
 use std::io::Cursor;
@@ -73,63 +75,140 @@ pub enum MarkdownAst {
     },
 }
 
+lazy_static! {
+    /// discord 消息解析规则, 按书写顺序优先匹配
+    /// - `dc` `qq` `khl` 桥的提及规则: @[DC] name#0000, @[QQ] name(10000), @[KHL] name#0000
+    /// - `user` `everyone` `here` `emoji` discord 的提及和表情; 没有用户id的 `<@>` 无法查询用户, 保留为文本(原来的解析服务会解析为提及)
+    static ref REG_MARKDOWN_RULE: Regex = Regex::new(concat!(
+        r"^(?:(?P<dc>@\[DC\] [^\n]+?#\d\d\d\d)",
+        r"|@\[KHL\] (?P<khl>[^\n#]+)#\d\d\d\d",
+        r"|(?P<qq>@\[QQ\] [^\n]+?\([0-9]+\))",
+        r"|<@!?(?P<user>[0-9]+)>",
+        r"|(?P<everyone>@everyone)",
+        r"|(?P<here>@here)",
+        r"|<(?P<animated>a?):(?P<name>\w+):(?P<emoji>\d+)>)",
+    ))
+    .unwrap();
+}
+
+/// 尝试从文本开头解析一个规则节点
+/// ## Return
+/// 匹配的节点和匹配的文本长度
+fn parse_markdown_rule(source: &str) -> Option<(MarkdownAst, usize)> {
+    let caps = REG_MARKDOWN_RULE.captures(source)?;
+    let len = caps.get(0)?.end();
+    let ast = if let Some(m) = caps.name("dc").or(caps.name("khl")).or(caps.name("qq")) {
+        MarkdownAst::At {
+            username: m.as_str().to_string(),
+        }
+    } else if let Some(m) = caps.name("user") {
+        MarkdownAst::DiscordAtUser {
+            id: m.as_str().to_string(),
+        }
+    } else if caps.name("everyone").is_some() {
+        MarkdownAst::DiscordAtEveryone {}
+    } else if caps.name("here").is_some() {
+        MarkdownAst::DiscordAtHere {}
+    } else {
+        MarkdownAst::DiscordEmoji {
+            id: caps.name("emoji")?.as_str().to_string(),
+            name: caps.name("name")?.as_str().to_string(),
+            animated: caps.name("animated").map_or(false, |m| m.as_str() == "a"),
+        }
+    };
+    Some((ast, len))
+}
+
 /**
  * 将dc和qq消息进行解析
  */
-pub async fn parser_message(content: &str) -> Vec<MarkdownAst> {
-    let client = reqwest::Client::new();
-    let mut result: Vec<MarkdownAst> = client
-        .post("http://localhost:3000/parse-discord-markdown")
-        .body(content.to_string())
-        .timeout(std::time::Duration::from_secs(2))
-        .send()
-        .await
-        .expect("请求解析discord消息服务失败")
-        .json()
-        .await
-        .expect("解析discord消息回传解析json失败");
-
-    if let Some(ast) = result.last() {
-        if let MarkdownAst::Plain { text } = ast {
-            if text.eq("\n") {
-                result.remove(result.len() - 1);
+pub fn parser_message(content: &str) -> Vec<MarkdownAst> {
+    let mut result: Vec<MarkdownAst> = vec![];
+    let mut plain = String::new();
+    let mut source = content;
+    while let Some(ch) = source.chars().next() {
+        // 规则都以 '@' 或 '<' 开头, 其余字符直接归入文本
+        if ch == '@' || ch == '<' {
+            if let Some((ast, len)) = parse_markdown_rule(source) {
+                if !plain.is_empty() {
+                    result.push(MarkdownAst::Plain {
+                        text: std::mem::take(&mut plain),
+                    });
+                }
+                result.push(ast);
+                source = &source[len..];
+                continue;
             }
         }
+        plain.push(ch);
+        source = &source[ch.len_utf8()..];
     }
-    if let Some(ast) = result.last() {
-        if let MarkdownAst::Plain { text } = ast {
-            if text.eq("\n") {
-                result.remove(result.len() - 1);
-            }
+    // 与原来的解析服务一致, 去掉消息末尾的换行(最多两个)
+    for _ in 0..2 {
+        if plain.ends_with('\n') {
+            plain.pop();
         }
     }
-
+    if !plain.is_empty() {
+        result.push(MarkdownAst::Plain { text: plain });
+    }
     result
 }
 
 #[test]
-fn test_send_post_parse_discord_message() {
-    let message = r#"@[DC] 6uopdong#4700
-    !绑定 qq 1261972160"#;
-    tokio::runtime::Builder::new_current_thread()
-        .enable_all()
-        .build()
-        .unwrap()
-        .block_on(async {
-            println!("发送");
-            let client = reqwest::Client::new();
-            let resp: Vec<MarkdownAst> = client
-                .post("http://localhost:3000/parse-discord-markdown")
-                .body(message)
-                .send()
-                .await
-                .unwrap()
-                .json()
-                .await
-                .unwrap();
-
-            println!("{:?}", resp);
-        })
+fn test_parser_message() {
+    let message = "@[DC] 6uopdong#4700\n!绑定 qq 1261972160";
+    let result = parser_message(message);
+    assert!(matches!(&result[0], MarkdownAst::At { username } if username == "@[DC] 6uopdong#4700"));
+    assert!(matches!(&result[1], MarkdownAst::Plain { text } if text == "\n!绑定 qq 1261972160"));
+    assert_eq!(result.len(), 2);
+}
+
+#[test]
+fn test_parser_message_mention() {
+    let result = parser_message("hi @[QQ] 兔子(243249439) @[KHL] rabbit#1234!");
+    assert!(matches!(&result[1], MarkdownAst::At { username } if username == "@[QQ] 兔子(243249439)"));
+    assert!(matches!(&result[3], MarkdownAst::At { username } if username == "rabbit"));
+    assert!(matches!(&result[4], MarkdownAst::Plain { text } if text == "!"));
+
+    let result = parser_message("<@!724829522230378536> <@1> @everyone @here");
+    assert!(matches!(&result[0], MarkdownAst::DiscordAtUser { id } if id == "724829522230378536"));
+    assert!(matches!(&result[2], MarkdownAst::DiscordAtUser { id } if id == "1"));
+    assert!(matches!(result[4], MarkdownAst::DiscordAtEveryone {}));
+    assert!(matches!(result[6], MarkdownAst::DiscordAtHere {}));
+    assert_eq!(result.len(), 7);
+
+    // 超出u64的id仍按提及解析, 由适配器保留原文
+    let result = parser_message("<@99999999999999999999>");
+    assert!(matches!(&result[0], MarkdownAst::DiscordAtUser { id } if id == "99999999999999999999" && id.parse::<u64>().is_err()));
+    assert_eq!(result.len(), 1);
+}
+
+#[test]
+fn test_parser_message_emoji() {
+    let result = parser_message("<:rabbit:1084186702567981077><a:dance:42> <@> a<b");
+    assert!(
+        matches!(&result[0], MarkdownAst::DiscordEmoji { id, name, animated: false } if id == "1084186702567981077" && name == "rabbit")
+    );
+    assert!(matches!(&result[1], MarkdownAst::DiscordEmoji { id, name, animated: true } if id == "42" && name == "dance"));
+    // 没有用户id的提及保留为文本
+    assert!(matches!(&result[2], MarkdownAst::Plain { text } if text == " <@> a<b"));
+    assert_eq!(result.len(), 3);
+}
+
+#[test]
+fn test_parser_message_trailing_newline() {
+    let result = parser_message("hi\n\n");
+    assert!(matches!(&result[0], MarkdownAst::Plain { text } if text == "hi"));
+    assert_eq!(result.len(), 1);
+
+    let result = parser_message("<@1>\n");
+    assert!(matches!(&result[0], MarkdownAst::DiscordAtUser { id } if id == "1"));
+    assert_eq!(result.len(), 1);
+
+    // 只去掉末尾的两个换行, 中间的换行保留
+    let result = parser_message("a\nb\n\n\n");
+    assert!(matches!(&result[0], MarkdownAst::Plain { text } if text == "a\nb\n"));
 }
 
 #[test]